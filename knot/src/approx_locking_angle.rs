@@ -1,6 +1,5 @@
 use std::f64::consts::PI;
 
-use alga::general::SubsetOf;
 use nalgebra::{Isometry3, Quaternion, UnitQuaternion, Vector3};
 
 fn align_and_flip(trans: &Isometry3<f64>) -> Isometry3<f64> {
@@ -15,15 +14,76 @@ fn modf(x: f64, m: f64) -> f64 {
     }
 }
 
-fn y_rotation_angle(rot: UnitQuaternion<f64>) -> f64 {
-    let scaled_axis = rot.scaled_axis();
-    if scaled_axis.x.abs() > 1e-6 || scaled_axis.z.abs() > 1e-6 {
-        eprintln!(
-            "WARNING (y_rotation_angle): The given rotation is not about the y axis: {:#?}",
-            scaled_axis
-        );
+/// A rotation decomposed into a twist about the y axis, followed by a swing about an axis
+/// perpendicular to the y axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwingTwist {
+    /// The angle of the twist about the y axis, in radians in the range `[0, 2 pi)`.
+    pub twist: f64,
+
+    /// The angle of the swing, in radians in the range `[0, pi]`.  This is also the angle between
+    /// the y axis and its image under the rotation.
+    pub swing: f64,
+}
+
+/// Decompose a rotation into a twist about the y axis followed by a swing.  The twist is
+/// well-defined for every rotation except those which swing the y axis to face in exactly the
+/// opposite direction, in which case it is taken to be zero.
+pub fn swing_twist(rot: &UnitQuaternion<f64>) -> SwingTwist {
+    // Writing the rotation as `swing * twist`, the `w` and `j` components of the quaternion are
+    // exactly those of the twist, scaled by the `w` component of the swing.
+    let quat = rot.quaternion();
+    let twist_w = quat.w;
+    let twist_j = quat.j;
+    let twist_norm = twist_w.hypot(twist_j);
+    let swing_norm = quat.i.hypot(quat.k);
+
+    SwingTwist {
+        twist: if twist_norm > 0.0 {
+            modf(2.0 * twist_j.atan2(twist_w), 2.0 * PI)
+        } else {
+            0.0
+        },
+        swing: 2.0 * swing_norm.atan2(twist_norm),
     }
-    modf(scaled_axis.y, 2.0 * PI)
+}
+
+/// Decompose the rotation between two coordinate systems whose y axes are supposed to face the
+/// **same** direction.
+pub fn swing_twist_aligned(trans_0: &Isometry3<f64>, trans_1: &Isometry3<f64>) -> SwingTwist {
+    swing_twist(&(trans_0.rotation.inverse() * trans_1.rotation))
+}
+
+/// Decompose the rotation between two coordinate systems whose y axes are supposed to face in
+/// **opposite** directions.
+pub fn swing_twist_opposing(trans_0: &Isometry3<f64>, trans_1: &Isometry3<f64>) -> SwingTwist {
+    swing_twist_aligned(trans_0, &align_and_flip(trans_1))
+}
+
+/// The rate of change of the twist computed by `swing_twist_aligned` as `trans_1` is rotated about
+/// its origin, as a rotation vector in world coordinates.  Rotating `trans_0` instead has exactly
+/// the opposite effect.  Zero where the twist is not well-defined.
+pub fn twist_gradient_aligned(trans_0: &Isometry3<f64>, trans_1: &Isometry3<f64>) -> Vector3<f64> {
+    // Rotating `trans_1` by a small world rotation vector `v` left-multiplies the relative rotation
+    // by the pure quaternion `v / 2`, expressed in the coordinate system of `trans_0`.
+    // Differentiating `2 atan2(j, w)` under that perturbation gives the expression below.
+    let rel = trans_0.rotation.inverse() * trans_1.rotation;
+    let quat = rel.quaternion();
+    let twist_norm_squ = quat.w * quat.w + quat.j * quat.j;
+    if twist_norm_squ == 0.0 {
+        return Vector3::zeros();
+    }
+    let local = Vector3::new(
+        (quat.i * quat.j - quat.w * quat.k) / twist_norm_squ,
+        1.0,
+        (quat.w * quat.i + quat.j * quat.k) / twist_norm_squ,
+    );
+    trans_0.rotation * local
+}
+
+/// Like `twist_gradient_aligned`, but for the twist computed by `swing_twist_opposing`.
+pub fn twist_gradient_opposing(trans_0: &Isometry3<f64>, trans_1: &Isometry3<f64>) -> Vector3<f64> {
+    twist_gradient_aligned(trans_0, &align_and_flip(trans_1))
 }
 
 pub fn locking_angle_aligned(
@@ -31,23 +91,7 @@ pub fn locking_angle_aligned(
     trans_0: &Isometry3<f64>,
     trans_1: &Isometry3<f64>,
 ) -> f64 {
-    let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-    let axis_1 = trans_1 * Vector3::y_axis().to_superset();
-
-    let align =
-        UnitQuaternion::rotation_between(&axis_1, &axis_0).unwrap_or(UnitQuaternion::identity());
-
-    let aligned_rel_rotation = trans_0.rotation.inverse() * align * trans_1.rotation;
-
-    let result = y_rotation_angle(aligned_rel_rotation) / (2.0 * PI) * (num_angles as f64);
-
-    if result.is_nan() {
-        // sometimes `align` has NaN entries when the alignment is very close.
-        // It is unclear whether this is the intended behavior of nalgebra.
-        0.0
-    } else {
-        result
-    }
+    swing_twist_aligned(trans_0, trans_1).twist / (2.0 * PI) * (num_angles as f64)
 }
 
 pub fn locking_angle_opposing(
@@ -62,6 +106,9 @@ pub fn locking_angle_opposing(
 mod test {
     use approx_locking_angle::*;
 
+    use alga::general::SubsetOf;
+    use nalgebra::Translation3;
+
     #[test]
     fn perfectly_aligned_y() {
         let trans_0 = Isometry3::identity();
@@ -98,4 +145,59 @@ mod test {
 
         assert_relative_eq!(locking_angle_aligned(1, &trans_0, &trans_1), 1.0 / 3.0);
     }
+
+    #[test]
+    fn swing_and_twist() {
+        let rot = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), PI / 8.0)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 2.0 * PI / 3.0);
+        let decomposed = swing_twist(&rot);
+        assert_relative_eq!(decomposed.twist, 2.0 * PI / 3.0);
+        assert_relative_eq!(decomposed.swing, PI / 8.0);
+
+        // Negative twists wrap around
+        let rot = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -PI / 3.0)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -PI / 4.0);
+        let decomposed = swing_twist(&rot);
+        assert_relative_eq!(decomposed.twist, 7.0 * PI / 4.0);
+        assert_relative_eq!(decomposed.swing, PI / 3.0);
+    }
+
+    #[test]
+    fn nearly_aligned() {
+        // `UnitQuaternion::rotation_between` can produce NaN for axes this close together
+        let trans_0 = Isometry3::identity();
+        let trans_1 = (UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 1e-12)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), PI / 2.0)).to_superset();
+        assert_relative_eq!(locking_angle_aligned(4, &trans_0, &trans_1), 1.0, epsilon = 1e-9);
+        assert_relative_eq!(swing_twist_aligned(&trans_0, &trans_1).swing, 1e-12);
+    }
+
+    #[test]
+    fn fully_swung() {
+        let rot = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI);
+        let decomposed = swing_twist(&rot);
+        assert!(!decomposed.twist.is_nan());
+        assert_relative_eq!(decomposed.swing, PI);
+    }
+
+    #[test]
+    fn twist_gradient() {
+        let trans_0 = Isometry3::from_parts(
+            Translation3::new(1.0, 2.0, 3.0),
+            UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.4),
+        );
+        let trans_1 = (UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 0.2)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 1.1)).to_superset();
+
+        let gradient = twist_gradient_aligned(&trans_0, &trans_1);
+        let step = 1e-7;
+        for axis in &[Vector3::x_axis(), Vector3::y_axis(), Vector3::z_axis()] {
+            let mut stepped = trans_1;
+            stepped.rotation = UnitQuaternion::from_axis_angle(axis, step) * stepped.rotation;
+            let rate = (swing_twist_aligned(&trans_0, &stepped).twist
+                - swing_twist_aligned(&trans_0, &trans_1).twist)
+                / step;
+            assert_relative_eq!(gradient.dot(axis), rate, epsilon = 1e-5);
+        }
+    }
 }
@@ -0,0 +1,483 @@
+//! Step-by-step instructions for assembling a knot from physical joints.
+//!
+//! A knot with dihedral-n symmetry is built from n identical horseshoes.  Each horseshoe is a pair
+//! of symmetric copies of the chain, joined at their first joints, so it runs from the last joint
+//! of one copy, backwards along that copy and forwards along the other, to the last joint of the
+//! other copy.  The horseshoes are then joined at their ends into a closed loop.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::fmt::Write;
+
+use nalgebra::Isometry3;
+
+use approx_locking_angle::swing_twist_opposing;
+use report::{JointsParity, KnotGeometry};
+
+/// A joint of the assembled knot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Part {
+    /// The horseshoe containing the joint, counted from 1.
+    pub horseshoe: usize,
+
+    /// The position of the joint along its horseshoe, counted from 1.
+    pub position: usize,
+}
+
+impl fmt::Display for Part {
+    /// The label `Hh-p` of the joint at position `p` along horseshoe `h`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "H{}-{}", self.horseshoe, self.position)
+    }
+}
+
+/// Which kind of junction a step makes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Junction {
+    /// Two consecutive joints of the same copy of the chain.
+    Chain,
+
+    /// The first joints of the two copies in a horseshoe, joined at their incoming legs.
+    Mirror,
+
+    /// The last joints of two adjacent horseshoes, joined at their outgoing legs.
+    Adjacent,
+}
+
+/// One connection between two joints.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    /// The joint already in place.
+    pub from: Part,
+
+    /// The joint being attached.
+    pub to: Part,
+
+    pub junction: Junction,
+
+    /// How many clicks to turn `to` relative to `from`, right-handed about the direction from
+    /// `from` to `to`, in `0..num_angles`.  At `Chain` junctions, zero clicks leaves the two joints
+    /// facing the same way.  At `Mirror` and `Adjacent` junctions, where two legs of the same kind
+    /// meet head on, zero clicks leaves each joint a half turn from the other about the axis
+    /// perpendicular to its plane, as measured by `swing_twist_opposing`.
+    pub clicks: i32,
+
+    /// How far the geometry is from turning exactly `clicks` clicks, as a fraction of a click.
+    pub deviation: f64,
+}
+
+/// Where a symmetric copy of the chain lies within its horseshoe.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CopyPlacement {
+    /// The index of the symmetry producing the copy, in the order produced by
+    /// `symmetry::symmetries_with_skip`.
+    pub symmetry_index: usize,
+
+    /// The first joint of the copy.  For odd parity, it is shared by both copies in a horseshoe.
+    pub first: Part,
+
+    /// The last joint of the copy.
+    pub last: Part,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Instructions {
+    pub num_angles: u16,
+    pub parity: JointsParity,
+    pub horseshoes: usize,
+    pub joints_per_horseshoe: usize,
+    pub copies: Vec<CopyPlacement>,
+
+    /// Every connection, in the order to make them: each horseshoe from end to end, then the
+    /// junctions between horseshoes.
+    pub steps: Vec<Step>,
+}
+
+impl Instructions {
+    /// Instructions for assembling a geometry at its nearest discrete angles.  See
+    /// `KnotGeometry::discrete_angles`.
+    pub fn new(geometry: &KnotGeometry) -> Instructions {
+        let recovered = geometry.discrete_angles();
+        let num_angles = geometry.num_angles as i32;
+        let spec = geometry.joint_spec;
+        let chain = geometry.joints();
+        let symmetries = geometry.symmetry_isometries();
+
+        // The junctions between copies of the chain are the same in every horseshoe, so they are
+        // measured in the first.
+        let first_in = chain[0] * spec.origin_to_in();
+        let last_out = chain[chain.len() - 1] * spec.origin_to_out();
+        let mirror = opposing_clicks(geometry.num_angles, &(symmetries[1] * first_in), &first_in);
+        let adjacent = opposing_clicks(geometry.num_angles, &last_out, &(symmetries[3] * last_out));
+        let horseshoes = geometry.symmetry_count() as usize;
+        let joints = geometry.transforms.len();
+        let joints_per_horseshoe = match geometry.parity {
+            JointsParity::Even => 2 * joints,
+            JointsParity::Odd => 2 * joints - 1,
+        };
+
+        let mut instructions = Instructions {
+            num_angles: geometry.num_angles,
+            parity: geometry.parity,
+            horseshoes,
+            joints_per_horseshoe,
+            copies: Vec::with_capacity(2 * horseshoes),
+            steps: Vec::with_capacity(horseshoes * joints_per_horseshoe),
+        };
+
+        for horseshoe in 1..(horseshoes + 1) {
+            let part = |position| Part {
+                horseshoe,
+                position,
+            };
+            let (up_first, down_first) = match geometry.parity {
+                JointsParity::Even => (joints + 1, joints),
+                JointsParity::Odd => (joints, joints),
+            };
+            instructions.copies.push(CopyPlacement {
+                symmetry_index: 2 * (horseshoe - 1),
+                first: part(up_first),
+                last: part(joints_per_horseshoe),
+            });
+            instructions.copies.push(CopyPlacement {
+                symmetry_index: 2 * (horseshoe - 1) + 1,
+                first: part(down_first),
+                last: part(1),
+            });
+
+            for position in 1..joints_per_horseshoe {
+                // The angle between two joints is the same whichever of them is placed first
+                let (_, to_index) = instructions.locate(part(position + 1));
+                let (_, from_index) = instructions.locate(part(position));
+                let angle = match geometry.parity {
+                    JointsParity::Even if from_index == 0 && to_index == 0 => None,
+                    JointsParity::Even => Some(from_index.max(to_index)),
+                    JointsParity::Odd => Some(from_index.max(to_index) - 1),
+                };
+                let (junction, (clicks, deviation)) = match angle {
+                    // The angle of the first joint is split between it and its mirror image, so
+                    // the clicks between the two are measured directly.
+                    None => (Junction::Mirror, mirror),
+                    Some(angle) => (
+                        Junction::Chain,
+                        (
+                            ((recovered.angles[angle] % num_angles) + num_angles) % num_angles,
+                            recovered.rounding_errors[angle],
+                        ),
+                    ),
+                };
+                instructions.steps.push(Step {
+                    from: part(position),
+                    to: part(position + 1),
+                    junction,
+                    clicks,
+                    deviation,
+                });
+            }
+        }
+
+        let (final_clicks, final_deviation) = adjacent;
+        for horseshoe in 1..(horseshoes + 1) {
+            instructions.steps.push(Step {
+                from: Part {
+                    horseshoe,
+                    position: joints_per_horseshoe,
+                },
+                to: Part {
+                    horseshoe: horseshoe % horseshoes + 1,
+                    position: 1,
+                },
+                junction: Junction::Adjacent,
+                clicks: final_clicks,
+                deviation: final_deviation,
+            });
+        }
+
+        instructions
+    }
+
+    /// The total number of joints in the knot.
+    pub fn part_count(&self) -> usize {
+        self.horseshoes * self.joints_per_horseshoe
+    }
+
+    /// The index of the symmetry placing a part, and the index of the part's joint within the
+    /// chain.  For odd parity, the joint shared by both copies in a horseshoe is placed by the
+    /// second of their symmetries, as in `KnotGeometry`.
+    pub fn locate(&self, part: Part) -> (usize, usize) {
+        let up = 2 * (part.horseshoe - 1);
+        match self.parity {
+            JointsParity::Even => {
+                let joints = self.joints_per_horseshoe / 2;
+                if part.position > joints {
+                    (up, part.position - joints - 1)
+                } else {
+                    (up + 1, joints - part.position)
+                }
+            }
+            JointsParity::Odd => {
+                let shared = (self.joints_per_horseshoe + 1) / 2;
+                if part.position > shared {
+                    (up, part.position - shared)
+                } else {
+                    (up + 1, shared - part.position)
+                }
+            }
+        }
+    }
+
+    /// The instructions as a Markdown document.
+    pub fn markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# Assembly instructions\n").unwrap();
+        writeln!(out, "{}\n", self.summary()).unwrap();
+        writeln!(out, "{}\n", self.click_convention()).unwrap();
+
+        writeln!(out, "## Symmetric copies\n").unwrap();
+        writeln!(out, "| Copy | First joint | Last joint |").unwrap();
+        writeln!(out, "|------|-------------|------------|").unwrap();
+        for copy in &self.copies {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                copy.symmetry_index,
+                copy.first,
+                copy.last,
+            ).unwrap();
+        }
+
+        writeln!(out, "\n## Steps\n").unwrap();
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(out, "{}. {}", i + 1, describe(step)).unwrap();
+        }
+        out
+    }
+
+    /// The instructions as an HTML document.
+    pub fn html(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "<!DOCTYPE html>").unwrap();
+        writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+        writeln!(out, "<title>Assembly instructions</title>\n</head>\n<body>").unwrap();
+        writeln!(out, "<h1>Assembly instructions</h1>").unwrap();
+        writeln!(out, "<p>{}</p>", self.summary()).unwrap();
+        writeln!(out, "<p>{}</p>", self.click_convention()).unwrap();
+
+        writeln!(out, "<h2>Symmetric copies</h2>\n<table>").unwrap();
+        writeln!(out, "<tr><th>Copy</th><th>First joint</th><th>Last joint</th></tr>").unwrap();
+        for copy in &self.copies {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                copy.symmetry_index,
+                copy.first,
+                copy.last,
+            ).unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+
+        writeln!(out, "<h2>Steps</h2>\n<ol>").unwrap();
+        for step in &self.steps {
+            writeln!(out, "<li>{}</li>", describe(step)).unwrap();
+        }
+        writeln!(out, "</ol>\n</body>\n</html>").unwrap();
+        out
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} joints in total, as {} identical horseshoes of {} joints each.  Joint Hh-p is the \
+             joint at position p along horseshoe h.",
+            self.part_count(),
+            self.horseshoes,
+            self.joints_per_horseshoe,
+        )
+    }
+
+    fn click_convention(&self) -> String {
+        format!(
+            "Each joint is turned relative to the joint it attaches to by a number of clicks, \
+             each a 1/{} revolution, right-handed about the direction from the attached joint to \
+             the new one.  Zero clicks leaves the two joints facing the same way, except where \
+             the two halves of a horseshoe or two horseshoes meet head on, where it leaves each \
+             joint a half turn from the other about the axis perpendicular to its plane.",
+            self.num_angles,
+        )
+    }
+}
+
+/// The clicks at a junction where two legs of the same kind meet head on, in `0..num_angles`, and
+/// how far the legs are from turning exactly that many clicks.
+fn opposing_clicks(num_angles: u16, leg_0: &Isometry3<f64>, leg_1: &Isometry3<f64>) -> (i32, f64) {
+    let twist = swing_twist_opposing(leg_0, leg_1).twist / (2.0 * PI) * (num_angles as f64);
+    let clicks = twist.round();
+    ((clicks as i32) % (num_angles as i32), twist - clicks)
+}
+
+fn describe(step: &Step) -> String {
+    let what = match step.junction {
+        Junction::Chain => "Attach",
+        Junction::Mirror => "Join the two halves of the horseshoe: attach",
+        Junction::Adjacent => "Join the horseshoes: attach",
+    };
+    let clicks = if step.clicks == 1 { "click" } else { "clicks" };
+    let off = if step.deviation.abs() >= 0.05 {
+        format!(" (off by {:+.2} clicks)", step.deviation)
+    } else {
+        String::new()
+    };
+    format!(
+        "{} {} to {}, turned {} {}{}.",
+        what,
+        step.to,
+        step.from,
+        step.clicks,
+        clicks,
+        off,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use assembly::*;
+
+    use nalgebra::Vector3;
+
+    use approx_locking_angle::swing_twist_aligned;
+    use report::fixtures::geometry;
+
+    /// Check that the legs of the two joints of every step meet.
+    fn check_connected(geometry: &KnotGeometry, instructions: &Instructions) {
+        let symmetries = geometry.symmetry_isometries();
+        let joints = geometry.joints();
+        let spec = geometry.joint_spec;
+        let ends = |part| {
+            let (symmetry, index) = instructions.locate(part);
+            let joint = symmetries[symmetry] * joints[index];
+            vec![
+                (joint * spec.origin_to_in()).translation.vector,
+                (joint * spec.origin_to_out()).translation.vector,
+            ]
+        };
+        for step in &instructions.steps {
+            let gap = ends(step.from)
+                .iter()
+                .flat_map(|a| ends(step.to).into_iter().map(move |b| (a - b).norm()))
+                .fold(::std::f64::INFINITY, f64::min);
+            let tolerance = match step.junction {
+                Junction::Adjacent => 0.5,
+                _ => 1e-6,
+            };
+            assert!(gap < tolerance, "{:?} leaves a gap of {}", step, gap);
+        }
+    }
+
+    /// Check that turning the leg of `from` by the clicks of every step rebuilds the leg of `to`
+    /// it meets, up to the deviation of the step.
+    fn check_clicks(geometry: &KnotGeometry, instructions: &Instructions) {
+        let symmetries = geometry.symmetry_isometries();
+        let joints = geometry.joints();
+        let spec = geometry.joint_spec;
+        let num_angles = geometry.num_angles as f64;
+        // The joint shared by both copies of an odd horseshoe has its legs swapped in the mirror
+        // copy, so it is placed in the copy of the joint it meets.
+        let legs = |part, other| {
+            let (mut symmetry, index) = instructions.locate(part);
+            if let (JointsParity::Odd, 0) = (geometry.parity, index) {
+                symmetry = instructions.locate(other).0;
+            }
+            let joint = symmetries[symmetry] * joints[index];
+            vec![
+                (joint * spec.origin_to_in(), false),
+                (joint * spec.origin_to_out(), true),
+            ]
+        };
+        let y = Vector3::y();
+        let flip = Isometry3::rotation(Vector3::z() * PI);
+        for step in &instructions.steps {
+            let (from_leg, to_leg) = legs(step.from, step.to)
+                .into_iter()
+                .flat_map(|a| legs(step.to, step.from).into_iter().map(move |b| (a, b)))
+                .min_by(|&((a, _), (b, _)), &((c, _), (d, _))| {
+                    let gap_0 = (a.translation.vector - b.translation.vector).norm();
+                    let gap_1 = (c.translation.vector - d.translation.vector).norm();
+                    gap_0.partial_cmp(&gap_1).unwrap()
+                }).unwrap();
+            let (from_frame, from_out) = from_leg;
+            let (to_frame, _) = to_leg;
+            let turn = step.clicks as f64 / num_angles * 2.0 * PI;
+            let rebuilt = match step.junction {
+                // Along the chain the legs face the same way, and their y axes point from `from`
+                // to `to` exactly when `from` is the earlier joint.
+                Junction::Chain if from_out => from_frame * Isometry3::rotation(y * turn),
+                Junction::Chain => from_frame * Isometry3::rotation(y * -turn),
+                Junction::Mirror | Junction::Adjacent => {
+                    from_frame * Isometry3::rotation(y * turn) * flip
+                }
+            };
+            let residual = swing_twist_aligned(&to_frame, &rebuilt).twist / (2.0 * PI);
+            let residual = (residual - residual.round()) * num_angles;
+            assert!(
+                (residual - step.deviation).abs() < 1e-6,
+                "{:?} rebuilds its junction {} clicks off",
+                step,
+                residual,
+            );
+            if step.junction != Junction::Adjacent {
+                assert!(
+                    rebuilt.rotation.angle_to(&to_frame.rotation) < 1e-6,
+                    "{:?} does not rebuild its junction",
+                    step,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn even_instructions() {
+        let geometry = geometry(JointsParity::Even, vec![5, 0, 14, 15, 2]);
+        let instructions = Instructions::new(&geometry);
+        assert_eq!(instructions.part_count(), 30);
+        assert_eq!(instructions.steps.len(), 30);
+        assert_eq!(instructions.copies.len(), 6);
+
+        let clicks = instructions.steps[0..9]
+            .iter()
+            .map(|step| step.clicks)
+            .collect::<Vec<_>>();
+        assert_eq!(clicks, vec![2, 15, 14, 0, 3, 0, 14, 15, 2]);
+        assert_eq!(instructions.steps[4].junction, Junction::Mirror);
+        assert_eq!(instructions.steps[29].junction, Junction::Adjacent);
+        assert_eq!(
+            instructions.steps[29].to,
+            Part {
+                horseshoe: 1,
+                position: 1
+            }
+        );
+        check_connected(&geometry, &instructions);
+        check_clicks(&geometry, &instructions);
+    }
+
+    #[test]
+    fn odd_instructions() {
+        let geometry = geometry(JointsParity::Odd, vec![4, 12, 7, 9]);
+        let instructions = Instructions::new(&geometry);
+        assert_eq!(instructions.part_count(), 27);
+        assert_eq!(instructions.steps.len(), 27);
+        assert_eq!(instructions.copies[0].first, instructions.copies[1].first);
+
+        let clicks = instructions.steps[0..8]
+            .iter()
+            .map(|step| step.clicks)
+            .collect::<Vec<_>>();
+        assert_eq!(clicks, vec![9, 7, 12, 4, 4, 12, 7, 9]);
+        check_connected(&geometry, &instructions);
+        check_clicks(&geometry, &instructions);
+
+        let markdown = instructions.markdown();
+        assert!(markdown.contains("27 joints in total"));
+        assert!(markdown.contains("27. Join the horseshoes: attach H1-1 to H3-9"));
+        assert!(instructions.html().contains("<li>Attach H1-2 to H1-1, turned 9 clicks.</li>"));
+    }
+}
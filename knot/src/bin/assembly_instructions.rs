@@ -0,0 +1,81 @@
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::fs::File;
+use std::io::Read;
+use std::process::exit;
+
+use knot::assembly::Instructions;
+use knot::report::{complete_report, complete_reports, knot_geometry, KnotGeometry, KnotReports};
+
+use clap::{App, Arg};
+
+fn main() {
+    let matches = App::new("Knot Assembly Instructions")
+        .about(
+            "Writes step-by-step instructions for assembling a knot from its joints, as Markdown \
+             or HTML",
+        ).arg(
+            Arg::with_name("input")
+                .value_name("FILE.json")
+                .help("Sets the geometry or reports file to write instructions for")
+                .required(true),
+        ).arg(
+            Arg::with_name("index")
+                .long("index")
+                .value_name("INT")
+                .default_value("0")
+                .help("Sets which knot of a reports file to write instructions for"),
+        ).arg(
+            Arg::with_name("html")
+                .long("html")
+                .help("Writes HTML instead of Markdown"),
+        ).get_matches();
+
+    let filename = matches.value_of("input").unwrap();
+    let mut contents = String::new();
+    File::open(&filename)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .unwrap_or_else(|_| {
+            eprintln!("Could not open file {}", filename);
+            exit(1);
+        });
+
+    // The input may be either a geometry or a set of reports
+    let geometry = if let Ok(geometry) = serde_json::from_str::<KnotGeometry>(&contents) {
+        geometry
+    } else if let Ok(reports) = serde_json::from_str::<KnotReports>(&contents) {
+        let index = matches
+            .value_of("index")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap_or_else(|_| {
+                eprintln!("Index must be an integer");
+                exit(1);
+            });
+        if !(index < reports.knots.len()) {
+            eprintln!("Index out of bounds -- only {} reports", reports.knots.len());
+            exit(1);
+        }
+        let reports = complete_reports(reports);
+        let knot = complete_report(&reports, index);
+        knot_geometry(&reports, &knot)
+    } else {
+        eprintln!("Could not parse input file");
+        exit(1);
+    };
+    if geometry.symmetries.len() < 4 || geometry.transforms.len() < 2 {
+        eprintln!("Geometry must have at least two joints and at least dihedral-2 symmetry");
+        exit(1);
+    }
+
+    let instructions = Instructions::new(&geometry);
+    if matches.is_present("html") {
+        print!("{}", instructions.html());
+    } else {
+        print!("{}", instructions.markdown());
+    }
+}
@@ -0,0 +1,212 @@
+extern crate clap;
+extern crate nalgebra;
+extern crate rand;
+
+extern crate knot;
+
+use std::process::exit;
+use std::time::Instant;
+
+use nalgebra::Isometry3;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use knot::defaults;
+use knot::defaults::NUM_ANGLES;
+use knot::filter::{
+    capsule_collisions_with_symmetry, collisions_with_symmetry, points, protection_depth,
+    CollisionOutcome, CollisionStrategy,
+};
+use knot::joint::JointSpec;
+use knot::rand_problem::{rand_chain, rand_placement};
+
+use clap::{App, Arg};
+
+/// Test every chain for collisions, returning the outcomes and the time taken in seconds.  Each
+/// chain is given as its placement and its joints relative to that placement.
+fn run<F: Fn(&Isometry3<f64>, &[Isometry3<f64>]) -> CollisionOutcome>(
+    chains: &[(Isometry3<f64>, Vec<Isometry3<f64>>)],
+    test: F,
+) -> (Vec<CollisionOutcome>, f64) {
+    let start = Instant::now();
+    let outcomes = chains
+        .iter()
+        .map(|(placement, chain)| test(placement, chain))
+        .collect();
+    let elapsed = start.elapsed();
+    (
+        outcomes,
+        elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9,
+    )
+}
+
+fn parse_arg<T: std::str::FromStr>(matches: &clap::ArgMatches, name: &str) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    matches
+        .value_of(name)
+        .unwrap()
+        .parse::<T>()
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid {}: {}", name, err);
+            exit(1);
+        })
+}
+
+fn main() {
+    let default_symmetry_str = defaults::SYMMETRY_COUNT.to_string();
+    let default_skip_str = (defaults::SYMMETRY_COUNT - 1).to_string();
+    let default_bend_angle_str = defaults::joint_spec().bend_angle().to_degrees().to_string();
+    let default_radius_str = defaults::joint_spec().radius().to_string();
+
+    let matches = App::new("Collision Strategy Comparison")
+        .about(
+            "Tests random chains for collisions with every collision strategy, checking that they \
+             agree and timing each",
+        ).arg(
+            Arg::with_name("symmetry")
+                .long("symmetry")
+                .value_name("INT")
+                .default_value(&default_symmetry_str)
+                .help("Sets dihedral-N symmetry"),
+        ).arg(
+            Arg::with_name("skip")
+                .long("skip")
+                .value_name("INT")
+                .default_value(&default_skip_str)
+                .help("Sets how many times the knot winds around the z axis"),
+        ).arg(
+            Arg::with_name("bend-angle")
+                .long("bend-angle")
+                .value_name("DEGREES")
+                .default_value(&default_bend_angle_str)
+                .help("Sets bend angle of all joints"),
+        ).arg(
+            Arg::with_name("radius")
+                .long("radius")
+                .value_name("FLOAT")
+                .default_value(&default_radius_str)
+                .help("Sets cylinder radius of all joints"),
+        ).arg(
+            Arg::with_name("joints")
+                .long("joints")
+                .value_name("INT")
+                .default_value("5")
+                .help("Sets the number of joints in each chain"),
+        ).arg(
+            Arg::with_name("chains")
+                .long("chains")
+                .value_name("INT")
+                .default_value("10000")
+                .help("Sets the number of random chains to test"),
+        ).arg(
+            Arg::with_name("max-offset")
+                .long("max-offset")
+                .value_name("FLOAT")
+                .default_value("10")
+                .help("Sets how far from the z axis chains may start"),
+        ).arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("INT")
+                .default_value("0")
+                .help("Sets the random seed used to generate chains"),
+        ).get_matches();
+
+    let symmetry = parse_arg::<u32>(&matches, "symmetry");
+    let skip = parse_arg::<u32>(&matches, "skip");
+    let bend_angle = parse_arg::<f64>(&matches, "bend-angle").to_radians();
+    let radius = parse_arg::<f64>(&matches, "radius");
+    let joint_count = parse_arg::<usize>(&matches, "joints");
+    let chain_count = parse_arg::<usize>(&matches, "chains");
+    let max_offset = parse_arg::<f64>(&matches, "max-offset");
+    let seed = parse_arg::<u64>(&matches, "seed");
+
+    let spec = JointSpec::new(1.0, 1.0, bend_angle, radius);
+    let depth = protection_depth(&spec).unwrap_or_else(|| {
+        eprintln!("Joints which fold back on themselves always collide");
+        exit(1);
+    });
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let chains = (0..chain_count)
+        .map(|_| {
+            let placement = rand_placement(&mut rng, max_offset);
+            (placement, rand_chain(&mut rng, spec, NUM_ANGLES, joint_count))
+        })
+        .collect::<Vec<_>>();
+
+    let mut disagreements = 0;
+
+    let mut reference_spheres: Option<Vec<CollisionOutcome>> = None;
+    for strategy in CollisionStrategy::all() {
+        let (outcomes, secs) = run(&chains, |placement, chain| {
+            collisions_with_symmetry(
+                symmetry,
+                skip,
+                depth,
+                points(spec, chain.iter().cloned()).map(|point| placement * point),
+                spec.radius(),
+                strategy,
+            )
+        });
+        let collision_count = outcomes
+            .iter()
+            .filter(|&&outcome| outcome == CollisionOutcome::Collision)
+            .count();
+        println!(
+            "Spheres, {:?} backend, wedge {}: {:.6}s ({} of {} colliding)",
+            strategy.backend,
+            if strategy.use_wedge { "on" } else { "off" },
+            secs,
+            collision_count,
+            chains.len(),
+        );
+        match reference_spheres {
+            Some(ref reference) => {
+                disagreements += reference
+                    .iter()
+                    .zip(&outcomes)
+                    .filter(|(a, b)| a != b)
+                    .count();
+            }
+            None => reference_spheres = Some(outcomes),
+        }
+    }
+
+    let mut reference_capsules: Option<Vec<CollisionOutcome>> = None;
+    for strategy in CollisionStrategy::all() {
+        // The wedge optimization does not apply to capsules
+        if strategy.use_wedge {
+            continue;
+        }
+        let (outcomes, secs) = run(&chains, |placement, chain| {
+            capsule_collisions_with_symmetry(
+                symmetry,
+                skip,
+                depth,
+                spec,
+                chain.iter().map(|&joint| placement * joint),
+                strategy.backend,
+            ).outcome
+        });
+        println!("Capsules, {:?} backend: {:.6}s", strategy.backend, secs);
+        match reference_capsules {
+            Some(ref reference) => {
+                disagreements += reference
+                    .iter()
+                    .zip(&outcomes)
+                    .filter(|(a, b)| a != b)
+                    .count();
+            }
+            None => reference_capsules = Some(outcomes),
+        }
+    }
+
+    if disagreements > 0 {
+        eprintln!("Strategies disagreed on {} outcomes", disagreements);
+        exit(1);
+    }
+    println!("All strategies agree");
+}
@@ -18,7 +18,7 @@ use kiss3d::window::Window;
 use alga::general::SubsetOf;
 use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
 
-use knot::optimize_tools::{Chain, Leg, PhantomJoint, RepulsionChain};
+use knot::optimize_tools::{Chain, JointConstraint, Leg, PhantomJoint, RepulsionChain};
 use knot::defaults;
 use knot::defaults::continuous_optimization::{
     COST_PARAMS, MAX_REPULSION_STRENGTH, RATE, REPULSION,
@@ -27,10 +27,11 @@ use knot::defaults::continuous_optimization::{
 };
 use knot::geometries::trefoil_curve;
 use knot::geometries::from_spline;
+use knot::geometries::presets;
 use knot::geometries::chinbut_spline;
 use knot::isometry_adjust;
 use knot::joint::{at_angles, RelativeJoint};
-use knot::report::{KnotGeometry, Transform};
+use knot::report::{JointsParity, KnotGeometry, Transform};
 use knot::symmetry::{symmetries, symmetries_with_skip};
 use knot::visualize::joint_render::{add_joints, Style};
 
@@ -38,8 +39,25 @@ const TAU: f64 = 2.0 * PI;
 
 const DEBUG_ANGLES: bool = false;
 
+/// Where the `S` key saves the current geometry, along with the constraint on each joint
+const SAVE_FILE: &str = "continuous_optimize.json";
+
 fn main() {
-    let mut chain = match args().nth(1) {
+    let (mut chain, parity) = match args().nth(1) {
+        Some(ref flag) if flag == "--preset" => {
+            let name = args().nth(2).unwrap_or_else(|| {
+                eprintln!("Usage: --preset NAME");
+                exit(1);
+            });
+            let preset = presets::find(&name).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            (
+                preset.repulsion_chain(CONTINUOUS_PARAMS, RETURN_TO_INITIAL_WEIGHT, RATE),
+                JointsParity::Even,
+            )
+        }
         Some(filename) => {
             let file = File::open(&filename).unwrap_or_else(|_| {
                 eprintln!("Could not open file {}", filename);
@@ -49,7 +67,7 @@ fn main() {
                 eprintln!("Could not parse input file");
                 exit(1);
             });
-            RepulsionChain::new(
+            let mut chain = RepulsionChain::new(
                 Chain::new(
                     geometry.joint_spec,
                     geometry.num_angles,
@@ -82,29 +100,35 @@ fn main() {
                 REPULSION_EXPONENT,
                 REPULSION_STRENGTH,
                 MAX_REPULSION_STRENGTH,
-            )
+            );
+            chain.constraints = geometry.constraints;
+            (chain, geometry.parity)
         }
-        None => RepulsionChain::new(
-            from_spline::generic_chain(
-                4.0,
-                CONTINUOUS_PARAMS,
-                RETURN_TO_INITIAL_WEIGHT,
-                RATE,
-                defaults::joint_spec(),
-                chinbut_spline::generate_chinbutspline,
-                6,
+        None => (
+            RepulsionChain::new(
+                from_spline::generic_chain(
+                    4.0,
+                    CONTINUOUS_PARAMS,
+                    RETURN_TO_INITIAL_WEIGHT,
+                    RATE,
+                    defaults::joint_spec(),
+                    chinbut_spline::generate_chinbutspline,
+                    6,
+                    2,
+                ),
+                // trefoil_curve::chain(
+                //     3.5, // scale
+                //     COST_PARAMS,
+                //     RETURN_TO_INITIAL_WEIGHT,
+                //     RATE,
+                //     defaults::joint_spec(),
+                // ),
+                symmetries(6).map(|quat| quat.to_superset()).collect(),
+                REPULSION_EXPONENT,
+                REPULSION_STRENGTH,
+                MAX_REPULSION_STRENGTH,
             ),
-            // trefoil_curve::chain(
-            //     3.5, // scale
-            //     COST_PARAMS,
-            //     RETURN_TO_INITIAL_WEIGHT,
-            //     RATE,
-            //     defaults::joint_spec(),
-            // ),
-            symmetries(6).map(|quat| quat.to_superset()).collect(),
-            REPULSION_EXPONENT,
-            REPULSION_STRENGTH,
-            MAX_REPULSION_STRENGTH,
+            JointsParity::Even,
         ),
     };
 
@@ -192,6 +216,44 @@ fn main() {
                         chain.joints[selected] = chain.joints[selected]
                             * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -TAU / 16.0);
                     }
+                    Key::P => {
+                        if chain.constraints.len() < chain.joints.len() {
+                            let len = chain.joints.len();
+                            chain.constraints.resize(len, JointConstraint::Free);
+                        }
+                        chain.constraints[selected] = chain.constraints[selected].next();
+                        println!(
+                            "Joint {} constraint: {:?}",
+                            selected, chain.constraints[selected]
+                        );
+                    }
+                    Key::S => {
+                        let geometry = KnotGeometry {
+                            joint_spec: chain.spec,
+                            num_angles: chain.num_angles,
+                            cost_params: chain.cost_params,
+                            parity,
+                            symmetries: chain
+                                .symmetries
+                                .iter()
+                                .map(|&iso| Transform::from_isometry(iso))
+                                .collect(),
+                            transforms: chain
+                                .joints
+                                .iter()
+                                .map(|&iso| Transform::from_isometry(iso))
+                                .collect(),
+                            constraints: chain.constraints.clone(),
+                        };
+                        match File::create(SAVE_FILE) {
+                            Ok(file) => {
+                                serde_json::to_writer_pretty(file, &geometry)
+                                    .expect("Could not write geometry");
+                                println!("Saved geometry to {}", SAVE_FILE);
+                            }
+                            Err(err) => eprintln!("Could not create {}: {}", SAVE_FILE, err),
+                        }
+                    }
                     Key::Q => {
                         let cost = chain.optimize();
                         println!("{{{}, {}}},", step, cost);
@@ -257,9 +319,18 @@ fn main() {
             let mut i = 0;
             let mut first = true;
             for sym in symmetries_with_skip(3, 2) {
-                for &joint in &chain.joints {
+                for (j, &joint) in chain.joints.iter().enumerate() {
                     if !first {
                         nodes[i].set_color(0.5, 0.5, 0.5);
+                    } else {
+                        // Free joints keep the alternating colors assigned by `add_joints`
+                        let (r, g, b) = match chain.constraint(j) {
+                            JointConstraint::Free if j % 2 == 0 => (1.0, 1.0, 1.0),
+                            JointConstraint::Free => (1.0, 0.0, 0.0),
+                            JointConstraint::FixedTwist => (0.2, 0.4, 1.0),
+                            JointConstraint::FixedPose => (0.2, 0.8, 0.2),
+                        };
+                        nodes[i].set_color(r, g, b);
                     }
                     nodes[i].set_local_transformation((sym * joint).to_superset());
                     i += 1;
@@ -1,4 +1,3 @@
-extern crate alga;
 extern crate nalgebra;
 extern crate serde_json;
 
@@ -11,18 +10,18 @@ use std::f64::INFINITY;
 use std::fs::File;
 use std::process::exit;
 
-use alga::general::SubsetOf;
 use nalgebra::{UnitQuaternion, Vector3};
 
+use knot::convergence::{optimize_until_converged, StopReason, StoppingCriteria};
 use knot::optimize_tools::{Chain, Leg, PhantomJoint, RepulsionChain};
 use knot::defaults::continuous_optimization::{
-    COST_PARAMS, CURVE_9_40_CHAIN_SIZE, MAX_REPULSION_STRENGTH, RATE, REPULSION,
-    REPULSION_EXPONENT, REPULSION_STRENGTH, RETURN_TO_INITIAL, RETURN_TO_INITIAL_WEIGHT, STEPS,
+    CONVERGENCE, COST_PARAMS, DIVERGENCE_GUARD, LBFGS, LBFGS_PARAMS,
+    MAX_REPULSION_STRENGTH, RATE, REPULSION, REPULSION_EXPONENT, REPULSION_STRENGTH,
+    RETURN_TO_INITIAL, RETURN_TO_INITIAL_WEIGHT, STEPS,
 };
-use knot::geometries::curve_9_40;
+use knot::geometries::presets;
 use knot::isometry_adjust;
 use knot::report::{JointsParity, KnotGeometry, Transform};
-use knot::symmetry::{symmetries, symmetries_with_skip};
 
 const TAU: f64 = 2.0 * PI;
 const TWO_WEIGHT: f64 = 0.5;
@@ -43,24 +42,41 @@ fn cooling_schedule(epoch: i32, cost_diff: f64) -> bool {
 }
 
 fn optimize(chain: &mut RepulsionChain, steps: u32) -> f64 {
-    let mut last_cost = INFINITY;
-    for _ in 0..steps {
-        last_cost = chain.optimize();
+    if LBFGS {
+        if !REPULSION {
+            chain.repulsion_strength = 0.0;
+        }
+        return chain.lbfgs_optimize(&LBFGS_PARAMS).final_cost;
+    }
+
+    let criteria = StoppingCriteria {
+        max_iterations: steps,
+        ..CONVERGENCE
+    };
+    let history = optimize_until_converged(chain, &criteria, |chain| {
+        chain.try_optimize(&DIVERGENCE_GUARD)?;
 
         if REPULSION {
-            chain.repulse();
+            chain.try_repulse(&DIVERGENCE_GUARD)?;
         }
 
         if RETURN_TO_INITIAL {
             chain.return_to_initial();
         }
+        Ok(())
+    });
+    match history.stop_reason {
+        StopReason::Diverged(error) => {
+            eprintln!("Optimization diverged: {}", error);
+            INFINITY
+        }
+        _ => history.final_cost(),
     }
-    last_cost
 }
 
 fn main() {
     let (mut curr_chain, symms, parity) = match args().nth(1) {
-        Some(filename) => {
+        Some(ref filename) if filename != "--preset" => {
             let file = File::open(&filename).unwrap_or_else(|_| {
                 eprintln!("Could not open file {}", filename);
                 exit(1);
@@ -108,25 +124,24 @@ fn main() {
                 geometry.parity,
             )
         }
-        None => (
-            RepulsionChain::new(
-                curve_9_40::chain(
-                    CURVE_9_40_CHAIN_SIZE,
-                    0.7,
-                    COST_PARAMS,
-                    RETURN_TO_INITIAL_WEIGHT,
-                    RATE,
-                ),
-                symmetries(3).map(|quat| quat.to_superset()).collect(),
-                REPULSION_EXPONENT,
-                REPULSION_STRENGTH,
-                MAX_REPULSION_STRENGTH,
-            ),
-            symmetries_with_skip(3, 4)
-                .map(|iso| Transform::from_isometry(iso.to_superset()))
-                .collect(),
-            JointsParity::Even,
-        ),
+        flag => {
+            let name = match flag {
+                Some(_) => args().nth(2).unwrap_or_else(|| {
+                    eprintln!("Usage: --preset NAME");
+                    exit(1);
+                }),
+                None => "8_19".to_string(),
+            };
+            let preset = presets::find(&name).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            (
+                preset.repulsion_chain(COST_PARAMS, RETURN_TO_INITIAL_WEIGHT, RATE),
+                preset.transforms(),
+                JointsParity::Even,
+            )
+        }
     };
     let mut curr_cost = optimize(&mut curr_chain, STEPS);
     eprintln!("Original cost: {}", curr_cost);
@@ -222,6 +237,7 @@ fn main() {
         parity: parity,
         symmetries: symms,
         transforms,
+        constraints: curr_chain.constraints.clone(),
     };
 
     eprintln!("\nFinal geometry:");
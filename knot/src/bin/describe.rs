@@ -0,0 +1,79 @@
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::fs::File;
+use std::process::exit;
+
+use knot::report::KnotGeometry;
+
+use clap::{App, Arg};
+
+fn main() {
+    let matches = App::new("Knot Geometry Descriptors")
+        .about(
+            "Prints the writhe, average crossing number, radius of gyration and bounding \
+             dimensions of the centerline of each geometry, to compare embeddings of the same knot",
+        ).arg(
+            Arg::with_name("input")
+                .value_name("FILE.json")
+                .help("Sets the geometry files to describe")
+                .multiple(true)
+                .required(true),
+        ).arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the descriptors as JSON"),
+        ).get_matches();
+
+    let described = matches
+        .values_of("input")
+        .unwrap()
+        .map(|filename| {
+            let file = File::open(&filename).unwrap_or_else(|_| {
+                eprintln!("Could not open file {}", filename);
+                exit(1);
+            });
+            let geometry: KnotGeometry = serde_json::from_reader(file).unwrap_or_else(|_| {
+                eprintln!("Could not parse input file {}", filename);
+                exit(1);
+            });
+            if geometry.symmetries.len() < 4 || geometry.transforms.is_empty() {
+                eprintln!(
+                    "Geometry {} must have at least one joint and at least dihedral-2 symmetry",
+                    filename
+                );
+                exit(1);
+            }
+            (filename, geometry.descriptors())
+        }).collect::<Vec<_>>();
+
+    if matches.is_present("json") {
+        let json = described
+            .iter()
+            .map(|&(filename, ref descriptors)| {
+                let mut json = serde_json::to_value(descriptors).unwrap();
+                json["file"] = serde_json::Value::String(filename.to_string());
+                json
+            }).collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    } else {
+        println!("length    writhe   crossings  gyration  dimensions               file");
+        for &(filename, ref descriptors) in &described {
+            println!(
+                "{:<8.2}  {:<7.3}  {:<9.3}  {:<8.2}  {:<23}  {}",
+                descriptors.length,
+                descriptors.writhe,
+                descriptors.average_crossing_number,
+                descriptors.radius_of_gyration,
+                format!(
+                    "{:.2} x {:.2} x {:.2}",
+                    descriptors.dimensions[0], descriptors.dimensions[1], descriptors.dimensions[2],
+                ),
+                filename,
+            );
+        }
+    }
+}
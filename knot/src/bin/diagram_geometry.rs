@@ -0,0 +1,182 @@
+extern crate alga;
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::process::exit;
+
+use alga::general::SubsetOf;
+
+use knot::defaults;
+use knot::defaults::continuous_optimization::{RATE, RETURN_TO_INITIAL_WEIGHT};
+use knot::defaults::diagram::{ARC_LENGTH_STEP, LIFT, SCALE};
+use knot::geometries::from_diagram::{chain, parse_code, GaussCode, SymmetricEmbedding};
+use knot::geometries::presets;
+use knot::report::{JointsParity, KnotGeometry, Transform};
+use knot::topology::Invariants;
+
+use clap::{App, Arg, ArgGroup};
+
+fn main() {
+    let default_symmetry_str = defaults::SYMMETRY_COUNT.to_string();
+    let default_scale_str = SCALE.to_string();
+    let default_lift_str = LIFT.to_string();
+    let default_arc_length_str = ARC_LENGTH_STEP.to_string();
+
+    let matches = App::new("Knot Diagram Geometry Generator")
+        .about(
+            "Lays out a knot diagram with dihedral symmetry and follows it with a chain of joints, \
+             printing the geometry as JSON for continuous optimization",
+        ).arg(
+            Arg::with_name("dt")
+                .long("dt")
+                .value_name("CODE")
+                .help("Sets the diagram by its Dowker-Thistlethwaite code, such as \"4 6 2\""),
+        ).arg(
+            Arg::with_name("gauss")
+                .long("gauss")
+                .value_name("CODE")
+                .allow_hyphen_values(true)
+                .help("Sets the diagram by its Gauss code, such as \"1 -2 3 -1 2 -3\""),
+        ).arg(
+            Arg::with_name("preset")
+                .long("preset")
+                .value_name("NAME")
+                .help("Sets the diagram, symmetry and scale from a preset, such as \"9_40\""),
+        ).group(
+            ArgGroup::with_name("code")
+                .args(&["dt", "gauss", "preset"])
+                .required(true),
+        ).arg(
+            Arg::with_name("symmetry")
+                .long("symmetry")
+                .value_name("INT")
+                .default_value(&default_symmetry_str)
+                .help("Sets dihedral-N symmetry"),
+        ).arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .value_name("FLOAT")
+                .default_value(&default_scale_str)
+                .help("Sets the radius of the layout"),
+        ).arg(
+            Arg::with_name("lift")
+                .long("lift")
+                .value_name("FRACTION")
+                .default_value(&default_lift_str)
+                .help("Sets how far crossings are lifted out of the plane, relative to the radius"),
+        ).arg(
+            Arg::with_name("arc-length")
+                .long("arc-length")
+                .value_name("FLOAT")
+                .default_value(&default_arc_length_str)
+                .help("Sets the arc length between consecutive joints"),
+        ).get_matches();
+
+    let parse_float = |name: &str| {
+        matches
+            .value_of(name)
+            .unwrap()
+            .parse::<f64>()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid {}: {}", name, err);
+                exit(1);
+            })
+    };
+    let mut scale = parse_float("scale");
+    let lift = parse_float("lift");
+    let arc_length = parse_float("arc-length");
+    let mut symmetry = matches
+        .value_of("symmetry")
+        .unwrap()
+        .parse::<u32>()
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid symmetry: {}", err);
+            exit(1);
+        });
+
+    let code = match (matches.value_of("dt"), matches.value_of("gauss")) {
+        (Some(dt), _) => parse_code(dt).and_then(|code| GaussCode::from_dowker(&code)),
+        (_, Some(gauss)) => gauss.parse::<GaussCode>(),
+        _ => {
+            let name = matches.value_of("preset").unwrap();
+            let preset = presets::find(name).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            // Explicit options take precedence over the preset
+            if matches.occurrences_of("symmetry") == 0 {
+                symmetry = preset.symmetry;
+            }
+            if matches.occurrences_of("scale") == 0 {
+                scale = preset.scale;
+            }
+            Ok(preset.code().unwrap_or_else(|| {
+                eprintln!("Preset {} is not built from a diagram", preset.name);
+                exit(1);
+            }))
+        }
+    }.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+
+    let embedding = SymmetricEmbedding::new(&code, symmetry, lift).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+    // The infinite thresholds of the continuous optimization parameters can't be written as JSON,
+    // so the geometry records the defaults, like geometries exported from exhaustive search
+    let chain = chain(
+        &embedding,
+        scale,
+        arc_length,
+        defaults::COST_PARAMS,
+        RETURN_TO_INITIAL_WEIGHT,
+        RATE,
+    );
+
+    let geometry = KnotGeometry {
+        joint_spec: chain.spec,
+        num_angles: chain.num_angles,
+        cost_params: chain.cost_params,
+        parity: JointsParity::Even,
+        symmetries: embedding
+            .symmetries()
+            .iter()
+            .map(|&quat| Transform::from_isometry(quat.to_superset()))
+            .collect(),
+        transforms: chain
+            .joints
+            .iter()
+            .cloned()
+            .map(Transform::from_isometry)
+            .collect(),
+        constraints: Vec::new(),
+    };
+
+    let describe = |invariants: &Invariants| {
+        format!(
+            "{} ({} crossings, Alexander polynomial {:?})",
+            invariants
+                .knot_type
+                .map_or("unknown knot".to_string(), |knot_type| knot_type.to_string()),
+            invariants.crossings,
+            invariants.alexander,
+        )
+    };
+    let curve_invariants = Invariants::new(&embedding.sample(8));
+    let chain_invariants = Invariants::new(&geometry.polyline());
+    eprintln!("Code: {:?}", code.sequence());
+    eprintln!("Symmetry: dihedral-{}, skip {}", embedding.symmetry, embedding.skip);
+    eprintln!("Joints per copy: {}", chain.joints.len());
+    eprintln!("Curve: {}", describe(&curve_invariants));
+    eprintln!("Chain: {}", describe(&chain_invariants));
+    if chain_invariants.alexander != curve_invariants.alexander {
+        eprintln!("Warning: the chain does not follow the curve closely enough to keep its type");
+    }
+
+    println!("{}", serde_json::to_string_pretty(&geometry).unwrap());
+}
@@ -0,0 +1,41 @@
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::env::args;
+use std::fs::File;
+use std::process::exit;
+
+use knot::report::KnotGeometry;
+
+fn main() {
+    let filename = args().nth(1).unwrap_or_else(|| {
+        eprintln!("Expected a single input file");
+        exit(1);
+    });
+    let file = File::open(&filename).unwrap_or_else(|_| {
+        eprintln!("Could not open file {}", filename);
+        exit(1);
+    });
+    let geometry: KnotGeometry = serde_json::from_reader(file).unwrap_or_else(|_| {
+        eprintln!("Could not parse input file");
+        exit(1);
+    });
+    if geometry.symmetries.len() < 4 || geometry.transforms.len() < 2 {
+        eprintln!("Geometry must have at least two joints and at least dihedral-2 symmetry");
+        exit(1);
+    }
+
+    let recovered = geometry.discrete_angles();
+    eprintln!("Angle  Rounding error");
+    for (angle, error) in recovered.angles.iter().zip(&recovered.rounding_errors) {
+        eprintln!("{:<5}  {:+.4}", angle, error);
+    }
+    eprintln!("Final angle: {:.4}", recovered.final_angle);
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&geometry.discrete_reports()).unwrap()
+    );
+}
@@ -8,23 +8,29 @@ extern crate serde_json;
 extern crate knot;
 
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::f64::consts::PI;
 use std::f64::INFINITY;
 use std::fs::File;
 use std::process::exit;
 
+use alga::general::SubsetOf;
 use nalgebra::Isometry3;
 
 use knot::approx_locking_angle::locking_angle_opposing;
-use knot::cost::Costs;
+use knot::cost::{ConnectionCost, Costs};
 use knot::defaults;
-use knot::defaults::{COST_PARAMS, NUM_ANGLES};
-use knot::filter::{collisions_with_symmetry, points, CollisionOutcome, WindingAngles};
+use knot::defaults::{COST_PARAMS, NUM_ANGLES, SMOOTH_COST_MODEL};
+use knot::filter::{
+    capsule_collisions_with_symmetry, collision_pairs_with_symmetry, collisions_with_symmetry,
+    points, protection_depth, CollisionBackend, CollisionOutcome, CollisionStrategy, WindingAngles,
+};
 use knot::joint::{at_angles, discrete_symmetric_angles, JointSpec};
 use knot::report::{JointsParity, KnotReport, KnotReports};
-use knot::symmetry::adjacent_symmetry;
+use knot::symmetry::{adjacent_symmetry, symmetries_with_skip};
 use knot::symmetry_adjust;
 use knot::symmetry_adjust::Problem;
+use knot::topology::{closed_polyline, Invariants, KnotType};
 
 use rayon::prelude::IntoParallelIterator;
 use rayon::prelude::*;
@@ -55,7 +61,28 @@ const WINDING_ANGLE_TOLERANCE: f64 = 0.1;
 const KEEP_COUNT: usize = 4286;
 
 
-#[derive(Clone, Copy)]
+/// How the joints of a candidate knot are modeled when testing it for collisions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CollisionModel {
+    /// A sphere at every point of the chain
+    Spheres,
+
+    /// A capsule around every leg of every joint
+    Capsules,
+}
+
+/// Everything determining how candidate knots are tested for collisions.
+#[derive(Clone, Copy, Debug)]
+struct CollisionSettings {
+    model: CollisionModel,
+    protection_depth: u32,
+    strategy: CollisionStrategy,
+
+    /// How deeply colliding joints may interpenetrate for the knot to be kept as a near-miss
+    max_penetration: f64,
+}
+
+#[derive(Clone)]
 struct Knot {
     angles: [u32; NUM_JOINTS as usize],
     final_angle: f64,
@@ -63,6 +90,13 @@ struct Knot {
     cost: f64,
     costs: Costs,
     good_candidate: bool,
+
+    /// How deeply the deepest colliding pair interpenetrates, or zero without collisions.  Only
+    /// computed for candidates which pass the other checks, and only exactly for near-misses.
+    penetration: f64,
+
+    /// Only computed for good candidates
+    invariants: Option<Invariants>,
 }
 
 /// Float variant which supports total ordering by considering NaN to be the greatest value and
@@ -114,12 +148,14 @@ fn update_max<T: PartialOrd>(accum: &mut T, new: T) {
     }
 }
 
-fn generate_knot(
+fn generate_knot<C: ConnectionCost + Copy>(
+    cost_model: &C,
     spec: JointSpec,
     symmetry: u32,
     skip: u32,
     angles: [u32; NUM_JOINTS as usize],
     parity: JointsParity,
+    collision: CollisionSettings,
 ) -> Knot {
     let mut joint_transformations = [Isometry3::identity(); NUM_JOINTS as usize];
 
@@ -142,7 +178,7 @@ fn generate_knot(
     let last_joint_trans = joint_transformations.last().unwrap();
 
     let last_joint_out = last_joint_trans * spec.origin_to_out();
-    let problem = Problem::new(COST_PARAMS, last_joint_out, NUM_ANGLES, symmetry, skip);
+    let problem = Problem::new(*cost_model, last_joint_out, NUM_ANGLES, symmetry, skip);
 
     let (vars, cost) = problem.solve_direct();
 
@@ -175,21 +211,82 @@ fn generate_knot(
     let good_z = (max_z - min_z) >= spec.radius();
     let good_r = (max_r - min_r) >= 2.0 * spec.radius() && min_r >= spec.radius();
 
-    let good = if good_winding && good_z && good_r {
+    let penetration = if good_winding && good_z && good_r {
         // TODO: Is it really best to recompute this?  In principle we could cache it in a
         // stack-allocated structure, although the compile-time-size issues could be tricky.
         // let centers = include_midpoints(points(spec, joint_transformations.iter().cloned()))
         //     .map(|point| symmetry_adjust_trans * point);
-        let centers = points(spec, joint_transformations.iter().cloned())
-            .map(|point| symmetry_adjust_trans * point);
+        let centers = || {
+            points(spec, joint_transformations.iter().cloned())
+                .map(|point| symmetry_adjust_trans * point)
+        };
+        match collision.model {
+            CollisionModel::Spheres => match collisions_with_symmetry(
+                symmetry,
+                skip,
+                collision.protection_depth,
+                centers(),
+                spec.radius(),
+                collision.strategy,
+            ) {
+                CollisionOutcome::NoCollisions => 0.0,
+                // Finding every colliding pair is only worth it if near-misses are kept
+                CollisionOutcome::Collision if collision.max_penetration > 0.0 => {
+                    collision_pairs_with_symmetry(
+                        symmetry,
+                        skip,
+                        collision.protection_depth,
+                        centers(),
+                        spec.radius(),
+                        collision.strategy.backend,
+                    ).iter()
+                        .map(|pair| pair.penetration)
+                        .fold(0.0, f64::max)
+                }
+                CollisionOutcome::Collision => INFINITY,
+            },
+            CollisionModel::Capsules => {
+                let joints = joint_transformations
+                    .iter()
+                    .map(|&trans| symmetry_adjust_trans * trans);
+                let min_clearance = capsule_collisions_with_symmetry(
+                    symmetry,
+                    skip,
+                    collision.protection_depth,
+                    spec,
+                    joints,
+                    collision.strategy.backend,
+                ).min_clearance;
+                (-min_clearance).max(0.0)
+            }
+        }
+    } else {
+        INFINITY
+    };
+    let good = penetration <= collision.max_penetration;
 
-        let collision_outcome = collisions_with_symmetry(symmetry, skip, centers, spec.radius());
-        match collision_outcome {
-            CollisionOutcome::NoCollisions => true,
-            CollisionOutcome::Collision => false,
+    let invariants = if good {
+        let mut joints = Vec::with_capacity(NUM_JOINTS as usize + 1);
+        match parity {
+            JointsParity::Even => {}
+            JointsParity::Odd => joints.push(symmetry_adjust_trans * spec.origin_to_symmetric()),
         }
+        joints.extend(
+            joint_transformations
+                .iter()
+                .map(|&trans| symmetry_adjust_trans * trans),
+        );
+        let symmetries = symmetries_with_skip(symmetry, skip)
+            .map(|quat| quat.to_superset())
+            .collect::<Vec<_>>();
+        Some(Invariants::new(&closed_polyline(
+            &spec,
+            parity,
+            &joints,
+            &symmetries,
+        )))
     } else {
-        false
+        None
     };
 
     // TODO: avoid redundant computation
@@ -205,33 +302,76 @@ fn generate_knot(
         cost,
         costs,
         good_candidate: good,
+        penetration,
         final_angle,
+        invariants,
     }
 }
 
-fn generate_knots(spec: JointSpec, symmetry: u32, skip: u32, parity: JointsParity) -> Vec<Knot> {
+fn generate_knots<C: ConnectionCost + Copy + Sync>(
+    cost_model: &C,
+    spec: JointSpec,
+    symmetry: u32,
+    skip: u32,
+    parity: JointsParity,
+    collision: CollisionSettings,
+    knot_type: Option<KnotType>,
+) -> Vec<Knot> {
     println!("Generating {} candidate knots", NUM_ANGLES.pow(NUM_JOINTS));
     let mut knots = exhaustive!(NUM_ANGLES as u32; NUM_JOINTS)
-        .map(|angles| generate_knot(spec, symmetry, skip, angles, parity))
+        .map(|angles| {
+            generate_knot(cost_model, spec, symmetry, skip, angles, parity, collision)
+        })
         .filter(|knot| knot.good_candidate)
         .collect::<Vec<_>>();
 
     println!("Generated {} good knots", knots.len());
+    if collision.max_penetration > 0.0 {
+        println!(
+            "{} of them near-misses",
+            knots.iter().filter(|knot| knot.penetration > 0.0).count()
+        );
+    }
+
+    // Knots without a known type are told apart by their Alexander polynomials
+    let mut counts = BTreeMap::new();
+    for knot in &knots {
+        let invariants = knot.invariants.as_ref().unwrap();
+        *counts.entry(&invariants.alexander).or_insert(0) += 1;
+    }
+    println!("Knot types:");
+    for (alexander, count) in &counts {
+        println!(
+            "  {:<8} {:<24} {}",
+            KnotType::identify(alexander).map_or("unknown".to_string(), |t| t.to_string()),
+            format!("{:?}", alexander),
+            count,
+        );
+    }
 
+    if let Some(knot_type) = knot_type {
+        knots.retain(|knot| knot.invariants.as_ref().unwrap().matches(knot_type));
+        println!("Kept {} knots of type {}", knots.len(), knot_type);
+    }
+
+    // Knots free of collisions come first, by cost, followed by near-misses from the shallowest
     println!("Sorting knots");
-    knots.par_sort_unstable_by_key(|knot| NanGreatest(knot.cost));
+    knots.par_sort_unstable_by_key(|knot| (NanGreatest(knot.penetration), NanGreatest(knot.cost)));
     println!("Sorted knots");
 
     knots
 }
 
-fn generate_reports(
+fn generate_reports<C: ConnectionCost + Copy + Sync>(
+    cost_model: &C,
     spec: JointSpec,
     symmetry: u32,
     skip: u32,
     parity: JointsParity,
+    collision: CollisionSettings,
+    knot_type: Option<KnotType>,
 ) -> KnotReports {
-    let knots = generate_knots(spec, symmetry, skip, parity);
+    let knots = generate_knots(cost_model, spec, symmetry, skip, parity, collision, knot_type);
 
     let reports = knots[0..KEEP_COUNT.min(knots.len())]
         .iter()
@@ -243,6 +383,8 @@ fn generate_reports(
             symmetry_adjust: Some(knot.symmetry_adjust),
             costs: Some(knot.costs),
             total_cost: knot.cost,
+            invariants: knot.invariants.clone(),
+            penetration: knot.penetration,
         }).collect();
 
     KnotReports {
@@ -261,6 +403,10 @@ fn main() {
     let default_skip_str = (defaults::SYMMETRY_COUNT - 1).to_string();
     let default_bend_angle_str = defaults::joint_spec().bend_angle().to_degrees().to_string();
     let default_radius_str = defaults::joint_spec().radius().to_string();
+    let default_backend_str = match defaults::COLLISION_STRATEGY.backend {
+        CollisionBackend::Exhaustive => "exhaustive",
+        CollisionBackend::Grid => "grid",
+    };
 
     let matches = App::new("Exhaustive Symmetric Knot Model Generator")
         .author("William Brandon <hypercube97@gmail.com>")
@@ -301,6 +447,51 @@ fn main() {
                 .short("o")
                 .long("odd")
                 .help("Use an odd number of segments in each horseshoe"),
+        ).arg(
+            Arg::with_name("capsules")
+                .long("capsules")
+                .help("Test for collisions between the legs of joints, rather than their points"),
+        ).arg(
+            Arg::with_name("protection-depth")
+                .long("protection-depth")
+                .value_name("INT")
+                .help(
+                    "Sets how many legs apart along the chain points or joints must be to be \
+                     tested for collisions (computed from the joint geometry by default)",
+                ),
+        ).arg(
+            Arg::with_name("max-penetration")
+                .long("max-penetration")
+                .value_name("FLOAT")
+                .default_value("0")
+                .help(
+                    "Keeps knots whose joints interpenetrate by at most this depth, ranking them \
+                     after all knots free of collisions",
+                ),
+        ).arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .possible_values(&["exhaustive", "grid"])
+                .default_value(default_backend_str)
+                .help("Sets the data structure used to find potentially colliding pairs"),
+        ).arg(
+            Arg::with_name("wedge")
+                .long("wedge")
+                .help("Only test for sphere collisions in one wedge of the symmetry group"),
+        ).arg(
+            Arg::with_name("cost-model")
+                .long("cost-model")
+                .possible_values(&["standard", "smooth"])
+                .default_value("standard")
+                .help(
+                    "Sets the cost function used to rank knots.  The standard cost parameters are \
+                     recorded in the output either way, for use by later optimization",
+                ),
+        ).arg(
+            Arg::with_name("knot-type")
+                .long("knot-type")
+                .value_name("KNOT")
+                .help("Only keeps knots of the given type, such as 3_1 or trefoil"),
         ).get_matches();
 
     let output = matches.value_of("output").unwrap();
@@ -341,17 +532,62 @@ fn main() {
     } else {
         JointsParity::Even
     };
+    let knot_type = matches.value_of("knot-type").map(|name| {
+        name.parse::<KnotType>().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        })
+    });
+    let spec = JointSpec::new(1.0, 1.0, bend_angle, radius);
+    let collision = CollisionSettings {
+        model: if matches.is_present("capsules") {
+            CollisionModel::Capsules
+        } else {
+            CollisionModel::Spheres
+        },
+        protection_depth: match matches.value_of("protection-depth") {
+            Some(depth_str) => depth_str.parse::<u32>().unwrap_or_else(|err| {
+                eprintln!("Invalid protection depth: {}", err);
+                exit(1);
+            }),
+            None => protection_depth(&spec).unwrap_or_else(|| {
+                eprintln!("Joints which fold back on themselves always collide");
+                exit(1);
+            }),
+        },
+        strategy: CollisionStrategy {
+            backend: match matches.value_of("backend").unwrap() {
+                "grid" => CollisionBackend::Grid,
+                _ => CollisionBackend::Exhaustive,
+            },
+            use_wedge: matches.is_present("wedge"),
+        },
+        max_penetration: matches
+            .value_of("max-penetration")
+            .unwrap()
+            .parse::<f64>()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid maximum penetration: {}", err);
+                exit(1);
+            }),
+    };
 
     let mut file = File::create(&output).unwrap_or_else(|_| {
         eprintln!("Could not create file {}", output);
         exit(1);
     });
-    let reports = generate_reports(
-        JointSpec::new(1.0, 1.0, bend_angle, radius),
-        symmetry,
-        skip,
-        parity,
-    );
+    let reports = match matches.value_of("cost-model").unwrap() {
+        "smooth" => generate_reports(
+            &SMOOTH_COST_MODEL,
+            spec,
+            symmetry,
+            skip,
+            parity,
+            collision,
+            knot_type,
+        ),
+        _ => generate_reports(&COST_PARAMS, spec, symmetry, skip, parity, collision, knot_type),
+    };
 
     println!(
         "Serializing best {} knots to {}",
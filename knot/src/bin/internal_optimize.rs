@@ -0,0 +1,66 @@
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::env::args;
+use std::fs::File;
+use std::process::exit;
+
+use knot::defaults::continuous_optimization::LBFGS_PARAMS;
+use knot::internal_chain::InternalChain;
+use knot::report::{complete_reports, CompleteKnotReports};
+
+fn main() {
+    let filename = args().nth(1).unwrap_or_else(|| {
+        eprintln!("Expected a single input file");
+        exit(1);
+    });
+    let index = args()
+        .nth(2)
+        .unwrap_or_else(|| {
+            eprintln!("Expected a result index");
+            exit(1);
+        }).parse::<usize>()
+        .unwrap_or_else(|_| {
+            eprintln!("Index must be an integer");
+            exit(1)
+        });
+    let file = File::open(&filename).unwrap_or_else(|_| {
+        eprintln!("Could not open file {}", filename);
+        exit(1);
+    });
+    let reports: CompleteKnotReports =
+        complete_reports(serde_json::from_reader(file).unwrap_or_else(|_| {
+            eprintln!("Could not parse input file");
+            exit(1);
+        }));
+
+    if !(index < reports.knots.len()) {
+        eprintln!(
+            "Index out of bounds -- only {} reports",
+            reports.knots.len()
+        );
+        exit(1);
+    }
+
+    let mut chain = InternalChain::from_discrete(
+        reports.joint_spec,
+        reports.num_angles,
+        reports.parity,
+        reports.symmetry_count,
+        reports.symmetry_skip,
+        reports.cost_params,
+        &reports.knots[index].angles,
+    );
+
+    let initial_cost = chain.total_cost();
+    let report = chain.lbfgs_optimize(&LBFGS_PARAMS);
+    eprintln!(
+        "Cost {} -> {} after {} iterations ({:?})",
+        initial_cost, report.final_cost, report.iterations, report.termination
+    );
+    eprintln!("Nearest discrete angles: {:?}", chain.nearest_discrete_angles());
+
+    println!("{}", serde_json::to_string_pretty(&chain.geometry()).unwrap());
+}
@@ -0,0 +1,54 @@
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::env::args;
+use std::fs::File;
+use std::io::Read;
+use std::process::exit;
+
+use knot::defaults;
+use knot::report::{KnotGeometry, KnotReports};
+
+fn main() {
+    let filename = args().nth(1).unwrap_or_else(|| {
+        eprintln!("Expected a single input file");
+        exit(1);
+    });
+    let mut contents = String::new();
+    File::open(&filename)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .unwrap_or_else(|_| {
+            eprintln!("Could not open file {}", filename);
+            exit(1);
+        });
+
+    // The input may be either a geometry or a set of reports
+    if let Ok(geometry) = serde_json::from_str::<KnotGeometry>(&contents) {
+        if geometry.symmetries.len() < 4 || geometry.transforms.len() < 2 {
+            eprintln!("Geometry must have at least two joints and at least dihedral-2 symmetry");
+            exit(1);
+        }
+        eprintln!(
+            "The knot is {}",
+            if geometry.is_amphichiral() {
+                "its own mirror image"
+            } else {
+                "chiral"
+            }
+        );
+        println!("{}", serde_json::to_string_pretty(&geometry.mirror()).unwrap());
+    } else if let Ok(reports) = serde_json::from_str::<KnotReports>(&contents) {
+        let num_angles = reports.num_angles.unwrap_or(defaults::NUM_ANGLES);
+        for (i, knot) in reports.knots.iter().enumerate() {
+            if knot.is_amphichiral(num_angles, reports.parity) {
+                eprintln!("Knot {} is its own mirror image", i);
+            }
+        }
+        println!("{}", serde_json::to_string_pretty(&reports.mirror()).unwrap());
+    } else {
+        eprintln!("Could not parse input file");
+        exit(1);
+    }
+}
@@ -6,39 +6,79 @@ extern crate nalgebra;
 extern crate serde;
 extern crate serde_json;
 
-use std::f64::consts::PI;
+use std::env::args;
+use std::fs::File;
+use std::process::exit;
 
 use kiss3d::light::Light;
 use kiss3d::window::Window;
 
+use knot::continuation::continuation;
+use knot::convergence::{optimize_until_converged, OptimizationHistory, StoppingCriteria};
 use knot::defaults;
-use knot::optimize_tools::{RepulsionChain};
+use knot::optimize_tools::{Chain, DivergenceError, RepulsionChain};
 use knot::defaults::continuous_optimization::{
-    COST_PARAMS, MAX_REPULSION_STRENGTH, RATE,
+    CONVERGENCE, COST_PARAMS, MAX_REPULSION_STRENGTH, RATE,
     REPULSION_EXPONENT, REPULSION_STRENGTH, RETURN_TO_INITIAL_WEIGHT,
-    CONTINUOUS_PARAMS
+    CONTINUOUS_PARAMS, DIVERGENCE_GUARD, LBFGS, LBFGS_PARAMS, LOCKING_CONTINUATION
 };
 use knot::geometries::custom_spline::generate_custom_spline;
 use knot::joint::Point;
-use knot::geometries::from_spline;
-use knot::symmetry::{symmetries, symmetries_with_skip};
+use knot::report::JointsParity;
+use knot::geometries::{from_spline, presets};
+use knot::symmetry::symmetries;
 use knot::visualize::joint_render::{add_joints, Style};
 
 use alga::general::SubsetOf;
-use nalgebra::{Isometry3, UnitQuaternion, Vector3};
+
+fn print_angles(chain: &Chain, parity: JointsParity) {
+    let recovered = chain.discrete_angles(parity);
+    for (angle, error) in recovered.angles.iter().zip(&recovered.rounding_errors) {
+        println!("{} ({:+.3})", angle, error);
+    }
+}
+
+fn step(chain: &mut RepulsionChain) -> Result<(), DivergenceError> {
+    chain.try_adaptive_optimize(&[2.0, 1.0, 0.5], 0.5, &DIVERGENCE_GUARD)?;
+    chain.try_repulse(&DIVERGENCE_GUARD)
+}
+
+/// Write the history of one phase of the optimization to `<history_prefix>_<phase>.csv`, if a
+/// prefix was given.
+fn write_history(history_prefix: &Option<String>, phase: &str, history: &OptimizationHistory) {
+    eprintln!(
+        "{}: {:?} after {} iterations",
+        phase,
+        history.stop_reason,
+        history.iterations()
+    );
+
+    if let Some(prefix) = history_prefix {
+        let filename = format!("{}_{}.csv", prefix, phase);
+        let file = File::create(&filename).unwrap_or_else(|_| {
+            eprintln!("Could not create file {}", filename);
+            exit(1);
+        });
+        history.write_csv(file).unwrap_or_else(|_| {
+            eprintln!("Could not write file {}", filename);
+            exit(1);
+        });
+    }
+}
 
 // Step 1: Create a bspline function! See trefoil_spline::generate_trefoil() for an example.
 // Step 2: Visualize the knot with no locking costs to confirm that the knot matches your idea.
 
 // Run gradient descent optimization on them to find "turn" angles
 
-fn polyline_optimize<F: Fn() -> bspline::BSpline<Point> + Copy>(
+fn spline_chain<F: Fn() -> bspline::BSpline<Point> + Copy>(
     bspline_generator: F,
     knot_sym: u32, //sym
+    skip: u32,
     scale: f32,
-) {
+) -> RepulsionChain {
     // Create a continuous knot using this curve
-    let mut chain = RepulsionChain::new(
+    RepulsionChain::new(
         from_spline::generic_chain(
             scale, // scale
             CONTINUOUS_PARAMS, // No angle locking weights.
@@ -47,66 +87,48 @@ fn polyline_optimize<F: Fn() -> bspline::BSpline<Point> + Copy>(
             defaults::joint_spec(),
             bspline_generator,
             knot_sym,
+            skip,
         ),
         symmetries(knot_sym).map(|quat| quat.to_superset()).collect(),
         REPULSION_EXPONENT,
         REPULSION_STRENGTH,
         MAX_REPULSION_STRENGTH,
-    );
+    )
+}
 
-    // Run gradient descent on chain.
-    for _ in 0..100000 {
-        chain.adaptive_optimize(&[2.0, 1.0, 0.5], 0.5);
-        chain.repulse();
+fn polyline_optimize(mut chain: RepulsionChain, history_prefix: Option<String>) {
+    // Relax the chain, with L-BFGS or by gradient descent.
+    if LBFGS {
+        let report = chain.lbfgs_optimize(&LBFGS_PARAMS);
+        eprintln!(
+            "relax: {:?} after {} iterations",
+            report.termination, report.iterations
+        );
+    } else {
+        let criteria = StoppingCriteria {
+            max_iterations: 100_000,
+            ..CONVERGENCE
+        };
+        let history = optimize_until_converged(&mut chain, &criteria, step);
+        write_history(&history_prefix, "relax", &history);
     }
 
-    // Impose locking weights, let it find its own best.
-    chain.cost_params.locking_weight = 0.17;
-    for _ in 0..50000 {
-        chain.adaptive_optimize(&[2.0, 1.0, 0.5], 0.5);
-        chain.repulse();
+    // Gradually impose locking weights, letting each joint find its own best angle.
+    let report = continuation(&mut chain, &LOCKING_CONTINUATION, JointsParity::Even, step);
+    for (i, stage) in report.stages.iter().enumerate() {
+        write_history(&history_prefix, &format!("stage_{}", i), &stage.history);
     }
-    cost = chain.adaptive_optimize(&[2.0, 1.0, 0.5], 0.5);
-    println!("{}", cost);
-    prev_trans = Isometry3::identity();
-    for &joint in &chain.joints {
-        let trans_0 = prev_trans;
-        let trans_1 = joint * chain.spec.origin_to_in();
-
-        let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-        let axis_1 = trans_1 * Vector3::y_axis().to_superset();
-
-        let align = UnitQuaternion::rotation_between(&axis_1, &axis_0)
-            .unwrap_or(UnitQuaternion::identity());
-        let aligned_rel_rotation =
-            trans_0.rotation.inverse() * align * trans_1.rotation;
-        let locking_angle = aligned_rel_rotation.angle();
-        let locking_number =
-            locking_angle / (2.0 * PI) * (chain.num_angles as f64);
-        println!("{}", locking_number);
-        prev_trans = joint * chain.spec.origin_to_out();
+    for (i, snap) in report.snaps.iter().enumerate() {
+        match snap {
+            Some(snap) => eprintln!(
+                "Angle {} snapped to {} at locking weight {}",
+                i, snap.angle, snap.locking_weight
+            ),
+            None => eprintln!("Angle {} did not snap", i),
+        }
     }
-
-    // let mut prev = 2.0;
-    // let mut cost = 1.0;
-    // let mut step = 0;
-    // let mut count = 0;
-    //
-    // while count < 3 {
-    //     step += 1;
-    //     prev = cost;
-    //     for _ in 0..10000 {
-    //         chain.adaptive_optimize(&[2.0, 1.0, 0.5], 0.5);
-    //         chain.repulse();
-    //     }
-    //     cost = chain.adaptive_optimize(&[2.0, 1.0, 0.5], 0.5);
-    //     println!("{{{}, {}}},", step, cost);
-    //     if (prev - cost > 0.00001) {
-    //         count += 1;
-    //     } else {
-    //         count = 0;
-    //     }
-    // }
+    println!("{}", report.stages.last().unwrap().history.final_cost());
+    print_angles(&chain, JointsParity::Even);
 
     let mut window = Window::new("Polyline Optimization");
     window.set_light(Light::StickToCamera);
@@ -116,14 +138,14 @@ fn polyline_optimize<F: Fn() -> bspline::BSpline<Point> + Copy>(
         window.scene_mut(), // Scene
         &chain.spec, // JointSpec
         16 as u16, // Number of angles
-        chain.joints.len() * 6, // Number of joints
+        chain.joints.len() * chain.symmetries.len(), // Number of joints
         Style::Flat,
     );
 
     while window.render() {
         {
             let mut i = 0;
-            for sym in symmetries_with_skip(3, 4) {
+            for sym in &chain.symmetries {
                 for &joint in &chain.joints {
                     nodes[i].set_color(0.5, 0.5, 0.5);
                     nodes[i].set_local_transformation((sym * joint).to_superset());
@@ -146,5 +168,22 @@ fn polyline_optimize<F: Fn() -> bspline::BSpline<Point> + Copy>(
 // Find best one.
 
 fn main() {
-    polyline_optimize(generate_custom_spline, 6, 4.0);
+    let (chain, history_prefix) = match args().nth(1) {
+        Some(ref flag) if flag == "--preset" => {
+            let name = args().nth(2).unwrap_or_else(|| {
+                eprintln!("Usage: --preset NAME [HISTORY_PREFIX]");
+                exit(1);
+            });
+            let preset = presets::find(&name).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            (
+                preset.repulsion_chain(CONTINUOUS_PARAMS, RETURN_TO_INITIAL_WEIGHT, RATE),
+                args().nth(3),
+            )
+        }
+        history_prefix => (spline_chain(generate_custom_spline, 6, 2, 4.0), history_prefix),
+    };
+    polyline_optimize(chain, history_prefix);
 }
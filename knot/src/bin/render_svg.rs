@@ -0,0 +1,124 @@
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::exit;
+
+use knot::report::{complete_report, complete_reports, knot_geometry, KnotGeometry, KnotReports};
+use knot::svg::{SvgOptions, View};
+
+use clap::{App, Arg};
+
+fn main() {
+    let matches = App::new("Knot SVG Diagrams")
+        .about(
+            "Draws a knot as a two-dimensional SVG diagram from one or more viewpoints, without a \
+             GPU, to accompany its assembly instructions",
+        ).arg(
+            Arg::with_name("input")
+                .value_name("FILE.json")
+                .help("Sets the geometry or reports file to draw")
+                .required(true),
+        ).arg(
+            Arg::with_name("index")
+                .long("index")
+                .value_name("INT")
+                .default_value("0")
+                .help("Sets which knot of a reports file to draw"),
+        ).arg(
+            Arg::with_name("view")
+                .long("view")
+                .value_name("VIEW")
+                .allow_hyphen_values(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Adds a view: top, front, side, or an azimuth and elevation in degrees such \
+                     as -60,30",
+                ),
+        ).arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("COLORING")
+                .possible_values(&["symmetry", "cost"])
+                .default_value("symmetry")
+                .help("Colors each leg by its symmetric copy or by the cost of its junction"),
+        ).arg(
+            Arg::with_name("labels")
+                .long("labels")
+                .help("Labels every joint, and the clicks at every junction"),
+        ).arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("FILE.svg")
+                .help("Sets the file to write, instead of standard output"),
+        ).get_matches();
+
+    let filename = matches.value_of("input").unwrap();
+    let mut contents = String::new();
+    File::open(&filename)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .unwrap_or_else(|_| {
+            eprintln!("Could not open file {}", filename);
+            exit(1);
+        });
+
+    // The input may be either a geometry or a set of reports
+    let geometry = if let Ok(geometry) = serde_json::from_str::<KnotGeometry>(&contents) {
+        geometry
+    } else if let Ok(reports) = serde_json::from_str::<KnotReports>(&contents) {
+        let index = matches
+            .value_of("index")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap_or_else(|_| {
+                eprintln!("Index must be an integer");
+                exit(1);
+            });
+        if !(index < reports.knots.len()) {
+            eprintln!("Index out of bounds -- only {} reports", reports.knots.len());
+            exit(1);
+        }
+        let reports = complete_reports(reports);
+        let knot = complete_report(&reports, index);
+        knot_geometry(&reports, &knot)
+    } else {
+        eprintln!("Could not parse input file");
+        exit(1);
+    };
+    if geometry.symmetries.len() < 4 || geometry.transforms.len() < 2 {
+        eprintln!("Geometry must have at least two joints and at least dihedral-2 symmetry");
+        exit(1);
+    }
+
+    let mut options = SvgOptions::default();
+    if let Some(views) = matches.values_of("view") {
+        options.views = views
+            .map(|view| {
+                view.parse::<View>().unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                })
+            }).collect();
+    }
+    options.coloring = matches.value_of("color").unwrap().parse().unwrap();
+    options.labels = matches.is_present("labels");
+
+    let svg = geometry.svg(&options);
+    match matches.value_of("output") {
+        Some(output) => {
+            File::create(output)
+                .and_then(|mut file| file.write_all(svg.as_bytes()))
+                .unwrap_or_else(|_| {
+                    eprintln!("Could not write file {}", output);
+                    exit(1);
+                });
+        }
+        None => print!("{}", svg),
+    }
+}
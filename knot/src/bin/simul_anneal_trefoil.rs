@@ -22,16 +22,18 @@ use kiss3d::event::{WindowEvent, Action, Key};
 use alga::general::SubsetOf;
 use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
 
+use knot::convergence::{optimize_until_converged, StopReason, StoppingCriteria};
 use knot::optimize_tools::{Chain, Leg, PhantomJoint, RepulsionChain};
 use knot::defaults;
 use knot::defaults::continuous_optimization::{
-    COST_PARAMS, TREFOIL_CHAIN_SIZE, MAX_REPULSION_STRENGTH, RATE, REPULSION,
-    REPULSION_EXPONENT, REPULSION_STRENGTH, RETURN_TO_INITIAL, RETURN_TO_INITIAL_WEIGHT, STEPS,
+    CONVERGENCE, COST_PARAMS, DIVERGENCE_GUARD, LBFGS, LBFGS_PARAMS,
+    MAX_REPULSION_STRENGTH, RATE, REPULSION, REPULSION_EXPONENT, REPULSION_STRENGTH,
+    RETURN_TO_INITIAL, RETURN_TO_INITIAL_WEIGHT, STEPS,
 };
-use knot::geometries::trefoil_curve;
+use knot::geometries::presets;
 use knot::isometry_adjust;
 use knot::report::{JointsParity, KnotGeometry, Transform};
-use knot::symmetry::{symmetries, symmetries_with_skip};
+use knot::symmetry::symmetries;
 use knot::visualize::joint_render::{add_joints, Style};
 use knot::joint::{RelativeJoint, at_angles};
 
@@ -41,6 +43,13 @@ const EPOCHS: i32 = 500;
 
 const DEBUG_ANGLES: bool = false;
 
+fn print_angles(chain: &Chain, parity: JointsParity) {
+    let recovered = chain.discrete_angles(parity);
+    for (angle, error) in recovered.angles.iter().zip(&recovered.rounding_errors) {
+        println!("{} ({:+.3})", angle, error);
+    }
+}
+
 
 /* Adjust the constant value as needed!
 fn cooling_schedule(epoch: i32, cost_diff: f64) -> bool {
@@ -74,24 +83,41 @@ fn cooling_schedule(epoch: i32, cost_diff: f64) -> bool {
 }
 
 fn optimize(chain: &mut RepulsionChain, steps: u32) -> f64 {
-    let mut last_cost = INFINITY;
-    for _ in 0..steps {
-        last_cost = chain.optimize();
+    if LBFGS {
+        if !REPULSION {
+            chain.repulsion_strength = 0.0;
+        }
+        return chain.lbfgs_optimize(&LBFGS_PARAMS).final_cost;
+    }
+
+    let criteria = StoppingCriteria {
+        max_iterations: steps,
+        ..CONVERGENCE
+    };
+    let history = optimize_until_converged(chain, &criteria, |chain| {
+        chain.try_optimize(&DIVERGENCE_GUARD)?;
 
         if REPULSION {
-            chain.repulse();
+            chain.try_repulse(&DIVERGENCE_GUARD)?;
         }
 
         if RETURN_TO_INITIAL {
             chain.return_to_initial();
         }
+        Ok(())
+    });
+    match history.stop_reason {
+        StopReason::Diverged(error) => {
+            eprintln!("Optimization diverged: {}", error);
+            INFINITY
+        }
+        _ => history.final_cost(),
     }
-    last_cost
 }
 
 fn main() {
     let (mut curr_chain, symms, parity) = match args().nth(1) {
-        Some(filename) => {
+        Some(ref filename) if filename != "--preset" => {
             let file = File::open(&filename).unwrap_or_else(|_| {
                 eprintln!("Could not open file {}", filename);
                 exit(1);
@@ -139,47 +165,29 @@ fn main() {
                 geometry.parity,
             )
         }
-        None => (
-            RepulsionChain::new(
-                trefoil_curve::chain(
-                    TREFOIL_CHAIN_SIZE,
-                    3.5,
-                    COST_PARAMS,
-                    RETURN_TO_INITIAL_WEIGHT,
-                    RATE,
-                ),
-                symmetries(3).map(|quat| quat.to_superset()).collect(),
-                REPULSION_EXPONENT,
-                REPULSION_STRENGTH,
-                MAX_REPULSION_STRENGTH,
-            ),
-            symmetries_with_skip(3, 2)
-                .map(|iso| Transform::from_isometry(iso.to_superset()))
-                .collect(),
-            JointsParity::Even,
-        ),
+        flag => {
+            let name = match flag {
+                Some(_) => args().nth(2).unwrap_or_else(|| {
+                    eprintln!("Usage: --preset NAME");
+                    exit(1);
+                }),
+                None => "3_1".to_string(),
+            };
+            let preset = presets::find(&name).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            (
+                preset.repulsion_chain(COST_PARAMS, RETURN_TO_INITIAL_WEIGHT, RATE),
+                preset.transforms(),
+                JointsParity::Even,
+            )
+        }
     };
     let mut curr_cost = optimize(&mut curr_chain, STEPS);
     println!("Original cost: {}", curr_cost);
     println!("Approximate original locking angles:");
-    let mut prev_trans = Isometry3::identity();
-    for &joint in &curr_chain.joints {
-        let trans_0 = prev_trans;
-        let trans_1 = joint * curr_chain.spec.origin_to_in();
-
-        let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-        let axis_1 = trans_1 * Vector3::y_axis().to_superset();
-
-        let align = UnitQuaternion::rotation_between(&axis_1, &axis_0)
-            .unwrap_or(UnitQuaternion::identity());
-        let aligned_rel_rotation =
-            trans_0.rotation.inverse() * align * trans_1.rotation;
-        let locking_angle = aligned_rel_rotation.angle();
-        let locking_number =
-            locking_angle / (2.0 * PI) * (curr_chain.num_angles as f64);
-        println!("{}", locking_number);
-        prev_trans = joint * curr_chain.spec.origin_to_out();
-    }
+    print_angles(&curr_chain, parity);
 
     let mut steps: Vec<(usize, f64)> = Vec::new();
 
@@ -287,47 +295,13 @@ fn main() {
     println!("{}", curr_cost);
 
     println!("Approximate locking angles:");
-    let mut prev_trans = Isometry3::identity();
-    for &joint in &curr_chain.joints {
-        let trans_0 = prev_trans;
-        let trans_1 = joint * curr_chain.spec.origin_to_in();
-
-        let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-        let axis_1 = trans_1 * Vector3::y_axis().to_superset();
-
-        let align = UnitQuaternion::rotation_between(&axis_1, &axis_0)
-            .unwrap_or(UnitQuaternion::identity());
-        let aligned_rel_rotation =
-            trans_0.rotation.inverse() * align * trans_1.rotation;
-        let locking_angle = aligned_rel_rotation.angle();
-        let locking_number =
-            locking_angle / (2.0 * PI) * (curr_chain.num_angles as f64);
-        println!("{}", locking_number);
-        prev_trans = joint * curr_chain.spec.origin_to_out();
-    }
+    print_angles(&curr_chain, parity);
 
     println!("\nBest Found Cost");
     println!("{} at epoch {}", best_cost, best_epoch);
 
     println!("Approximate locking angles:");
-    let mut prev_trans = Isometry3::identity();
-    for &joint in &best_chain.joints {
-        let trans_0 = prev_trans;
-        let trans_1 = joint * best_chain.spec.origin_to_in();
-
-        let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-        let axis_1 = trans_1 * Vector3::y_axis().to_superset();
-
-        let align = UnitQuaternion::rotation_between(&axis_1, &axis_0)
-            .unwrap_or(UnitQuaternion::identity());
-        let aligned_rel_rotation =
-            trans_0.rotation.inverse() * align * trans_1.rotation;
-        let locking_angle = aligned_rel_rotation.angle();
-        let locking_number =
-            locking_angle / (2.0 * PI) * (best_chain.num_angles as f64);
-        println!("{}", locking_number);
-        prev_trans = joint * best_chain.spec.origin_to_out();
-    }
+    print_angles(&best_chain, parity);
 
     let mut chain = best_chain;
 
@@ -364,24 +338,7 @@ fn main() {
                 WindowEvent::Key(code, _, _) => match code {
                     Key::Space => {
                         println!("Approximate locking angles:");
-                        let mut prev_trans = Isometry3::identity();
-                        for &joint in &chain.joints {
-                            let trans_0 = prev_trans;
-                            let trans_1 = joint * chain.spec.origin_to_in();
-
-                            let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-                            let axis_1 = trans_1 * Vector3::y_axis().to_superset();
-
-                            let align = UnitQuaternion::rotation_between(&axis_1, &axis_0)
-                                .unwrap_or(UnitQuaternion::identity());
-                            let aligned_rel_rotation =
-                                trans_0.rotation.inverse() * align * trans_1.rotation;
-                            let locking_angle = aligned_rel_rotation.angle();
-                            let locking_number =
-                                locking_angle / (2.0 * PI) * (chain.num_angles as f64);
-                            println!("{}", locking_number);
-                            prev_trans = joint * chain.spec.origin_to_out();
-                        }
+                        print_angles(&chain, parity);
                     }
                     Key::Right => {
                         chain.cost_params.locking_weight *= 1.5;
@@ -0,0 +1,165 @@
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+
+extern crate knot;
+
+use std::fs::File;
+use std::process::exit;
+
+use knot::defaults::validation::TOLERANCES;
+use knot::report::KnotGeometry;
+use knot::topology::{Invariants, KnotType};
+use knot::validate::{validate, Tolerances};
+
+use clap::{App, Arg};
+
+fn main() {
+    let default_dist_str = TOLERANCES.dist.to_string();
+    let default_axis_str = TOLERANCES.axis.to_string();
+    let default_locking_str = TOLERANCES.locking.to_string();
+
+    let matches = App::new("Knot Geometry Validator")
+        .about(
+            "Checks that a geometry can be physically assembled.  Exits with status 0 if it can, \
+             and 1 otherwise.",
+        ).arg(
+            Arg::with_name("input")
+                .value_name("FILE.json")
+                .help("Sets the geometry file to validate")
+                .required(true),
+        ).arg(
+            Arg::with_name("max-dist")
+                .long("max-dist")
+                .value_name("FLOAT")
+                .default_value(&default_dist_str)
+                .help("Sets the maximum distance between the ends of joints which meet"),
+        ).arg(
+            Arg::with_name("max-axis")
+                .long("max-axis")
+                .value_name("FLOAT")
+                .default_value(&default_axis_str)
+                .help("Sets the maximum axis misalignment cost at any junction"),
+        ).arg(
+            Arg::with_name("max-locking")
+                .long("max-locking")
+                .value_name("FRACTION")
+                .default_value(&default_locking_str)
+                .help("Sets the maximum deviation of any locking angle from a valid angle"),
+        ).arg(
+            Arg::with_name("knot-type")
+                .long("knot-type")
+                .value_name("KNOT")
+                .help(
+                    "Also checks that the geometry forms the given knot, such as 3_1 or trefoil, \
+                     by its invariants",
+                ),
+        ).arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the full validation report as JSON"),
+        ).get_matches();
+
+    let parse_tolerance = |name: &str| {
+        matches
+            .value_of(name)
+            .unwrap()
+            .parse::<f64>()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid {}: {}", name, err);
+                exit(1);
+            })
+    };
+    let tolerances = Tolerances {
+        dist: parse_tolerance("max-dist"),
+        axis: parse_tolerance("max-axis"),
+        locking: parse_tolerance("max-locking"),
+    };
+
+    let knot_type = matches.value_of("knot-type").map(|name| {
+        name.parse::<KnotType>().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        })
+    });
+
+    let filename = matches.value_of("input").unwrap();
+    let file = File::open(&filename).unwrap_or_else(|_| {
+        eprintln!("Could not open file {}", filename);
+        exit(1);
+    });
+    let geometry: KnotGeometry = serde_json::from_reader(file).unwrap_or_else(|_| {
+        eprintln!("Could not parse input file");
+        exit(1);
+    });
+    let report = validate(&geometry, &tolerances).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+    let invariants = knot_type.map(|_| Invariants::new(&geometry.polyline()));
+    let knot_type_matches = match (knot_type, &invariants) {
+        (Some(knot_type), &Some(ref invariants)) => invariants.matches(knot_type),
+        _ => true,
+    };
+
+    if matches.is_present("json") {
+        let mut json = serde_json::to_value(&report).unwrap();
+        if let Some(ref invariants) = invariants {
+            json["invariants"] = serde_json::to_value(invariants).unwrap();
+            json["knot_type_matches"] = serde_json::Value::Bool(knot_type_matches);
+        }
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    } else {
+        println!("junction  distance  axis      locking  deviation");
+        for junction in &report.junctions {
+            println!(
+                "{:<8}  {:<8.2e}  {:<8.2e}  {:<7.3}  {:.3}{}",
+                junction.index,
+                junction.costs.dist.sqrt(),
+                junction.costs.axis,
+                junction.locking_angle,
+                junction.locking_deviation,
+                if junction.within(&tolerances) {
+                    ""
+                } else {
+                    "  (out of tolerance)"
+                },
+            );
+        }
+
+        println!("{} collisions", report.collisions.len());
+        for pair in &report.collisions {
+            println!(
+                "  joint {} (symmetry {}) and joint {} (symmetry {}): penetration {:.4}",
+                pair.first.index,
+                pair.first.symmetry_index,
+                pair.second.index,
+                pair.second.symmetry_index,
+                pair.penetration,
+            );
+        }
+
+        if let (Some(knot_type), Some(invariants)) = (knot_type, &invariants) {
+            println!(
+                "{} crossings, Alexander polynomial {:?}, knot type {}{}",
+                invariants.crossings,
+                invariants.alexander,
+                invariants
+                    .knot_type
+                    .map_or("unknown".to_string(), |knot_type| knot_type.to_string()),
+                if knot_type_matches {
+                    String::new()
+                } else {
+                    format!("  (expected {})", knot_type)
+                },
+            );
+        }
+
+        let valid = report.valid && knot_type_matches;
+        println!("{}", if valid { "PASS" } else { "FAIL" });
+    }
+
+    if !report.valid || !knot_type_matches {
+        exit(1);
+    }
+}
@@ -0,0 +1,284 @@
+//! Mirror images of knot designs.
+//!
+//! Designs are mirrored through the xy plane, which is perpendicular to the axis of their dihedral
+//! symmetry.  This reflection commutes with every symmetry of the knot, so a mirrored design has
+//! the same symmetries, skip and parity as the original, and only the twist of each joint relative
+//! to the previous one is reversed.
+//!
+//! A design with even parity can also be read from the other end of its horseshoe, starting from
+//! the junction with the adjacent horseshoe, which describes the same physical knot with different
+//! angles.  A knot is amphichiral if its mirror image matches it read from either end.
+
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+
+use defaults;
+use report::{JointsParity, KnotGeometry, KnotReport, KnotReports, Transform};
+
+/// The discrete angle giving the opposite twist to `angle`, in `0..num_angles`.
+pub fn mirror_angle(angle: i32, num_angles: u16) -> i32 {
+    let num_angles = num_angles as i32;
+    ((num_angles - angle % num_angles) % num_angles + num_angles) % num_angles
+}
+
+/// The canonical form of a sequence of discrete angles, with each angle in `0..num_angles`.
+/// Angles differing by a whole revolution describe the same design, including the first angle of
+/// an even-parity chain, which is only the twist between the first joint and its mirror image.
+pub fn canonical_angles(angles: &[i32], num_angles: u16) -> Vec<i32> {
+    let num_angles = num_angles as i32;
+    angles
+        .iter()
+        .map(|&angle| ((angle % num_angles) + num_angles) % num_angles)
+        .collect()
+}
+
+/// The discrete angles, in canonical form, of a design with even parity read from the other end of
+/// its horseshoe.  The junction with the adjacent horseshoe becomes the junction with the mirror
+/// image, so `final_angle`, as recovered by `report::recover_angles`, gives the first angle, and
+/// the first of `angles` gives the final angle of the reversed design, both offset by half a
+/// revolution.  The remaining angles are unchanged, but in reverse order.
+pub fn reversed_angles(angles: &[i32], final_angle: f64, num_angles: u16) -> Vec<i32> {
+    let mut reversed = vec![final_angle.round() as i32 + num_angles as i32 / 2];
+    reversed.extend(angles[1..].iter().rev());
+    canonical_angles(&reversed, num_angles)
+}
+
+/// Whether a design matches `mirrored`, the angles of its mirror image in canonical form, when
+/// read from either end of its horseshoe.  Only the first end is tried if the final angle is
+/// unknown.
+fn matches_mirror(
+    angles: &[i32],
+    final_angle: Option<f64>,
+    mirrored: &[i32],
+    num_angles: u16,
+    parity: JointsParity,
+) -> bool {
+    if canonical_angles(angles, num_angles) == mirrored {
+        return true;
+    }
+    // The other end of an odd horseshoe is a junction between two joints rather than a joint
+    // straddling the axis of symmetry, so reading from it doesn't give a design with odd parity
+    match (parity, final_angle) {
+        (JointsParity::Even, Some(final_angle)) => {
+            reversed_angles(angles, final_angle, num_angles) == mirrored
+        }
+        _ => false,
+    }
+}
+
+/// Reflect an isometry through the xy plane, in both its input and its output coordinate systems,
+/// giving a proper isometry again.  The xy plane of a joint's coordinate system contains both of
+/// its legs, so the reflection maps a joint onto a joint.
+fn reflect(iso: &Isometry3<f64>) -> Isometry3<f64> {
+    let t = iso.translation.vector;
+    let q = iso.rotation.quaternion();
+    // The axis of rotation is a pseudovector, so its component along z is kept
+    Isometry3::from_parts(
+        Translation3::new(t.x, t.y, -t.z),
+        UnitQuaternion::new_unchecked(Quaternion::new(q.w, -q.i, -q.j, q.k)),
+    )
+}
+
+impl KnotReport {
+    /// The mirror image of the knot.  The placement of the chain is left to be solved again, as by
+    /// `report::complete_report`, since the mirrored angles are in canonical form.
+    pub fn mirror(&self, num_angles: u16) -> KnotReport {
+        KnotReport {
+            angles: self
+                .angles
+                .iter()
+                .map(|&angle| mirror_angle(angle, num_angles))
+                .collect(),
+            final_angle: self.final_angle.map(|angle| {
+                let num_angles = num_angles as f64;
+                ((num_angles - angle) % num_angles + num_angles) % num_angles
+            }),
+            angle_parity: mirror_angle(self.angle_parity, num_angles),
+            symmetry_adjust: None,
+            // Costs don't depend on handedness
+            costs: self.costs,
+            total_cost: self.total_cost,
+            // Nor do the invariants computed, which can't tell a knot from its mirror image
+            invariants: self.invariants.clone(),
+            penetration: self.penetration,
+        }
+    }
+
+    /// Whether the knot is its own mirror image, with the same angles in canonical form when read
+    /// from one end of its horseshoe or the other.
+    pub fn is_amphichiral(&self, num_angles: u16, parity: JointsParity) -> bool {
+        matches_mirror(
+            &self.angles,
+            self.final_angle,
+            &canonical_angles(&self.mirror(num_angles).angles, num_angles),
+            num_angles,
+            parity,
+        )
+    }
+}
+
+impl KnotReports {
+    /// The mirror images of all the knots.
+    pub fn mirror(&self) -> KnotReports {
+        let num_angles = self.num_angles.unwrap_or(defaults::NUM_ANGLES);
+        KnotReports {
+            knots: self
+                .knots
+                .iter()
+                .map(|knot| knot.mirror(num_angles))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl KnotGeometry {
+    /// The mirror image of the knot, with every joint reflected through the xy plane.
+    pub fn mirror(&self) -> KnotGeometry {
+        let reflect_all = |transforms: &[Transform]| {
+            transforms
+                .iter()
+                .map(|transform| Transform::from_isometry(reflect(&transform.to_isometry())))
+                .collect()
+        };
+        KnotGeometry {
+            symmetries: reflect_all(&self.symmetries),
+            transforms: reflect_all(&self.transforms),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the knot is its own mirror image, with the same discrete angles in canonical form
+    /// when read from one end of its horseshoe or the other.
+    pub fn is_amphichiral(&self) -> bool {
+        let recovered = self.discrete_angles();
+        matches_mirror(
+            &recovered.angles,
+            Some(recovered.final_angle),
+            &canonical_angles(&self.mirror().discrete_angles().angles, self.num_angles),
+            self.num_angles,
+            self.parity,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chirality::*;
+
+    use std::f64::consts::PI;
+
+    use nalgebra::{Point3, UnitQuaternion, Vector3};
+
+    use report::fixtures::{first_geometry, geometry, reports};
+    use report::{recover_angles, JointsParity};
+
+    #[test]
+    fn mirror_angles() {
+        assert_eq!(mirror_angle(0, 16), 0);
+        assert_eq!(mirror_angle(3, 16), 13);
+        assert_eq!(mirror_angle(8, 16), 8);
+        assert_eq!(mirror_angle(-3, 16), 3);
+        assert_eq!(canonical_angles(&[-1, 16, 17], 16), vec![15, 0, 1]);
+
+        let knot = &reports(JointsParity::Even, vec![5, 0, 14, 15, 2]).knots[0];
+        assert_eq!(knot.mirror(16).angles, vec![11, 0, 2, 1, 14]);
+        assert_eq!(knot.mirror(16).mirror(16).angles, knot.angles);
+        assert!(!knot.is_amphichiral(16, JointsParity::Even));
+        assert!(
+            reports(JointsParity::Odd, vec![0, 8, 8, 0]).knots[0]
+                .is_amphichiral(16, JointsParity::Odd)
+        );
+
+        // The mirror image of this knot is the knot read from the other end of its horseshoe
+        let mut knot = reports(JointsParity::Even, vec![2, 3, 13]).knots[0].clone();
+        knot.final_angle = Some(6.0);
+        assert_eq!(reversed_angles(&knot.angles, 6.0, 16), vec![14, 13, 3]);
+        assert!(knot.is_amphichiral(16, JointsParity::Even));
+        assert!(!knot.is_amphichiral(16, JointsParity::Odd));
+        knot.final_angle = None;
+        assert!(!knot.is_amphichiral(16, JointsParity::Even));
+    }
+
+    #[test]
+    fn reversed_reading() {
+        let geometry = geometry(JointsParity::Even, vec![3, 7, 1, 12, 9]);
+        let spec = geometry.joint_spec;
+        let symms = geometry.symmetry_isometries();
+
+        // Swap the legs of every joint with a half turn about the axis bisecting them
+        let half_turn = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI);
+        let swap = spec.origin_to_symmetric().inverse() * half_turn * spec.origin_to_symmetric();
+        let reversed = geometry
+            .joints()
+            .iter()
+            .rev()
+            .map(|&joint| joint * swap)
+            .collect::<Vec<_>>();
+
+        let recovered = geometry.discrete_angles();
+        let recovered_reversed = recover_angles(
+            &spec,
+            geometry.num_angles,
+            &geometry.cost_params,
+            JointsParity::Even,
+            &reversed,
+            &symms[3],
+            &symms[1],
+        );
+        assert_eq!(
+            recovered_reversed.angles,
+            reversed_angles(&recovered.angles, recovered.final_angle, 16)
+        );
+        assert_eq!(
+            reversed_angles(
+                &recovered_reversed.angles,
+                recovered_reversed.final_angle,
+                16
+            ),
+            vec![3, 7, 1, 12, 9]
+        );
+    }
+
+    #[test]
+    fn mirror_reports() {
+        for &(parity, ref angles) in &[
+            (JointsParity::Even, vec![5, 0, 14, 15, 2]),
+            (JointsParity::Odd, vec![4, 12, 7, 9]),
+        ] {
+            let original = reports(parity, angles.clone());
+            let descriptors = first_geometry(original.clone()).descriptors();
+            let mirrored = first_geometry(original.mirror()).descriptors();
+            assert_relative_eq!(mirrored.writhe, -descriptors.writhe, epsilon = 1e-6);
+            assert_relative_eq!(mirrored.length, descriptors.length, epsilon = 1e-6);
+            assert_relative_eq!(
+                mirrored.average_crossing_number,
+                descriptors.average_crossing_number,
+                epsilon = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn mirror_geometry() {
+        let original = first_geometry(reports(JointsParity::Even, vec![5, 0, 14, 15, 2]));
+        let mirrored = original.mirror();
+
+        let reflected = original
+            .polyline()
+            .iter()
+            .map(|p| Point3::new(p.x, p.y, -p.z))
+            .collect::<Vec<_>>();
+        for (a, b) in mirrored.polyline().iter().zip(&reflected) {
+            assert_relative_eq!(a, b, epsilon = 1e-9);
+        }
+        assert_relative_eq!(
+            mirrored.descriptors().writhe,
+            -original.descriptors().writhe,
+            epsilon = 1e-6
+        );
+
+        assert_eq!(mirrored.discrete_angles().angles, vec![11, 0, 2, 1, 14]);
+        assert_eq!(mirrored.symmetry_skip(), original.symmetry_skip());
+        assert!(!original.is_amphichiral());
+    }
+}
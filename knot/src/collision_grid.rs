@@ -26,16 +26,30 @@ pub trait CheckCollision<T>: BoundedCollider {
     fn check_collision(&self, other: &T) -> Option<Self::CollisionData>;
 }
 
+/// A collection of colliders which can be tested for collisions against other colliders.
+/// Implemented both by the spatially partitioned `CollisionGrid` in this module and by the
+/// exhaustive one in `collision_grid_trivial`, so that either may be selected at runtime.
+pub trait CollisionIndex<T> {
+    fn add(&mut self, collider: &T)
+    where
+        T: Clone + BoundedCollider;
+
+    fn collisions<U: CheckCollision<T>>(&self, test_collider: &U) -> Vec<U::CollisionData>;
+}
+
 #[derive(Clone, Debug)]
 pub struct CollisionGrid<T> {
     cell_size: f64,
-    cells: HashMap<Point3<i32>, Vec<T>>,
+    colliders: Vec<T>,
+    // Indices into `colliders`.  A collider spanning several cells is listed in each of them.
+    cells: HashMap<Point3<i32>, Vec<usize>>,
 }
 
 impl<T> CollisionGrid<T> {
     pub fn new(cell_size: f64) -> Self {
         CollisionGrid {
             cell_size,
+            colliders: Vec::new(),
             cells: HashMap::new(),
         }
     }
@@ -47,12 +61,16 @@ impl<T> CollisionGrid<T> {
     fn bounds_indices(&self, bounds: Bounds) -> RangeInclusive<i32> {
         self.coord_index(bounds.min)..=self.coord_index(bounds.max)
     }
+}
 
-    pub fn add(&mut self, collider: &T)
+impl<T> CollisionIndex<T> for CollisionGrid<T> {
+    fn add(&mut self, collider: &T)
     where
         T: Clone + BoundedCollider,
     {
         let bounding_box = collider.bounding_box();
+        let index = self.colliders.len();
+        self.colliders.push(collider.clone());
 
         for cell_x in self.bounds_indices(bounding_box.x) {
             for cell_y in self.bounds_indices(bounding_box.y) {
@@ -60,31 +78,35 @@ impl<T> CollisionGrid<T> {
                     self.cells
                         .entry(Point3::new(cell_x, cell_y, cell_z))
                         .or_insert_with(|| Vec::new())
-                        .push(collider.clone());
+                        .push(index);
                 }
             }
         }
     }
 
-    pub fn collisions<U: CheckCollision<T>>(&self, test_collider: &U) -> Vec<U::CollisionData> {
-        let mut results = Vec::new();
-
+    fn collisions<U: CheckCollision<T>>(&self, test_collider: &U) -> Vec<U::CollisionData> {
         let bounding_box = test_collider.bounding_box();
 
+        let mut candidates = Vec::new();
         for cell_x in self.bounds_indices(bounding_box.x) {
             for cell_y in self.bounds_indices(bounding_box.y) {
                 for cell_z in self.bounds_indices(bounding_box.z) {
                     if let Some(cell) = self.cells.get(&Point3::new(cell_x, cell_y, cell_z)) {
-                        for collider in cell {
-                            if let Some(collision) = test_collider.check_collision(collider) {
-                                results.push(collision);
-                            }
-                        }
+                        candidates.extend_from_slice(cell);
                     }
                 }
             }
         }
 
-        results
+        // Colliders sharing several cells with the test collider must only be tested once.  Sorting
+        // also means collisions are reported in the order the colliders were added, matching
+        // `collision_grid_trivial`.
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        candidates
+            .into_iter()
+            .filter_map(|index| test_collider.check_collision(&self.colliders[index]))
+            .collect()
     }
 }
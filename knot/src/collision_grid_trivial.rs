@@ -1,29 +1,8 @@
-// Designed for API compatibility with collision_grid, so that imports can be easily swapped out to
-// experiment with the effects of spatial partitioning optimization.  May be worth using true
-// conditional compilation for this at some point.
+// Designed for API compatibility with collision_grid, so that the two can be selected between at
+// runtime to experiment with the effects of spatial partitioning optimization.  The collider traits
+// are shared, so any collider usable with one grid is usable with the other.
 
-#[derive(Clone, Copy, Debug)]
-pub struct Bounds {
-    pub min: f64,
-    pub max: f64,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct BoundingBox {
-    pub x: Bounds,
-    pub y: Bounds,
-    pub z: Bounds,
-}
-
-pub trait BoundedCollider {
-    fn bounding_box(&self) -> BoundingBox;
-}
-
-pub trait CheckCollision<T>: BoundedCollider {
-    type CollisionData;
-
-    fn check_collision(&self, other: &T) -> Option<Self::CollisionData>;
-}
+pub use collision_grid::{BoundedCollider, BoundingBox, Bounds, CheckCollision, CollisionIndex};
 
 #[derive(Clone, Debug)]
 pub struct CollisionGrid<T> {
@@ -36,15 +15,17 @@ impl<T> CollisionGrid<T> {
             colliders: Vec::new(),
         }
     }
+}
 
-    pub fn add(&mut self, collider: &T)
+impl<T> CollisionIndex<T> for CollisionGrid<T> {
+    fn add(&mut self, collider: &T)
     where
         T: Clone + BoundedCollider,
     {
         self.colliders.push(collider.clone());
     }
 
-    pub fn collisions<U: CheckCollision<T>>(&self, test_collider: &U) -> Vec<U::CollisionData> {
+    fn collisions<U: CheckCollision<T>>(&self, test_collider: &U) -> Vec<U::CollisionData> {
         let mut results = Vec::new();
 
         for collider in &self.colliders {
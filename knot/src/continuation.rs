@@ -0,0 +1,226 @@
+use convergence::{optimize_until_converged, OptimizationHistory, StopReason, StoppingCriteria};
+use optimize_tools::{DivergenceError, RepulsionChain};
+use report::JointsParity;
+
+/// A schedule for gradually imposing the locking cost on a chain, so that its joints can settle
+/// into valid angles one at a time rather than all being forced to the nearest valid angle at once.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ContinuationSchedule {
+    /// The locking weight during the first stage.
+    pub initial_locking_weight: f64,
+
+    /// The locking weight is multiplied by `growth` after every stage until it reaches this value.
+    pub final_locking_weight: f64,
+
+    pub growth: f64,
+
+    /// The factor by which the 'return to initial' weight is multiplied after every stage.  A value
+    /// of 1 leaves it unchanged.
+    pub return_to_initial_decay: f64,
+
+    /// A joint counts as snapped once its continuous angle is within this distance of an integer.
+    pub snap_tolerance: f64,
+
+    pub max_stages: u32,
+
+    /// When to stop optimizing within each stage.
+    pub stage: StoppingCriteria,
+}
+
+/// The stage at which the angle of a joint settled on a valid value.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Snap {
+    pub stage: u32,
+    pub locking_weight: f64,
+    pub angle: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StageReport {
+    pub locking_weight: f64,
+    pub return_to_initial_weight: f64,
+
+    /// The largest distance from a continuous angle to the nearest integer at the end of the stage.
+    pub max_rounding_error: f64,
+
+    pub history: OptimizationHistory,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContinuationReport {
+    pub stages: Vec<StageReport>,
+
+    /// For each angle recovered by `Chain::discrete_angles`, the stage from which on it remained
+    /// snapped, if it was snapped at the end.
+    pub snaps: Vec<Option<Snap>>,
+
+    /// Whether every angle was snapped at the end, with the locking weight at its final value.
+    pub converged: bool,
+}
+
+/// Optimize the chain through the stages of the schedule, applying `step` once per iteration as in
+/// `optimize_until_converged`.  The chain is left with the weights of the last stage.  If a stage
+/// diverges, it is the last.
+pub fn continuation<F>(
+    chain: &mut RepulsionChain,
+    schedule: &ContinuationSchedule,
+    parity: JointsParity,
+    mut step: F,
+) -> ContinuationReport
+where
+    F: FnMut(&mut RepulsionChain) -> Result<(), DivergenceError>,
+{
+    let mut stages = Vec::new();
+    let mut snaps: Vec<Option<Snap>> = Vec::new();
+    let mut locking_weight = schedule.initial_locking_weight;
+    let mut converged = false;
+
+    for stage in 0..schedule.max_stages {
+        if stage > 0 {
+            locking_weight = (locking_weight * schedule.growth).min(schedule.final_locking_weight);
+            chain.return_to_initial_weight *= schedule.return_to_initial_decay;
+        }
+        chain.cost_params.locking_weight = locking_weight;
+        let history = optimize_until_converged(chain, &schedule.stage, &mut step);
+
+        let recovered = chain.discrete_angles(parity);
+        snaps.resize(recovered.angles.len(), None);
+        for (i, (&angle, &error)) in recovered
+            .angles
+            .iter()
+            .zip(&recovered.rounding_errors)
+            .enumerate()
+        {
+            let snapped = error.abs() <= schedule.snap_tolerance;
+            snaps[i] = match snaps[i] {
+                Some(snap) if snapped && snap.angle == angle => Some(snap),
+                _ if snapped => Some(Snap {
+                    stage,
+                    locking_weight,
+                    angle,
+                }),
+                _ => None,
+            };
+        }
+
+        let diverged = match history.stop_reason {
+            StopReason::Diverged(_) => true,
+            _ => false,
+        };
+        let max_rounding_error = recovered
+            .rounding_errors
+            .iter()
+            .fold(0.0, |max, error| error.abs().max(max));
+        stages.push(StageReport {
+            locking_weight,
+            return_to_initial_weight: chain.return_to_initial_weight,
+            max_rounding_error,
+            history,
+        });
+
+        if diverged {
+            break;
+        }
+        let at_final_weight = locking_weight >= schedule.final_locking_weight;
+        if at_final_weight && snaps.iter().all(Option::is_some) {
+            converged = true;
+            break;
+        }
+    }
+
+    ContinuationReport {
+        stages,
+        snaps,
+        converged,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use continuation::*;
+
+    use defaults::continuous_optimization::{
+        CONTINUOUS_PARAMS, DIVERGENCE_GUARD, LOCKING_CONTINUATION,
+    };
+    use geometries::curve_9_40;
+
+    #[test]
+    fn ramps_locking_weight() {
+        let mut chain = curve_9_40::repulsion_chain(CONTINUOUS_PARAMS);
+        let schedule = ContinuationSchedule {
+            max_stages: 12,
+            stage: StoppingCriteria {
+                max_iterations: 300,
+                check_interval: 50,
+                ..LOCKING_CONTINUATION.stage
+            },
+            ..LOCKING_CONTINUATION
+        };
+        let report = continuation(&mut chain, &schedule, JointsParity::Even, |chain| {
+            chain.try_adaptive_optimize(&[2.0, 1.0, 0.5], 0.5, &DIVERGENCE_GUARD)?;
+            chain.return_to_initial();
+            Ok(())
+        });
+
+        assert!(report.stages.len() <= schedule.max_stages as usize);
+        assert_relative_eq!(report.stages[0].locking_weight, schedule.initial_locking_weight);
+        for pair in report.stages.windows(2) {
+            assert!(pair[1].locking_weight >= pair[0].locking_weight);
+            assert!(pair[1].locking_weight <= schedule.final_locking_weight);
+            assert_relative_eq!(
+                pair[1].return_to_initial_weight,
+                pair[0].return_to_initial_weight * schedule.return_to_initial_decay
+            );
+        }
+        let last = report.stages.last().unwrap();
+        assert_relative_eq!(chain.cost_params.locking_weight, last.locking_weight);
+        assert_eq!(chain.return_to_initial_weight, last.return_to_initial_weight);
+
+        let recovered = chain.discrete_angles(JointsParity::Even);
+        assert_eq!(report.snaps.len(), recovered.angles.len());
+        for (snap, (&angle, &error)) in report
+            .snaps
+            .iter()
+            .zip(recovered.angles.iter().zip(&recovered.rounding_errors))
+        {
+            match snap {
+                Some(snap) => {
+                    assert_eq!(snap.angle, angle);
+                    assert!(error.abs() <= schedule.snap_tolerance);
+                    assert!((snap.stage as usize) < report.stages.len());
+                }
+                None => assert!(error.abs() > schedule.snap_tolerance),
+            }
+        }
+        assert_eq!(
+            report.converged,
+            last.locking_weight >= schedule.final_locking_weight
+                && report.snaps.iter().all(Option::is_some)
+        );
+    }
+
+    #[test]
+    fn keeps_last_stage_weights() {
+        // Too few stages to reach the final locking weight
+        let mut chain = curve_9_40::repulsion_chain(CONTINUOUS_PARAMS);
+        let schedule = ContinuationSchedule {
+            max_stages: 2,
+            stage: StoppingCriteria {
+                max_iterations: 10,
+                check_interval: 10,
+                ..LOCKING_CONTINUATION.stage
+            },
+            ..LOCKING_CONTINUATION
+        };
+        let report = continuation(&mut chain, &schedule, JointsParity::Even, |chain| {
+            chain.try_adaptive_optimize(&[2.0, 1.0, 0.5], 0.5, &DIVERGENCE_GUARD)?;
+            Ok(())
+        });
+
+        assert!(!report.converged);
+        assert_eq!(report.stages.len(), 2);
+        let last = report.stages.last().unwrap();
+        assert_eq!(chain.cost_params.locking_weight, last.locking_weight);
+        assert_eq!(chain.return_to_initial_weight, last.return_to_initial_weight);
+    }
+}
@@ -0,0 +1,290 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use lbfgs::Objective;
+use optimize_tools::{DivergenceError, RepulsionChain};
+
+/// Conditions under which to stop an iterative optimization of a chain.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StoppingCriteria {
+    pub max_iterations: u32,
+
+    /// The number of iterations between checks of the other criteria.  A record is added to the
+    /// history at each check.
+    pub check_interval: u32,
+
+    /// A check counts as stalled if the cost decreased by less than this fraction of its magnitude
+    /// since the previous check.
+    pub relative_tolerance: f64,
+
+    /// Stop after this many consecutive stalled checks.
+    pub patience: u32,
+
+    /// Stop once the norm of the gradient of the connection cost falls below this value.  The
+    /// gradient is measured in the same units as in `Chain::lbfgs_optimize`.
+    pub gradient_tolerance: f64,
+
+    /// Stop once this many seconds have passed, if given.
+    pub time_budget: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    CostConverged,
+    GradientConverged,
+    TimeBudget,
+    MaxIterations,
+
+    /// A step failed to recover from non-finite values.  The chain is left as it was before the
+    /// failed step, and the last record describes that state.
+    Diverged(DivergenceError),
+}
+
+/// The state of the chain at one check.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub iteration: u32,
+
+    /// Seconds since the start of the optimization.
+    pub elapsed: f64,
+
+    pub cost: f64,
+
+    /// The unweighted components of the connection cost, as in `Chain::cost_components`.
+    pub dist: f64,
+    pub axis: f64,
+    pub locking: f64,
+
+    /// The total magnitude of the repulsive forces on the chain, or of the weighted gradient of the
+    /// Möbius energy if it takes their place.  See `RepulsionChain::repulsion_magnitude`.
+    pub repulsion: f64,
+
+    pub gradient_norm: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OptimizationHistory {
+    /// A record before the first iteration, and one at every check after it.
+    pub records: Vec<HistoryRecord>,
+    pub stop_reason: StopReason,
+}
+
+impl OptimizationHistory {
+    pub fn final_cost(&self) -> f64 {
+        self.records.last().unwrap().cost
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.records.last().unwrap().iteration
+    }
+
+    /// Write the history as CSV, with a header row.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "iteration,elapsed,cost,dist,axis,locking,repulsion,gradient_norm"
+        )?;
+        for record in &self.records {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                record.iteration,
+                record.elapsed,
+                record.cost,
+                record.dist,
+                record.axis,
+                record.locking,
+                record.repulsion,
+                record.gradient_norm,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn record(chain: &mut RepulsionChain, iteration: u32, start: &Instant) -> HistoryRecord {
+    let elapsed = start.elapsed();
+    let components = chain.cost_components();
+    let (_, gradient) = chain.chain.cost_and_gradient();
+    HistoryRecord {
+        iteration,
+        elapsed: elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9,
+        cost: chain.total_cost(),
+        dist: components.dist,
+        axis: components.axis,
+        locking: components.locking,
+        repulsion: chain.repulsion_magnitude(),
+        gradient_norm: gradient.iter().map(|g| g * g).sum::<f64>().sqrt(),
+    }
+}
+
+/// Repeatedly apply `step` to the chain until one of the stopping criteria is met or it fails,
+/// recording the history of the optimization.
+pub fn optimize_until_converged<F>(
+    chain: &mut RepulsionChain,
+    criteria: &StoppingCriteria,
+    mut step: F,
+) -> OptimizationHistory
+where
+    F: FnMut(&mut RepulsionChain) -> Result<(), DivergenceError>,
+{
+    let start = Instant::now();
+    let mut records = vec![record(chain, 0, &start)];
+    let mut stalled = 0;
+
+    let stop_reason = loop {
+        let last = *records.last().unwrap();
+        if last.gradient_norm <= criteria.gradient_tolerance {
+            break StopReason::GradientConverged;
+        }
+        if last.iteration >= criteria.max_iterations {
+            break StopReason::MaxIterations;
+        }
+        if let Some(budget) = criteria.time_budget {
+            if last.elapsed >= budget {
+                break StopReason::TimeBudget;
+            }
+        }
+
+        let batch = criteria
+            .check_interval
+            .max(1)
+            .min(criteria.max_iterations - last.iteration);
+        let mut completed = 0;
+        let mut failure = None;
+        while completed < batch {
+            if let Err(error) = step(chain) {
+                failure = Some(error);
+                break;
+            }
+            completed += 1;
+        }
+
+        let curr = record(chain, last.iteration + completed, &start);
+        records.push(curr);
+        if let Some(error) = failure {
+            break StopReason::Diverged(error);
+        }
+
+        if last.cost - curr.cost <= criteria.relative_tolerance * last.cost.abs() {
+            stalled += 1;
+        } else {
+            stalled = 0;
+        }
+        if stalled >= criteria.patience {
+            break StopReason::CostConverged;
+        }
+    };
+
+    OptimizationHistory {
+        records,
+        stop_reason,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use convergence::*;
+
+    use defaults::continuous_optimization::{CONVERGENCE, COST_PARAMS, DIVERGENCE_GUARD, RATE};
+    use geometries::curve_9_40;
+    use optimize_tools::NonFinite;
+
+    #[test]
+    fn stops_on_plateau() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        let criteria = StoppingCriteria {
+            max_iterations: 2_000,
+            check_interval: 50,
+            relative_tolerance: 1e-2,
+            ..CONVERGENCE
+        };
+        let history = optimize_until_converged(&mut chain, &criteria, |chain| {
+            chain.try_adaptive_optimize(&[2.0, 1.0, 0.5], 0.5, &DIVERGENCE_GUARD)?;
+            Ok(())
+        });
+
+        assert_eq!(history.stop_reason, StopReason::CostConverged);
+        assert!(history.iterations() < criteria.max_iterations);
+        assert!(history.final_cost() < history.records[0].cost);
+        assert_relative_eq!(history.final_cost(), chain.total_cost());
+        for pair in history.records.windows(2) {
+            assert_eq!(pair[1].iteration, pair[0].iteration + criteria.check_interval);
+        }
+    }
+
+    #[test]
+    fn stops_at_max_iterations() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        let criteria = StoppingCriteria {
+            max_iterations: 25,
+            check_interval: 10,
+            ..CONVERGENCE
+        };
+        let mut steps = 0;
+        let history = optimize_until_converged(&mut chain, &criteria, |chain| {
+            chain.optimize();
+            chain.repulse();
+            steps += 1;
+            Ok(())
+        });
+
+        assert_eq!(history.stop_reason, StopReason::MaxIterations);
+        assert_eq!(steps, 25);
+        let iterations = history.records.iter().map(|r| r.iteration).collect::<Vec<_>>();
+        assert_eq!(iterations, vec![0, 10, 20, 25]);
+        assert!(history.records.iter().all(|r| r.repulsion > 0.0));
+
+        let mut csv = Vec::new();
+        history.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), history.records.len() + 1);
+        assert_eq!(
+            lines[0],
+            "iteration,elapsed,cost,dist,axis,locking,repulsion,gradient_norm"
+        );
+        assert!(lines[4].starts_with("25,"));
+        assert_eq!(lines[4].split(',').count(), 8);
+    }
+
+    #[test]
+    fn records_weighted_energy() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.energy_weight = 0.01;
+        let criteria = StoppingCriteria {
+            max_iterations: 5,
+            check_interval: 5,
+            ..CONVERGENCE
+        };
+        let history = optimize_until_converged(&mut chain, &criteria, |chain| {
+            chain.optimize();
+            chain.repulse();
+            Ok(())
+        });
+        assert!(history.records.iter().all(|r| r.repulsion > 0.0));
+    }
+
+    #[test]
+    fn stops_on_divergence() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        let error = DivergenceError {
+            cause: NonFinite::Cost,
+            attempts: 1,
+            descent_rate: RATE,
+        };
+        let mut steps = 0;
+        let history = optimize_until_converged(&mut chain, &CONVERGENCE, |chain| {
+            if steps == 15 {
+                return Err(error);
+            }
+            chain.optimize();
+            steps += 1;
+            Ok(())
+        });
+
+        assert_eq!(history.stop_reason, StopReason::Diverged(error));
+        assert_eq!(history.iterations(), 15);
+        assert!(history.final_cost().is_finite());
+    }
+}
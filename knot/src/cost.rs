@@ -1,8 +1,9 @@
+use std::f64::consts::PI;
 use std::f64::INFINITY;
+use std::ops::{Add, Mul};
 
-use approx_locking_angle::locking_angle_aligned;
+use approx_locking_angle::{swing_twist_aligned, twist_gradient_aligned};
 
-use alga::general::SubsetOf;
 use nalgebra::{Isometry3, Quaternion, UnitQuaternion, Vector3};
 
 /// Configurable parameters for determinng when certain terms in the cost function should be
@@ -78,16 +79,14 @@ pub fn costs_aligned(
 
     // Axis misalignment cost
     let (axis_cost, locking_cost) = if dist_cost < thresholds.dist_for_axis {
-        // TODO: Optimize identically-zero terms in rotation calculation.
-        let axis_0 = trans_0 * Vector3::y_axis().to_superset();
-        let axis_1 = trans_1 * Vector3::y_axis().to_superset();
+        let swing_twist = swing_twist_aligned(trans_0, trans_1);
 
-        let axis_cost = 1.0 - axis_0.dot(&axis_1);
+        // One minus the dot product of the two y axes
+        let axis_cost = 1.0 - swing_twist.swing.cos();
 
         // Locking angle cost
         let locking_cost = if axis_cost < thresholds.axis_for_locking {
-            // TODO: Avoid redundant recomputation of axes
-            let locking_angle = locking_angle_aligned(num_angles, trans_0, trans_1);
+            let locking_angle = swing_twist.twist / (2.0 * PI) * (num_angles as f64);
             let locking_interval_frac = locking_angle % 1.0;
 
             // From the observation that x^2 * (1 - x)^2 has local minima at x = 0 and x = 1 and a
@@ -160,9 +159,356 @@ pub fn cost_opposing(
     cost_aligned(cost_params, num_angles, trans_0, &align(trans_1))
 }
 
+/// The rate of change of a connection cost as the second of its two coordinate systems is
+/// translated, or rotated about its own origin.  Rotations are measured as rotation vectors in
+/// world coordinates.  Moving the first coordinate system instead has exactly the opposite effect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostGradient {
+    pub translation: Vector3<f64>,
+    pub rotation: Vector3<f64>,
+}
+
+impl CostGradient {
+    pub fn zero() -> Self {
+        CostGradient {
+            translation: Vector3::zeros(),
+            rotation: Vector3::zeros(),
+        }
+    }
+}
+
+impl Add for CostGradient {
+    type Output = CostGradient;
+
+    fn add(self, other: CostGradient) -> CostGradient {
+        CostGradient {
+            translation: self.translation + other.translation,
+            rotation: self.rotation + other.rotation,
+        }
+    }
+}
+
+impl Mul<f64> for CostGradient {
+    type Output = CostGradient;
+
+    fn mul(self, factor: f64) -> CostGradient {
+        CostGradient {
+            translation: self.translation * factor,
+            rotation: self.rotation * factor,
+        }
+    }
+}
+
+/// A measure of how well two joints fit together.  `CostParams` implements the original model;
+/// `CostModel` assembles alternative forms of each term, so that models can be compared by how well
+/// they predict which knots are physically buildable.
+pub trait ConnectionCost {
+    /// Compute each term of the cost, given two coordinate systems rooted at the ends of the
+    /// joints, whose y-axes are supposed to face the **same** direction.
+    fn costs_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> Costs;
+
+    /// Combine the terms of the cost into a single number.
+    fn weigh(&self, costs: &Costs) -> f64;
+
+    /// Compute each term of the cost, given two coordinate systems whose y-axes are supposed to
+    /// face in **opposite** directions.
+    fn costs_opposing(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> Costs {
+        self.costs_aligned(num_angles, trans_0, &align(trans_1))
+    }
+
+    fn cost_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> f64 {
+        self.weigh(&self.costs_aligned(num_angles, trans_0, trans_1))
+    }
+
+    fn cost_opposing(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> f64 {
+        self.weigh(&self.costs_opposing(num_angles, trans_0, trans_1))
+    }
+
+    /// Compute the cost along with its gradient, given two coordinate systems whose y-axes are
+    /// supposed to face the **same** direction.
+    fn gradient_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> (f64, CostGradient);
+
+    /// Compute the cost along with its gradient, given two coordinate systems whose y-axes are
+    /// supposed to face in **opposite** directions.
+    fn gradient_opposing(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> (f64, CostGradient) {
+        // `align` rotates about the origin of `trans_1`, so it leaves the gradient unchanged.
+        self.gradient_aligned(num_angles, trans_0, &align(trans_1))
+    }
+}
+
+impl ConnectionCost for CostParams {
+    fn costs_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> Costs {
+        costs_aligned(&self.thresholds, num_angles, trans_0, trans_1)
+    }
+
+    fn weigh(&self, costs: &Costs) -> f64 {
+        costs.dist * self.dist_weight
+            + costs.axis * self.axis_weight
+            + costs.locking * self.locking_weight
+    }
+
+    fn gradient_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> (f64, CostGradient) {
+        CostModel::from(*self).gradient_aligned(num_angles, trans_0, trans_1)
+    }
+}
+
+/// The form of the distance term of a `CostModel`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DistancePenalty {
+    /// The squared euclidean distance, as in `CostParams`.
+    Squared,
+
+    /// The squared euclidean distance up to `delta`, beyond which it grows only linearly, so that a
+    /// single badly separated junction does not dominate the total cost.
+    Huber { delta: f64 },
+}
+
+/// The form of the locking term of a `CostModel`, as a function of the fractional part `x` of the
+/// locking angle.  Both forms vanish at valid angles and reach a maximum of 1 halfway between them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LockingPenalty {
+    /// `16 x^2 (1 - x)^2`, as in `CostParams`.
+    Polynomial,
+
+    /// `(1 - cos(2 pi x)) / 2`.
+    Cosine,
+}
+
+/// How a `CostModel` stops paying attention to axis and locking angle terms when the joints aren't
+/// anywhere close to connecting.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Gating {
+    /// Replace terms by their maxima above the thresholds, as in `CostParams`.
+    Hard(Thresholds),
+
+    /// Blend terms into their maxima with a logistic function centered at each threshold.  The
+    /// widths set how gradually the blend happens, in units of the quantity being compared against
+    /// the threshold.
+    Sigmoid {
+        thresholds: Thresholds,
+        dist_width: f64,
+        axis_width: f64,
+    },
+}
+
+/// A cost model assembled from interchangeable forms of each term.  With `Squared` distance,
+/// `Polynomial` locking and `Hard` gating it is equivalent to `CostParams`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CostModel {
+    pub dist_weight: f64,
+    pub axis_weight: f64,
+    pub locking_weight: f64,
+    pub distance: DistancePenalty,
+    pub locking: LockingPenalty,
+    pub gating: Gating,
+}
+
+/// The weight given to a term gated at `threshold`, for a value `x` of the quantity it is gated on.
+fn sigmoid_gate(x: f64, threshold: f64, width: f64) -> f64 {
+    (1.0 + ((x - threshold) / width).exp()).recip()
+}
+
+/// The rates of change of each term of a connection cost, in the sense of `CostGradient`.
+#[derive(Clone, Copy, Debug)]
+struct TermGradients {
+    dist: CostGradient,
+    axis: CostGradient,
+    locking: CostGradient,
+}
+
+/// The derivative of `sigmoid_gate` with respect to the gated quantity, given the gate's value.
+fn sigmoid_gate_slope(gate: f64, width: f64) -> f64 {
+    -gate * (1.0 - gate) / width
+}
+
+impl CostModel {
+    /// Compute each term of the cost along with its gradient.
+    fn terms(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> (Costs, TermGradients) {
+        let offset = trans_1.translation.vector - trans_0.translation.vector;
+        let dist_squ = offset.norm_squared();
+        let d_dist_squ = CostGradient {
+            translation: offset * 2.0,
+            rotation: Vector3::zeros(),
+        };
+        let (dist_cost, d_dist_cost) = match self.distance {
+            DistancePenalty::Squared => (dist_squ, d_dist_squ),
+            DistancePenalty::Huber { delta } => {
+                let dist = dist_squ.sqrt();
+                if dist <= delta {
+                    (dist_squ, d_dist_squ)
+                } else {
+                    (delta * (2.0 * dist - delta), d_dist_squ * (delta / dist))
+                }
+            }
+        };
+
+        let swing_twist = swing_twist_aligned(trans_0, trans_1);
+
+        // One minus the dot product of the two y axes
+        let raw_axis_cost = 1.0 - swing_twist.swing.cos();
+        let d_raw_axis_cost = CostGradient {
+            translation: Vector3::zeros(),
+            rotation: (trans_0.rotation * Vector3::y()).cross(&(trans_1.rotation * Vector3::y())),
+        };
+
+        let locking_scale = (num_angles as f64) / (2.0 * PI);
+        let locking_frac = (swing_twist.twist * locking_scale) % 1.0;
+        let (raw_locking_cost, locking_slope) = match self.locking {
+            LockingPenalty::Polynomial => (
+                16.0 * locking_frac * locking_frac * (1.0 - locking_frac) * (1.0 - locking_frac),
+                32.0 * locking_frac * (1.0 - locking_frac) * (1.0 - 2.0 * locking_frac),
+            ),
+            LockingPenalty::Cosine => (
+                0.5 * (1.0 - (2.0 * PI * locking_frac).cos()),
+                PI * (2.0 * PI * locking_frac).sin(),
+            ),
+        };
+        let d_raw_locking_cost = CostGradient {
+            translation: Vector3::zeros(),
+            rotation: twist_gradient_aligned(trans_0, trans_1) * (locking_scale * locking_slope),
+        };
+
+        let ((axis_gate, d_axis_gate), (locking_gate, d_locking_gate)) = match self.gating {
+            Gating::Hard(thresholds) => {
+                let (axis_gate, locking_gate) = if dist_squ >= thresholds.dist_for_axis {
+                    (0.0, 0.0)
+                } else if raw_axis_cost >= thresholds.axis_for_locking {
+                    (1.0, 0.0)
+                } else {
+                    (1.0, 1.0)
+                };
+                (
+                    (axis_gate, CostGradient::zero()),
+                    (locking_gate, CostGradient::zero()),
+                )
+            }
+            Gating::Sigmoid {
+                thresholds,
+                dist_width,
+                axis_width,
+            } => {
+                let dist_gate = sigmoid_gate(dist_squ, thresholds.dist_for_axis, dist_width);
+                let d_dist_gate = d_dist_squ * sigmoid_gate_slope(dist_gate, dist_width);
+                let axis_gate =
+                    sigmoid_gate(raw_axis_cost, thresholds.axis_for_locking, axis_width);
+                let d_axis_gate = d_raw_axis_cost * sigmoid_gate_slope(axis_gate, axis_width);
+                (
+                    (dist_gate, d_dist_gate),
+                    (
+                        dist_gate * axis_gate,
+                        d_dist_gate * axis_gate + d_axis_gate * dist_gate,
+                    ),
+                )
+            }
+        };
+
+        let costs = Costs {
+            dist: dist_cost,
+            axis: axis_gate * raw_axis_cost + (1.0 - axis_gate) * 2.0,
+            locking: locking_gate * raw_locking_cost + (1.0 - locking_gate),
+        };
+        let gradients = TermGradients {
+            dist: d_dist_cost,
+            axis: d_raw_axis_cost * axis_gate + d_axis_gate * (raw_axis_cost - 2.0),
+            locking: d_raw_locking_cost * locking_gate + d_locking_gate * (raw_locking_cost - 1.0),
+        };
+        (costs, gradients)
+    }
+}
+
+impl ConnectionCost for CostModel {
+    fn costs_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> Costs {
+        self.terms(num_angles, trans_0, trans_1).0
+    }
+
+    fn weigh(&self, costs: &Costs) -> f64 {
+        costs.dist * self.dist_weight
+            + costs.axis * self.axis_weight
+            + costs.locking * self.locking_weight
+    }
+
+    fn gradient_aligned(
+        &self,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> (f64, CostGradient) {
+        let (costs, gradients) = self.terms(num_angles, trans_0, trans_1);
+        let gradient = gradients.dist * self.dist_weight
+            + gradients.axis * self.axis_weight
+            + gradients.locking * self.locking_weight;
+        (self.weigh(&costs), gradient)
+    }
+}
+
+impl From<CostParams> for CostModel {
+    fn from(params: CostParams) -> Self {
+        CostModel {
+            dist_weight: params.dist_weight,
+            axis_weight: params.axis_weight,
+            locking_weight: params.locking_weight,
+            distance: DistancePenalty::Squared,
+            locking: LockingPenalty::Polynomial,
+            gating: Gating::Hard(params.thresholds),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use cost::*;
+
+    use alga::general::SubsetOf;
     use nalgebra::Translation3;
     use std::f64::consts::PI;
 
@@ -328,4 +674,121 @@ mod test {
 
         assert_symmetry(&thresholds, 1, &trans_0, &trans_2);
     }
+
+    fn sample_transforms() -> Vec<Isometry3<f64>> {
+        vec![
+            Isometry3::identity(),
+            Isometry3::from_parts(
+                Translation3::new(0.0, 0.5, 0.0),
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI / 6.0),
+            ),
+            Isometry3::from_parts(
+                Translation3::new(1.0, 1.5, -0.5),
+                UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.3)
+                    * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.1),
+            ),
+            (UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 2.0)).to_superset(),
+        ]
+    }
+
+    #[test]
+    fn model_matches_params() {
+        let params = CostParams {
+            dist_weight: 4.5,
+            axis_weight: 1.0,
+            locking_weight: 1.4,
+            thresholds: Thresholds {
+                dist_for_axis: 1.0,
+                axis_for_locking: 0.05,
+            },
+        };
+        let model = CostModel {
+            dist_weight: params.dist_weight,
+            axis_weight: params.axis_weight,
+            locking_weight: params.locking_weight,
+            distance: DistancePenalty::Squared,
+            locking: LockingPenalty::Polynomial,
+            gating: Gating::Hard(params.thresholds),
+        };
+
+        let transforms = sample_transforms();
+        for trans_0 in &transforms {
+            for trans_1 in &transforms {
+                let expected = params.costs_opposing(3, trans_0, trans_1);
+                let actual = model.costs_opposing(3, trans_0, trans_1);
+                assert_relative_eq!(actual.dist, expected.dist);
+                assert_relative_eq!(actual.axis, expected.axis);
+                assert_relative_eq!(actual.locking, expected.locking, epsilon = 1e-12);
+                assert_relative_eq!(
+                    model.cost_aligned(3, trans_0, trans_1),
+                    cost_aligned(&params, 3, trans_0, trans_1),
+                    epsilon = 1e-12
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn alternative_terms() {
+        let model = CostModel {
+            dist_weight: 1.0,
+            axis_weight: 1.0,
+            locking_weight: 1.0,
+            distance: DistancePenalty::Huber { delta: 1.0 },
+            locking: LockingPenalty::Cosine,
+            gating: Gating::Hard(NO_THRESHOLD),
+        };
+
+        let trans_0 = Isometry3::identity();
+        let near = Translation3::new(0.0, 0.5, 0.0).to_superset();
+        let far = Isometry3::from_parts(
+            Translation3::new(3.0, 0.0, 0.0),
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), PI / 4.0),
+        );
+
+        assert_relative_eq!(model.costs_aligned(4, &trans_0, &near).dist, 0.25);
+
+        let costs = model.costs_aligned(4, &trans_0, &far);
+        assert_relative_eq!(costs.dist, 5.0);
+        assert_relative_eq!(costs.axis, 0.0, epsilon = 1e-12);
+        // Halfway between valid angles
+        assert_relative_eq!(costs.locking, 1.0);
+
+        let costs = model.costs_aligned(8, &trans_0, &far);
+        assert_relative_eq!(costs.locking, 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn sigmoid_gating() {
+        let thresholds = Thresholds {
+            dist_for_axis: 1.0,
+            axis_for_locking: INFINITY,
+        };
+        let model = |dist_width| CostModel {
+            dist_weight: 1.0,
+            axis_weight: 1.0,
+            locking_weight: 1.0,
+            distance: DistancePenalty::Squared,
+            locking: LockingPenalty::Polynomial,
+            gating: Gating::Sigmoid {
+                thresholds,
+                dist_width,
+                axis_width: 1.0,
+            },
+        };
+
+        let trans_0 = Isometry3::identity();
+        let at_threshold = Translation3::new(1.0, 0.0, 0.0).to_superset();
+        let beyond = Translation3::new(3.0, 0.0, 0.0).to_superset();
+
+        // Exactly halfway between the true axis cost of zero and the maximum
+        assert_relative_eq!(model(0.1).costs_aligned(1, &trans_0, &at_threshold).axis, 1.0);
+
+        // Sharp sigmoids approach hard thresholds
+        assert_relative_eq!(
+            model(1e-3).costs_aligned(1, &trans_0, &beyond).axis,
+            costs_aligned(&thresholds, 1, &trans_0, &beyond).axis
+        );
+        assert!(model(1.0).costs_aligned(1, &trans_0, &beyond).axis < 2.0);
+    }
 }
@@ -2,9 +2,15 @@ use std::f64::consts::PI;
 use std::f64::INFINITY;
 use std::iter::once;
 
-use cost::{CostParams, Thresholds};
+use continuation::ContinuationSchedule;
+use convergence::StoppingCriteria;
+use cost::{CostModel, CostParams, DistancePenalty, Gating, LockingPenalty, Thresholds};
+use filter::{CollisionBackend, CollisionStrategy};
 use joint::JointSpec;
+use lbfgs::LbfgsParams;
+use optimize_tools::DivergenceGuard;
 use symmetry_adjust::{OptimizationParams, Vars};
+use validate::Tolerances;
 
 pub const COST_PARAMS: CostParams = CostParams {
     dist_weight: 4.5,
@@ -16,6 +22,24 @@ pub const COST_PARAMS: CostParams = CostParams {
     },
 };
 
+/// An alternative to `COST_PARAMS` with the same weights, but with every term shaped to vary
+/// smoothly: a Huber distance term, a cosine locking term and sigmoid thresholds.
+pub const SMOOTH_COST_MODEL: CostModel = CostModel {
+    dist_weight: 4.5,
+    axis_weight: 1.0,
+    locking_weight: 1.4,
+    distance: DistancePenalty::Huber { delta: 1.0 },
+    locking: LockingPenalty::Cosine,
+    gating: Gating::Sigmoid {
+        thresholds: Thresholds {
+            dist_for_axis: 4.0,
+            axis_for_locking: 0.2,
+        },
+        dist_width: 0.5,
+        axis_width: 0.02,
+    },
+};
+
 pub const OPTIMIZATION_PARAMS: OptimizationParams = OptimizationParams {
     radius_step: 0.01,
     radial_angle_step: 0.01,
@@ -40,6 +64,11 @@ pub fn initial_symmetry_adjusts() -> impl Iterator<Item = Vars> {
     }))
 }
 
+pub const COLLISION_STRATEGY: CollisionStrategy = CollisionStrategy {
+    backend: CollisionBackend::Exhaustive,
+    use_wedge: false,
+};
+
 pub const NUM_ANGLES: u16 = 16;
 
 pub const SYMMETRY_COUNT: u32 = 3;
@@ -67,6 +96,59 @@ pub mod curve_visualize {
     };
 }
 
+pub mod validation {
+    use super::*;
+
+    /// Default tolerances for judging whether a geometry can be physically assembled.
+    pub const TOLERANCES: Tolerances = Tolerances {
+        dist: 0.05,
+        axis: 0.001,
+        locking: 0.1,
+    };
+}
+
+pub mod topology {
+    /// The direction along which knots are projected to compute their invariants.  It is tilted
+    /// slightly from the axis of symmetry, so that symmetric copies of the chain are not seen
+    /// exactly edge on.
+    pub const PROJECTION_DIRECTION: [f64; 3] = [0.0123, 0.0271, 1.0];
+}
+
+pub mod diagram {
+    /// How far crossings are lifted above or below the plane when laying out a knot diagram, as a
+    /// fraction of the radius of the layout.
+    pub const LIFT: f64 = 0.15;
+
+    /// The radius of the layout of a knot diagram when it is followed by a chain of joints.
+    pub const SCALE: f64 = 12.0;
+
+    /// The arc length between consecutive joints placed along a curve.
+    pub const ARC_LENGTH_STEP: f64 = 2.7;
+}
+
+pub mod svg {
+    /// The width and height of each view in an SVG diagram, in pixels.
+    pub const PANEL_SIZE: f64 = 480.0;
+
+    /// The empty border around the knot in each view, in pixels.
+    pub const MARGIN: f64 = 24.0;
+
+    /// The views drawn when none are given, as azimuth and elevation in degrees: an oblique view,
+    /// then the view from above.
+    pub const VIEWS: [(f64, f64); 2] = [(-60.0, 30.0), (-90.0, 90.0)];
+
+    /// The width of each stroke, as a fraction of the radius of a joint.  Joints are too thick
+    /// relative to their length to draw at full width and still show the strokes behind them.
+    pub const STROKE_WIDTH: f64 = 0.4;
+
+    /// The width of the white outline on either side of each stroke, which breaks the strokes
+    /// behind it, as a fraction of the width of the stroke.
+    pub const HALO: f64 = 0.5;
+
+    /// The height of label text, in pixels.
+    pub const FONT_SIZE: f64 = 10.0;
+}
+
 pub mod continuous_optimization {
     use super::*;
 
@@ -98,6 +180,50 @@ pub mod continuous_optimization {
     /// Empirically-determined number of steps for gradient descent to stabilize
     pub const STEPS: u32 = 5_000;
 
+    /// When to stop gradient descent before `STEPS` steps, if the cost has stopped improving.
+    pub const CONVERGENCE: StoppingCriteria = StoppingCriteria {
+        max_iterations: STEPS,
+        check_interval: 250,
+        relative_tolerance: 1e-4,
+        patience: 3,
+        gradient_tolerance: 1e-8,
+        time_budget: None,
+    };
+
+    /// Ramps the locking weight up from almost nothing to that of `COST_PARAMS`, at the rate of the
+    /// interactive controls of `continuous_optimize`.
+    pub const LOCKING_CONTINUATION: ContinuationSchedule = ContinuationSchedule {
+        initial_locking_weight: 0.01,
+        final_locking_weight: 0.17,
+        growth: 1.5,
+        return_to_initial_decay: 0.7,
+        snap_tolerance: 0.05,
+        max_stages: 20,
+        stage: CONVERGENCE,
+    };
+
+    /// Whether to minimize the full cost of a chain with L-BFGS in place of `STEPS` steps of
+    /// gradient descent.  Repulsion enters as its potential energy, and returning to the initial
+    /// curve is skipped.
+    pub const LBFGS: bool = false;
+
+    pub const LBFGS_PARAMS: LbfgsParams = LbfgsParams {
+        memory: 10,
+        max_iterations: 5_000,
+        gradient_tolerance: 1e-8,
+        cost_tolerance: 1e-12,
+        initial_step: 0.01,
+        sufficient_decrease: 1e-4,
+        backtrack_ratio: 0.5,
+        max_backtracks: 40,
+    };
+
+    /// How the optimizers recover from steps which produce infinite or NaN values.
+    pub const DIVERGENCE_GUARD: DivergenceGuard = DivergenceGuard {
+        max_retries: 8,
+        rate_shrink: 0.5,
+    };
+
     pub const REPULSION: bool = true;
 
     pub const REPULSION_EXPONENT: i32 = 2;
@@ -106,6 +232,18 @@ pub mod continuous_optimization {
 
     pub const MAX_REPULSION_STRENGTH: f64 = 1.0; // TODO: Empirically determine best value
 
+    /// Repulsive forces weaker than this fraction of `MAX_REPULSION_STRENGTH` are neglected, so
+    /// that the rest can be found with a spatial hash grid.  Zero considers every pair of joints.
+    pub const REPULSION_CUTOFF_FRACTION: f64 = 0.0;
+
+    /// Whether to approximate the repulsion neglected by `REPULSION_CUTOFF_FRACTION` by aggregating
+    /// distant joints, rather than dropping it.
+    pub const REPULSION_FAR_FIELD: bool = false;
+
+    /// Weight of the Möbius energy in the objective, which takes the place of the forces applied by
+    /// `repulse` when nonzero.  Zero leaves repulsion entirely to `repulse`.
+    pub const MOBIUS_ENERGY_WEIGHT: f64 = 0.0;
+
     pub const CURVE_9_40_CHAIN_SIZE: usize = 8;
 
     pub const TREFOIL_CHAIN_SIZE: usize = 6;
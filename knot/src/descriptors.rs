@@ -0,0 +1,228 @@
+//! Numeric descriptors of the shape of a knot, for comparing different embeddings of the same knot.
+
+use std::f64::consts::PI;
+
+use nalgebra::{Point3, Vector3};
+
+use report::KnotGeometry;
+
+/// Descriptors of the shape of a closed polyline.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Descriptors {
+    /// The length of the polyline.
+    pub length: f64,
+
+    /// The Gauss integral of the polyline: the signed crossing number, averaged over every
+    /// direction of projection.
+    pub writhe: f64,
+
+    /// The number of crossings, averaged over every direction of projection.
+    pub average_crossing_number: f64,
+
+    /// The root mean square distance of the polyline from its center of mass, weighted by length.
+    pub radius_of_gyration: f64,
+
+    /// The size of the axis-aligned bounding box of the polyline, along x, y and z.
+    pub dimensions: [f64; 3],
+}
+
+impl Descriptors {
+    /// Describe a closed polyline, whose last point is joined back to the first.
+    pub fn new(points: &[Point3<f64>]) -> Descriptors {
+        let segments = segments(points);
+        let length = segments.iter().map(|&(a, b)| (b - a).norm()).sum::<f64>();
+
+        let mut writhe = 0.0;
+        let mut average_crossing_number = 0.0;
+        for i in 0..segments.len() {
+            // Adjacent segments are coplanar, so contribute nothing
+            for j in (i + 2)..segments.len() {
+                if i == 0 && j == segments.len() - 1 {
+                    continue;
+                }
+                let omega = solid_angle(segments[i], segments[j]);
+                writhe += omega;
+                average_crossing_number += omega.abs();
+            }
+        }
+
+        let center = segments
+            .iter()
+            .fold(Vector3::zeros(), |sum, &(a, b)| {
+                sum + (a.coords + b.coords) * (0.5 * (b - a).norm())
+            }) / length;
+        let second_moment = segments
+            .iter()
+            .map(|&(a, b)| {
+                let start = a.coords - center;
+                let step = b - a;
+                step.norm() * (start.norm_squared() + start.dot(&step) + step.norm_squared() / 3.0)
+            }).sum::<f64>();
+
+        let mut dimensions = [0.0; 3];
+        for axis in 0..3 {
+            let min = points.iter().map(|p| p[axis]).fold(::std::f64::INFINITY, f64::min);
+            let max = points.iter().map(|p| p[axis]).fold(::std::f64::NEG_INFINITY, f64::max);
+            dimensions[axis] = max - min;
+        }
+
+        Descriptors {
+            length,
+            // Each unordered pair of segments counts once for each order
+            writhe: writhe / (2.0 * PI),
+            average_crossing_number: average_crossing_number / (2.0 * PI),
+            radius_of_gyration: (second_moment / length).sqrt(),
+            dimensions,
+        }
+    }
+}
+
+/// The linking number of two disjoint closed polylines, as their Gauss integral.  It is an integer
+/// up to rounding error.
+pub fn linking_number(first: &[Point3<f64>], second: &[Point3<f64>]) -> f64 {
+    let second = segments(second);
+    segments(first)
+        .iter()
+        .map(|&a| second.iter().map(|&b| solid_angle(a, b)).sum::<f64>())
+        .sum::<f64>()
+        / (4.0 * PI)
+}
+
+/// The segments of a closed polyline, including the one joining the last point to the first.
+fn segments(points: &[Point3<f64>]) -> Vec<(Point3<f64>, Point3<f64>)> {
+    (0..points.len())
+        .map(|i| (points[i], points[(i + 1) % points.len()]))
+        .collect()
+}
+
+/// The signed solid angle of directions in which the projections of two segments cross, positive
+/// when the crossing is right-handed.  Over a closed curve, these sum to `4 * PI` times the writhe.
+/// See Klenin and Langowski, "Computation of writhe in modeling of supercoiled DNA" (2000).
+fn solid_angle(
+    (start_0, end_0): (Point3<f64>, Point3<f64>),
+    (start_1, end_1): (Point3<f64>, Point3<f64>),
+) -> f64 {
+    let r_00 = start_1 - start_0;
+    let r_01 = end_1 - start_0;
+    let r_10 = start_1 - end_0;
+    let r_11 = end_1 - end_0;
+    let normals = [
+        r_00.cross(&r_01),
+        r_01.cross(&r_11),
+        r_11.cross(&r_10),
+        r_10.cross(&r_00),
+    ];
+    if normals.iter().any(|normal| normal.norm() < 1e-12) {
+        // The segments are coplanar
+        return 0.0;
+    }
+    let normals = normals.iter().map(|normal| normal.normalize()).collect::<Vec<_>>();
+    let magnitude = (0..4)
+        .map(|i| normals[i].dot(&normals[(i + 1) % 4]).max(-1.0).min(1.0).asin())
+        .sum::<f64>();
+    let sign = (end_1 - start_1).cross(&(end_0 - start_0)).dot(&r_00);
+    magnitude * sign.signum()
+}
+
+impl KnotGeometry {
+    /// Descriptors of the closed centerline of the knot.
+    pub fn descriptors(&self) -> Descriptors {
+        Descriptors::new(&self.polyline())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use descriptors::*;
+
+    use topology::Diagram;
+
+    fn sample<F: Fn(f64) -> Point3<f64>>(count: usize, curve: F) -> Vec<Point3<f64>> {
+        (0..count)
+            .map(|i| curve(2.0 * PI * (i as f64) / (count as f64)))
+            .collect()
+    }
+
+    fn circle(radius: f64, center: Point3<f64>, normal: usize) -> Vec<Point3<f64>> {
+        sample(100, |t| {
+            let mut point = center;
+            point[(normal + 1) % 3] += radius * t.cos();
+            point[(normal + 2) % 3] += radius * t.sin();
+            point
+        })
+    }
+
+    #[test]
+    fn planar_circle() {
+        let descriptors = Descriptors::new(&circle(2.0, Point3::origin(), 2));
+        assert_eq!(descriptors.writhe, 0.0);
+        assert_eq!(descriptors.average_crossing_number, 0.0);
+        assert_relative_eq!(descriptors.length, 4.0 * PI, max_relative = 1e-3);
+        assert_relative_eq!(descriptors.radius_of_gyration, 2.0, max_relative = 1e-3);
+        assert_relative_eq!(descriptors.dimensions[0], 4.0, max_relative = 1e-9);
+        assert_relative_eq!(descriptors.dimensions[1], 4.0, max_relative = 1e-3);
+        assert_eq!(descriptors.dimensions[2], 0.0);
+    }
+
+    #[test]
+    fn trefoil_writhe() {
+        // Nearly flat, so the writhe is close to the sum of the signs of the crossings of its
+        // diagram
+        let trefoil = |height: f64| {
+            sample(300, move |t| {
+                Point3::new(
+                    t.sin() + 2.0 * (2.0 * t).sin(),
+                    t.cos() - 2.0 * (2.0 * t).cos(),
+                    -height * (3.0 * t).sin(),
+                )
+            })
+        };
+        let points = trefoil(0.05);
+        let diagram = Diagram::project(&points, &Vector3::z_axis());
+        let signs = diagram.crossings.iter().map(|crossing| crossing.sign).sum::<i32>();
+        assert_eq!(signs.abs(), 3);
+
+        let descriptors = Descriptors::new(&points);
+        assert_relative_eq!(descriptors.writhe, signs as f64, max_relative = 0.05);
+        assert_relative_eq!(descriptors.average_crossing_number, 3.0, max_relative = 0.05);
+
+        // Lifting the crossings apart spreads them over more directions
+        let descriptors = Descriptors::new(&trefoil(1.0));
+        assert!(descriptors.average_crossing_number > descriptors.writhe.abs());
+        assert!(descriptors.average_crossing_number > 3.0);
+
+        // The mirror image has the opposite writhe
+        let mirror = trefoil(1.0)
+            .iter()
+            .map(|p| Point3::new(p.x, p.y, -p.z))
+            .collect::<Vec<_>>();
+        assert_relative_eq!(
+            Descriptors::new(&mirror).writhe,
+            -descriptors.writhe,
+            max_relative = 1e-9
+        );
+    }
+
+    #[test]
+    fn hopf_link() {
+        let first = circle(1.0, Point3::origin(), 2);
+        let second = circle(1.0, Point3::new(1.0, 0.0, 0.0), 1);
+        assert_relative_eq!(linking_number(&first, &second).abs(), 1.0, max_relative = 1e-6);
+        assert_relative_eq!(
+            linking_number(&first, &second),
+            linking_number(&second, &first),
+            max_relative = 1e-9
+        );
+
+        let mut reversed = second.clone();
+        reversed.reverse();
+        assert_relative_eq!(
+            linking_number(&first, &reversed),
+            -linking_number(&first, &second),
+            max_relative = 1e-9
+        );
+
+        let apart = circle(1.0, Point3::new(3.0, 0.0, 0.0), 1);
+        assert_relative_eq!(linking_number(&first, &apart), 0.0, epsilon = 1e-9);
+    }
+}
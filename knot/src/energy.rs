@@ -0,0 +1,136 @@
+use nalgebra::{Isometry3, Vector3};
+
+use joint::JointSpec;
+
+/// Compute the discrete [Möbius energy](https://en.wikipedia.org/wiki/M%C3%B6bius_energy) of the
+/// closed polyline formed by the centers of a chain of joints and all of its symmetric copies,
+/// counted once per copy of the chain.
+///
+/// Each joint is treated as a vertex of the polyline, carrying an edge of length
+/// `dist_in + dist_out`.  The discrete energy of a polyline with vertices `x_i` and edge lengths
+/// `l_i` is the sum over all non-adjacent pairs of `(1 / |x_i - x_j|^2 - 1 / D(i, j)^2) l_i l_j`,
+/// where `D(i, j)` is the arc length between the two vertices.  Because every joint has the same
+/// fixed length, the arc length term is a constant for a given chain and is omitted.
+///
+/// * `excluded`: Given the index of a joint in the chain, the index of a symmetry, and the index of
+///   a joint in the symmetric copy of the chain, determine whether the pair of joints is adjacent
+///   and should therefore not contribute to the energy.
+pub fn mobius_energy<F: Fn(usize, usize, usize) -> bool>(
+    spec: &JointSpec,
+    joints: &[Isometry3<f64>],
+    symmetries: &[Isometry3<f64>],
+    excluded: F,
+) -> f64 {
+    let edge_len = spec.dist_in() + spec.dist_out();
+    let mut energy = 0.0;
+    for (i, joint) in joints.iter().enumerate() {
+        for (sym_i, sym) in symmetries.iter().enumerate() {
+            for (j, other) in joints.iter().enumerate() {
+                if !excluded(i, sym_i, j) {
+                    let diff = joint.translation.vector - (sym * other).translation.vector;
+                    energy += edge_len * edge_len / diff.norm_squared();
+                }
+            }
+        }
+    }
+    // Every unordered pair was counted twice
+    energy * 0.5
+}
+
+/// Compute the gradient of `mobius_energy` with respect to the translation of each joint.  Each
+/// pair contributes to the gradients of both of its joints, so `excluded` need not rule out a pair
+/// and its counterpart under the inverse symmetry together.
+pub fn mobius_gradient<F: Fn(usize, usize, usize) -> bool>(
+    spec: &JointSpec,
+    joints: &[Isometry3<f64>],
+    symmetries: &[Isometry3<f64>],
+    excluded: F,
+) -> Vec<Vector3<f64>> {
+    let edge_len = spec.dist_in() + spec.dist_out();
+    let mut gradients = vec![Vector3::zeros(); joints.len()];
+    for (i, joint) in joints.iter().enumerate() {
+        for (sym_i, sym) in symmetries.iter().enumerate() {
+            for (j, other) in joints.iter().enumerate() {
+                if !excluded(i, sym_i, j) {
+                    let diff = joint.translation.vector - (sym * other).translation.vector;
+                    let dist_squ = diff.norm_squared();
+                    // The derivative of the pair's term, including the factor of one half in
+                    // `mobius_energy`, with respect to the position of joint i
+                    let term = diff * (-edge_len * edge_len / (dist_squ * dist_squ));
+                    gradients[i] += term;
+                    gradients[j] -= sym.rotation.inverse() * term;
+                }
+            }
+        }
+    }
+    gradients
+}
+
+#[cfg(test)]
+mod test {
+    use energy::*;
+
+    use alga::general::SubsetOf;
+    use nalgebra::{Translation3, UnitQuaternion};
+    use std::f64::consts::PI;
+
+    use symmetry::symmetries;
+
+    fn joints() -> Vec<Isometry3<f64>> {
+        vec![
+            Isometry3::from_parts(
+                Translation3::new(4.0, 0.5, 1.0),
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI / 3.0),
+            ),
+            Isometry3::from_parts(
+                Translation3::new(3.0, 2.0, -0.5),
+                UnitQuaternion::from_axis_angle(&Vector3::z_axis(), PI / 5.0),
+            ),
+            Translation3::new(1.5, 3.0, 0.25).to_superset(),
+        ]
+    }
+
+    fn excluded(i: usize, sym_i: usize, j: usize) -> bool {
+        sym_i == 0 && (i as isize - j as isize).abs() <= 1
+    }
+
+    fn check_gradient<F: Fn(usize, usize, usize) -> bool + Copy>(
+        spec: &JointSpec,
+        joints: &[Isometry3<f64>],
+        symms: &[Isometry3<f64>],
+        excluded: F,
+    ) {
+        let gradient = mobius_gradient(spec, joints, symms, excluded);
+        let energy = mobius_energy(spec, joints, symms, excluded);
+
+        let step = 1e-7;
+        for i in 0..joints.len() {
+            for axis in 0..3 {
+                let mut stepped = joints.to_vec();
+                stepped[i].translation.vector[axis] += step;
+                let stepped_energy = mobius_energy(spec, &stepped, symms, excluded);
+                assert_relative_eq!(
+                    (stepped_energy - energy) / step,
+                    gradient[i][axis],
+                    epsilon = 1e-4
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gradient_matches_finite_difference() {
+        let spec = JointSpec::new(1.0, 1.0, PI / 6.0, 0.5);
+        let symms = symmetries(3)
+            .map(|quat| quat.to_superset())
+            .collect::<Vec<Isometry3<f64>>>();
+        let joints = joints();
+
+        // The gradient must not depend on the exclusion being the same for a pair and its
+        // counterpart under the inverse symmetry
+        let lopsided =
+            |i: usize, sym_i: usize, j: usize| excluded(i, sym_i, j) || (sym_i, i) == (2, 0);
+        check_gradient(&spec, &joints, &symms, excluded);
+        check_gradient(&spec, &joints, &symms, lopsided);
+    }
+}
@@ -1,4 +1,6 @@
 use std::f64::consts::PI;
+use std::cmp::max;
+use std::f64::INFINITY;
 use std::fmt::Debug;
 use std::iter::once;
 
@@ -6,8 +8,9 @@ use joint::JointSpec;
 use nalgebra::{Isometry3, Point3, Vector2, Vector3};
 use symmetry::symmetries;
 
-// Swap to collision_grid to enable experimental spatial partitioning optimization
-use collision_grid_trivial::{BoundedCollider, BoundingBox, Bounds, CheckCollision, CollisionGrid};
+use collision_grid;
+use collision_grid::{BoundedCollider, BoundingBox, Bounds, CheckCollision, CollisionIndex};
+use collision_grid_trivial;
 
 #[derive(Clone, Copy, Debug)]
 enum PointsState {
@@ -156,6 +159,19 @@ struct CollisionSphere {
     symmetry_index: u32,
     symmetry_count: u32,
     skip: u32,
+    protection_depth: u32,
+}
+
+impl CollisionSphere {
+    /// Identify this sphere by the joint its point belongs to: the first and last points are the
+    /// ends of the first and last joints, and every other point is the middle of a joint.
+    fn collider_id(&self) -> ColliderId {
+        ColliderId {
+            symmetry_index: self.symmetry_index,
+            index: self.index.max(1).min(self.point_count - 2) - 1,
+            point: Some(self.index),
+        }
+    }
 }
 
 impl BoundedCollider for CollisionSphere {
@@ -177,10 +193,97 @@ impl BoundedCollider for CollisionSphere {
     }
 }
 
-fn one_apart(a: u32, b: u32) -> bool {
-    a + 1 == b || a == b + 1
-    // TODO: Adaptable neighbor test depth
-    || a + 2 == b || a == b + 2
+/// An upper bound on the distance between two points of a chain which are `leg_count` legs apart.
+///
+/// Between two such points lie some number of whole joints, each of whose two legs meet at the bend
+/// angle, and at most one lone leg at either end.  Since copies of the chain meet leg to leg, a
+/// lone leg may be either kind of leg, and is taken to be the longer of the two.  Any two legs
+/// which do not belong to the same whole joint are treated as if they could be parallel.
+fn max_separation(spec: &JointSpec, leg_count: u32) -> f64 {
+    let separation_squ = |whole_joints: u32, lone_legs: u32| {
+        let whole_joints = whole_joints as f64;
+        let length = whole_joints * (spec.dist_in() + spec.dist_out())
+            + (lone_legs as f64) * spec.dist_in().max(spec.dist_out());
+        let bend = 2.0 * (1.0 - spec.bend_angle().cos());
+        length * length - bend * whole_joints * spec.dist_in() * spec.dist_out()
+    };
+
+    let squ = if leg_count == 0 {
+        0.0
+    } else if leg_count % 2 == 1 {
+        separation_squ(leg_count / 2, 1)
+    } else {
+        separation_squ(leg_count / 2, 0).max(separation_squ(leg_count / 2 - 1, 2))
+    };
+    squ.max(0.0).sqrt()
+}
+
+/// Determine how many legs apart along a chain two colliders (points or joints) must be before they
+/// can be tested for collisions, based on the geometry of the joints.
+///
+/// Anything closer together along the chain than this is necessarily within a diameter of each
+/// other, no matter how the joints are twisted, and so would always register as colliding.  The
+/// distances are measured in legs rather than in points or joints because the legs of a joint
+/// alternate between its "in" and "out" lengths, and because two copies of the chain meet at the
+/// ends of their legs rather than at their midpoints.  Returns `None` if the spec does not describe
+/// a usable joint, e.g. if it folds back on itself.
+pub fn protection_depth(spec: &JointSpec) -> Option<u32> {
+    if spec.bend_angle().abs() >= PI
+        || !(spec.dist_in() > 0.0 && spec.dist_out() > 0.0)
+        || !spec.radius().is_finite()
+    {
+        return None;
+    }
+
+    let diameter = spec.radius() * 2.0;
+    let mut depth = 0;
+    while max_separation(spec, depth + 1) < diameter {
+        depth += 1;
+    }
+    Some(depth)
+}
+
+/// The stretch of a chain occupied by a collider, measured in legs from the start of the chain.
+/// The "in" point of joint `j` lies `2 * j` legs along the chain, its midpoint `2 * j + 1` legs
+/// along, and its "out" point `2 * j + 2` legs along.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LegSpan {
+    start: u32,
+    end: u32,
+}
+
+impl LegSpan {
+    /// The span of the point with the given index, as yielded by `points`, of a chain with
+    /// `point_count` points.
+    fn point(index: u32, point_count: u32) -> LegSpan {
+        let position = if index == 0 {
+            0
+        } else if index + 1 == point_count {
+            2 * (point_count - 2)
+        } else {
+            2 * index - 1
+        };
+        LegSpan {
+            start: position,
+            end: position,
+        }
+    }
+
+    /// The span of the joint with the given index, from its "in" point to its "out" point.
+    fn joint(index: u32) -> LegSpan {
+        LegSpan {
+            start: 2 * index,
+            end: 2 * index + 2,
+        }
+    }
+
+    /// The number of legs between two spans of the same chain, or zero if they overlap.
+    fn gap(&self, other: &LegSpan) -> u32 {
+        max(
+            other.start.saturating_sub(self.end),
+            self.start.saturating_sub(other.end),
+        )
+    }
 }
 
 fn same_horseshoe_partner(symmetry_count: u32, symmetry_index: u32) -> u32 {
@@ -199,22 +302,6 @@ fn opposing_horseshoe_partner(symmetry_count: u32, skip: u32, symmetry_index: u3
     }
 }
 
-fn connected_branches(
-    symmetry_count: u32,
-    skip: u32,
-    symmetry_index1: u32,
-    symmetry_index2: u32,
-) -> bool {
-    same_horseshoe_partner(symmetry_count, symmetry_index1) == symmetry_index2
-        || opposing_horseshoe_partner(symmetry_count, skip, symmetry_index1) == symmetry_index2
-}
-
-fn is_extreme(x: u32, count: u32) -> bool {
-    x == 0 || x + 1 == count
-    // TOTDO: adaptable neighbor test depth
-    || x + 2 == count || x == 1
-}
-
 // Small sylistic convenience, to avoid the confusion of having to arbitrarily refer to one of two
 // values after establishing that they are in fact identical.
 fn assert_same<T: Debug + PartialEq>(a: T, b: T) -> T {
@@ -222,6 +309,36 @@ fn assert_same<T: Debug + PartialEq>(a: T, b: T) -> T {
     a
 }
 
+/// Determine whether two colliders, identified by the stretch of the chain they occupy and the
+/// index of the symmetry branch they belong to, are exempt from collision testing because they are
+/// meant to touch or overlap.  `leg_count` is the total number of legs in the chain.
+fn protected(
+    leg_count: u32,
+    symmetry_count: u32,
+    skip: u32,
+    depth: u32,
+    span1: LegSpan,
+    symmetry_index1: u32,
+    span2: LegSpan,
+    symmetry_index2: u32,
+) -> bool {
+    if symmetry_index1 == symmetry_index2 {
+        // Colliders on the same symmetry branch are collision-protected iff they are within `depth`
+        // legs of each other.
+        span1.gap(&span2) <= depth
+    } else {
+        // The two halves of a horseshoe meet at the start of the chain, and opposing horseshoes
+        // meet at its end.  Colliders on branches which meet are collision-protected iff they are
+        // within `depth` legs of each other, counting through the point where the branches meet.
+        let via_start = same_horseshoe_partner(symmetry_count, symmetry_index1) == symmetry_index2
+            && span1.start + span2.start <= depth;
+        let via_end = opposing_horseshoe_partner(symmetry_count, skip, symmetry_index1)
+            == symmetry_index2
+            && (leg_count - span1.end) + (leg_count - span2.end) <= depth;
+        via_start || via_end
+    }
+}
+
 impl CheckCollision<CollisionSphere> for CollisionSphere {
     type CollisionData = CollisionSphere;
 
@@ -229,31 +346,18 @@ impl CheckCollision<CollisionSphere> for CollisionSphere {
         let point_count = assert_same(self.point_count, other.point_count);
         let symmetry_count = assert_same(self.symmetry_count, other.symmetry_count);
         let skip = assert_same(self.skip, other.skip);
+        let depth = assert_same(self.protection_depth, other.protection_depth);
 
-        let protected = if self.symmetry_index == other.symmetry_index {
-            // Points on the same symmetry branch are collision-protected iff they are the same
-            // point or immediate neighbors.
-            self.index == other.index || one_apart(self.index, other.index)
-        } else if connected_branches(
+        let colliding = if protected(
+            2 * (point_count - 2),
             symmetry_count,
             skip,
+            depth,
+            LegSpan::point(self.index, point_count),
             self.symmetry_index,
+            LegSpan::point(other.index, point_count),
             other.symmetry_index,
         ) {
-            // Points on adjacent symmetry branches are collision-protected iff they are the same
-            // initial or final point (because they are then meant to exactly overlap in space), or
-            // one is an initial or final point and the other is its immediate neighbor (because one
-            // is then meant to exactly overlap the other's immediate neighbor).
-            let self_extreme = is_extreme(self.index, point_count);
-            let other_extreme = is_extreme(other.index, point_count);
-            let overlapping = self_extreme && other_extreme && self.index == other.index;
-            let neighbors = (self_extreme || other_extreme) && one_apart(self.index, other.index);
-            overlapping || neighbors
-        } else {
-            false
-        };
-
-        let colliding = if protected {
             false
         } else {
             let total_rad = self.radius + other.radius;
@@ -288,15 +392,121 @@ fn exact_size<I: Iterator>(it: &I) -> usize {
     }
 }
 
-// Set to true to enable symmetry-based spatial partitioning optimization
-const USE_WEDGE: bool = false;
+/// The data structure used to find the pairs of colliders which need to be tested against each
+/// other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionBackend {
+    /// Test every pair of colliders, using `collision_grid_trivial`.
+    Exhaustive,
 
-pub fn collisions_with_symmetry<I: Iterator<Item = Point3<f64>>>(
+    /// Only test colliders sharing a cell of a uniform spatial grid, using `collision_grid`.
+    Grid,
+}
+
+/// How collision tests should be carried out.  Every strategy finds exactly the same collisions;
+/// they differ only in how quickly they do so, which depends on the size and shape of the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollisionStrategy {
+    pub backend: CollisionBackend,
+
+    /// Only place spheres in (and near) a single wedge of the symmetry group, relying on symmetry
+    /// to account for collisions elsewhere.  This is only used when testing for the presence of a
+    /// collision, and is ignored when enumerating collisions or testing capsules.
+    pub use_wedge: bool,
+}
+
+impl CollisionStrategy {
+    /// Every combination of backend and wedge optimization, for comparison purposes.
+    pub fn all() -> Vec<CollisionStrategy> {
+        let mut strategies = Vec::new();
+        for &backend in &[CollisionBackend::Exhaustive, CollisionBackend::Grid] {
+            for &use_wedge in &[false, true] {
+                strategies.push(CollisionStrategy { backend, use_wedge });
+            }
+        }
+        strategies
+    }
+}
+
+/// A `CollisionIndex` using whichever backend was selected at runtime.
+enum SelectedGrid<T> {
+    Exhaustive(collision_grid_trivial::CollisionGrid<T>),
+    Grid(collision_grid::CollisionGrid<T>),
+}
+
+impl<T> SelectedGrid<T> {
+    fn new(backend: CollisionBackend, cell_size: f64) -> Self {
+        match backend {
+            CollisionBackend::Exhaustive => {
+                SelectedGrid::Exhaustive(collision_grid_trivial::CollisionGrid::new(cell_size))
+            }
+            CollisionBackend::Grid => {
+                SelectedGrid::Grid(collision_grid::CollisionGrid::new(cell_size))
+            }
+        }
+    }
+}
+
+impl<T> CollisionIndex<T> for SelectedGrid<T> {
+    fn add(&mut self, collider: &T)
+    where
+        T: Clone + BoundedCollider,
+    {
+        match self {
+            SelectedGrid::Exhaustive(grid) => grid.add(collider),
+            SelectedGrid::Grid(grid) => grid.add(collider),
+        }
+    }
+
+    fn collisions<U: CheckCollision<T>>(&self, test_collider: &U) -> Vec<U::CollisionData> {
+        match self {
+            SelectedGrid::Exhaustive(grid) => grid.collisions(test_collider),
+            SelectedGrid::Grid(grid) => grid.collisions(test_collider),
+        }
+    }
+}
+
+/// Identifies one symmetric copy of a point or joint of a chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColliderId {
+    /// The index of the symmetry (as enumerated by `symmetry::symmetries`) mapping the chain to the
+    /// copy containing the collider.
+    pub symmetry_index: u32,
+
+    /// The index along the chain of the joint the collider belongs to.  The point at either end of
+    /// the chain belongs to the joint it ends.
+    pub index: u32,
+
+    /// For spheres, the index of the point as yielded by `points`.  `None` for capsules, which
+    /// cover a whole leg.
+    pub point: Option<u32>,
+}
+
+/// A single pair of colliders which overlap.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollisionPair {
+    pub first: ColliderId,
+    pub second: ColliderId,
+
+    /// The depth to which the two colliders interpenetrate, i.e. the sum of their radii minus the
+    /// distance between them.  Always positive.
+    pub penetration: f64,
+}
+
+/// Place a sphere at every center of a chain and all its symmetric copies, and call `on_collision`
+/// with every pair of spheres which collide until it returns `false`.
+fn sphere_collisions<I, F>(
     symmetry_count: u32,
     skip: u32,
+    protection_depth: u32,
     centers: I,
     radius: f64,
-) -> CollisionOutcome {
+    strategy: CollisionStrategy,
+    mut on_collision: F,
+) where
+    I: Iterator<Item = Point3<f64>>,
+    F: FnMut(&CollisionSphere, &CollisionSphere) -> bool,
+{
     // TODO: Calculate all global parameters outside function.  Possibly factor out "symmetry"
     // object wich does caching similar to JointSpec of cos, sin, vector isometries, etc?
 
@@ -310,14 +520,17 @@ pub fn collisions_with_symmetry<I: Iterator<Item = Point3<f64>>>(
 
     let symms = symmetries(symmetry_count).collect::<Vec<_>>();
 
-    let mut grid = CollisionGrid::new(radius);
+    let mut grid = SelectedGrid::new(strategy.backend, radius);
 
     let point_count = exact_size(&centers);
 
+    // With only one rotation there is only one wedge, which the planes above do not describe
+    let use_wedge = strategy.use_wedge && symmetry_count > 1;
+
     for (index, center) in centers.enumerate() {
         for (symm_index, symm) in symms.iter().enumerate() {
             let symm_center = symm * center;
-            if !USE_WEDGE
+            if !use_wedge
                 || (symm_center.coords.dot(&wedge_plane1_normal) <= radius
                     && symm_center.coords.dot(&wedge_plane2_normal) <= radius)
             {
@@ -329,20 +542,350 @@ pub fn collisions_with_symmetry<I: Iterator<Item = Point3<f64>>>(
                     symmetry_index: symm_index as u32,
                     symmetry_count,
                     skip,
+                    protection_depth,
                 };
 
-                let collisions = grid.collisions(&sphere);
-
-                if collisions.len() > 0 {
-                    return CollisionOutcome::Collision;
+                for other in grid.collisions(&sphere) {
+                    if !on_collision(&other, &sphere) {
+                        return;
+                    }
                 }
 
                 grid.add(&sphere)
             }
         }
     }
+}
+
+pub fn collisions_with_symmetry<I: Iterator<Item = Point3<f64>>>(
+    symmetry_count: u32,
+    skip: u32,
+    protection_depth: u32,
+    centers: I,
+    radius: f64,
+    strategy: CollisionStrategy,
+) -> CollisionOutcome {
+    let mut outcome = CollisionOutcome::NoCollisions;
+    sphere_collisions(
+        symmetry_count,
+        skip,
+        protection_depth,
+        centers,
+        radius,
+        strategy,
+        |_, _| {
+            outcome = CollisionOutcome::Collision;
+            false
+        },
+    );
+    outcome
+}
+
+/// Like `collisions_with_symmetry`, but rather than stopping at the first collision, return every
+/// pair of colliding spheres.  Intended for diagnostics, so the symmetry wedge optimization is
+/// never used.
+pub fn collision_pairs_with_symmetry<I: Iterator<Item = Point3<f64>>>(
+    symmetry_count: u32,
+    skip: u32,
+    protection_depth: u32,
+    centers: I,
+    radius: f64,
+    backend: CollisionBackend,
+) -> Vec<CollisionPair> {
+    let mut pairs = Vec::new();
+    sphere_collisions(
+        symmetry_count,
+        skip,
+        protection_depth,
+        centers,
+        radius,
+        CollisionStrategy {
+            backend,
+            use_wedge: false,
+        },
+        |first, second| {
+            pairs.push(CollisionPair {
+                first: first.collider_id(),
+                second: second.collider_id(),
+                penetration: first.radius + second.radius - (second.center - first.center).norm(),
+            });
+            true
+        },
+    );
+    pairs
+}
+
+/// Compute the squared distance between the closest points of the segments from `p1` to `q1` and
+/// from `p2` to `q2`.
+///
+/// Adapted from Christer Ericson, *Real-Time Collision Detection*, section 5.1.9.
+pub fn segment_distance_squ(
+    p1: &Point3<f64>,
+    q1: &Point3<f64>,
+    p2: &Point3<f64>,
+    q2: &Point3<f64>,
+) -> f64 {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.norm_squared();
+    let e = d2.norm_squared();
+    let f = d2.dot(&r);
+
+    // Clamp a segment parameter to the unit interval
+    let clamp = |x: f64| x.max(0.0).min(1.0);
+
+    let (s, t) = if a <= SEGMENT_EPSILON && e <= SEGMENT_EPSILON {
+        // Both segments degenerate into points
+        (0.0, 0.0)
+    } else if a <= SEGMENT_EPSILON {
+        // First segment degenerates into a point
+        (0.0, clamp(f / e))
+    } else {
+        let c = d1.dot(&r);
+        if e <= SEGMENT_EPSILON {
+            // Second segment degenerates into a point
+            (clamp(-c / a), 0.0)
+        } else {
+            let b = d1.dot(&d2);
+            let denom = a * e - b * b;
+
+            // If the segments are not parallel, find the closest point on the first segment's line
+            // to the second segment's line, and clamp it to the first segment.  Otherwise, pick an
+            // arbitrary point on the first segment.
+            let s = if denom > SEGMENT_EPSILON {
+                clamp((b * f - c * e) / denom)
+            } else {
+                0.0
+            };
+
+            // Find the closest point on the second segment to the chosen point on the first, and
+            // if it must be clamped, recompute the closest point on the first segment.
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                (clamp(-c / a), 0.0)
+            } else if t > 1.0 {
+                (clamp((b - c) / a), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+
+    let closest1 = p1 + d1 * s;
+    let closest2 = p2 + d2 * t;
+    (closest1 - closest2).norm_squared()
+}
+
+/// Squared segment lengths below which segments are treated as points.
+const SEGMENT_EPSILON: f64 = 1e-12;
+
+/// Iterate over the "legs" of a sequence of joints, i.e. the segments from each joint's "in" point
+/// to its midpoint and from its midpoint to its "out" point, in the order in which they occur along
+/// the chain.
+pub fn legs<I: Iterator<Item = Isometry3<f64>>>(
+    spec: JointSpec,
+    joints: I,
+) -> impl Iterator<Item = (Point3<f64>, Point3<f64>)> {
+    joints.flat_map(move |joint| {
+        let in_point = Point3::from((joint * spec.origin_to_in()).translation.vector);
+        let mid_point = Point3::from(joint.translation.vector);
+        let out_point = Point3::from((joint * spec.origin_to_out()).translation.vector);
+        once((in_point, mid_point)).chain(once((mid_point, out_point)))
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CollisionCapsule {
+    start: Point3<f64>,
+    end: Point3<f64>,
+    radius: f64,
+    joint_index: u32,
+    joint_count: u32,
+    symmetry_index: u32,
+    symmetry_count: u32,
+    skip: u32,
+    protection_depth: u32,
+}
+
+impl BoundedCollider for CollisionCapsule {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            x: Bounds {
+                min: self.start.x.min(self.end.x) - self.radius,
+                max: self.start.x.max(self.end.x) + self.radius,
+            },
+            y: Bounds {
+                min: self.start.y.min(self.end.y) - self.radius,
+                max: self.start.y.max(self.end.y) + self.radius,
+            },
+            z: Bounds {
+                min: self.start.z.min(self.end.z) - self.radius,
+                max: self.start.z.max(self.end.z) + self.radius,
+            },
+        }
+    }
+}
+
+impl CheckCollision<CollisionCapsule> for CollisionCapsule {
+    /// The other capsule, and the clearance between the two capsules, which is negative if they
+    /// interpenetrate.
+    type CollisionData = (CollisionCapsule, f64);
+
+    fn check_collision(&self, other: &CollisionCapsule) -> Option<(CollisionCapsule, f64)> {
+        let joint_count = assert_same(self.joint_count, other.joint_count);
+        let symmetry_count = assert_same(self.symmetry_count, other.symmetry_count);
+        let skip = assert_same(self.skip, other.skip);
+        let depth = assert_same(self.protection_depth, other.protection_depth);
+
+        // Both legs of a joint are given the span of the whole joint, so the neighbor protections
+        // apply to joints rather than to individual legs.
+        if protected(
+            2 * joint_count,
+            symmetry_count,
+            skip,
+            depth,
+            LegSpan::joint(self.joint_index),
+            self.symmetry_index,
+            LegSpan::joint(other.joint_index),
+            other.symmetry_index,
+        ) {
+            None
+        } else {
+            let dist_squ = segment_distance_squ(&self.start, &self.end, &other.start, &other.end);
+            let dist = dist_squ.sqrt();
+            Some((*other, dist - self.radius - other.radius))
+        }
+    }
+}
+
+/// The result of testing a chain of joints for collisions, modeling each leg of each joint as a
+/// capsule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapsuleCollisions {
+    pub outcome: CollisionOutcome,
+
+    /// The smallest clearance between any two unprotected legs which were tested against each
+    /// other, or infinity if there were none.  Negative when the legs interpenetrate.
+    pub min_clearance: f64,
+}
+
+/// Place a capsule around every leg of a chain and all its symmetric copies, and call `on_pair`
+/// with every pair of unprotected capsules tested against each other, along with their clearance.
+fn capsule_clearances<I, F>(
+    symmetry_count: u32,
+    skip: u32,
+    protection_depth: u32,
+    spec: JointSpec,
+    joints: I,
+    backend: CollisionBackend,
+    mut on_pair: F,
+) where
+    I: Iterator<Item = Isometry3<f64>>,
+    F: FnMut(&CollisionCapsule, &CollisionCapsule, f64),
+{
+    let symms = symmetries(symmetry_count).collect::<Vec<_>>();
+
+    let mut grid = SelectedGrid::new(backend, spec.radius());
+
+    let leg_segments = legs(spec, joints).collect::<Vec<_>>();
+    let joint_count = (leg_segments.len() / 2) as u32;
+
+    for (leg_index, &(start, end)) in leg_segments.iter().enumerate() {
+        for (symm_index, symm) in symms.iter().enumerate() {
+            let capsule = CollisionCapsule {
+                start: symm * start,
+                end: symm * end,
+                radius: spec.radius(),
+                joint_index: (leg_index / 2) as u32,
+                joint_count,
+                symmetry_index: symm_index as u32,
+                symmetry_count,
+                skip,
+                protection_depth,
+            };
+
+            for (other, clearance) in grid.collisions(&capsule) {
+                on_pair(&other, &capsule, clearance);
+            }
+
+            grid.add(&capsule);
+        }
+    }
+}
+
+/// Test the legs of a chain of joints, and of all its symmetric copies, for collisions.  Unlike
+/// `collisions_with_symmetry`, which only places spheres at the points of the chain, this tests the
+/// exact distances between the segments of the legs, so it cannot miss legs interpenetrating
+/// between sample points.
+pub fn capsule_collisions_with_symmetry<I: Iterator<Item = Isometry3<f64>>>(
+    symmetry_count: u32,
+    skip: u32,
+    protection_depth: u32,
+    spec: JointSpec,
+    joints: I,
+    backend: CollisionBackend,
+) -> CapsuleCollisions {
+    let mut min_clearance = INFINITY;
+    capsule_clearances(
+        symmetry_count,
+        skip,
+        protection_depth,
+        spec,
+        joints,
+        backend,
+        |_, _, clearance| {
+            min_clearance = min_clearance.min(clearance);
+        },
+    );
+
+    CapsuleCollisions {
+        outcome: if min_clearance < 0.0 {
+            CollisionOutcome::Collision
+        } else {
+            CollisionOutcome::NoCollisions
+        },
+        min_clearance,
+    }
+}
 
-    CollisionOutcome::NoCollisions
+/// Like `capsule_collisions_with_symmetry`, but return every pair of colliding capsules.  A pair of
+/// joints is reported once for each pair of their legs which collide.
+pub fn capsule_collision_pairs_with_symmetry<I: Iterator<Item = Isometry3<f64>>>(
+    symmetry_count: u32,
+    skip: u32,
+    protection_depth: u32,
+    spec: JointSpec,
+    joints: I,
+    backend: CollisionBackend,
+) -> Vec<CollisionPair> {
+    let mut pairs = Vec::new();
+    capsule_clearances(
+        symmetry_count,
+        skip,
+        protection_depth,
+        spec,
+        joints,
+        backend,
+        |first, second, clearance| {
+            if clearance < 0.0 {
+                pairs.push(CollisionPair {
+                    first: ColliderId {
+                        symmetry_index: first.symmetry_index,
+                        index: first.joint_index,
+                        point: None,
+                    },
+                    second: ColliderId {
+                        symmetry_index: second.symmetry_index,
+                        index: second.joint_index,
+                        point: None,
+                    },
+                    penetration: -clearance,
+                });
+            }
+        },
+    );
+    pairs
 }
 
 #[cfg(test)]
@@ -350,6 +893,12 @@ mod test {
     use filter::*;
     use std::f64::consts::PI;
 
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use defaults::COLLISION_STRATEGY;
+    use rand_problem::{rand_chain, rand_placement};
+
     #[test]
     fn single_joint_points() {
         let spec = JointSpec::new(1.0, 1.0, PI / 4.0, 0.5);
@@ -388,6 +937,7 @@ mod test {
                 point_count: 7,
                 symmetry_count: 3,
                 skip: 2,
+                protection_depth: 2,
 
                 index,
                 symmetry_index,
@@ -437,4 +987,318 @@ mod test {
         assert!(!collide(sphere(6, 0), sphere(5, 5)));
         assert!(!collide(sphere(5, 0), sphere(6, 5)));
     }
+
+    #[test]
+    fn geometric_protection_depths() {
+        // The default geometry protects colliders up to two legs apart, i.e. neighboring points and
+        // joints two apart
+        assert_eq!(protection_depth(&JointSpec::new(1.0, 1.0, PI / 6.0, 1.25)), Some(2));
+
+        // Thin joints only protect colliders which touch
+        assert_eq!(protection_depth(&JointSpec::new(1.0, 1.0, PI / 6.0, 0.4)), Some(0));
+
+        // Thick joints
+        assert_eq!(protection_depth(&JointSpec::new(1.0, 1.0, PI / 6.0, 2.0)), Some(4));
+
+        // Sharply bent joints
+        assert_eq!(protection_depth(&JointSpec::new(1.0, 1.0, 2.0 * PI / 3.0, 1.25)), Some(3));
+
+        // Straight joints
+        assert_eq!(protection_depth(&JointSpec::new(1.0, 1.0, 0.0, 1.25)), Some(2));
+
+        // Two long legs meet end to end where copies of the chain meet
+        assert_eq!(protection_depth(&JointSpec::new(0.5, 3.0, PI / 6.0, 1.25)), Some(0));
+        assert_eq!(protection_depth(&JointSpec::new(0.5, 3.0, PI / 6.0, 2.0)), Some(1));
+
+        // Joints which fold back on themselves
+        assert_eq!(protection_depth(&JointSpec::new(1.0, 1.0, PI, 1.25)), None);
+        assert_eq!(protection_depth(&JointSpec::new(0.0, 1.0, PI / 6.0, 1.25)), None);
+    }
+
+    #[test]
+    fn separation_bounds() {
+        let spec = JointSpec::new(0.5, 3.0, PI / 6.0, 1.25);
+        assert_relative_eq!(max_separation(&spec, 0), 0.0);
+        assert_relative_eq!(max_separation(&spec, 1), 3.0);
+
+        // Two out legs back to back, where copies of the chain meet
+        assert_relative_eq!(max_separation(&spec, 2), 6.0);
+
+        // One whole joint and two lone legs bend less than two whole joints
+        let spec = JointSpec::new(1.0, 1.0, PI / 6.0, 1.25);
+        let bend = 2.0 * (1.0 - (PI / 6.0).cos());
+        assert_relative_eq!(max_separation(&spec, 3), (9.0 - bend).sqrt());
+        assert_relative_eq!(max_separation(&spec, 4), (16.0 - bend).sqrt());
+    }
+
+    #[test]
+    fn leg_spans() {
+        // A chain of three joints, with points at its start, at each midpoint, and at its end
+        let spans = (0..5).map(|i| LegSpan::point(i, 5)).collect::<Vec<_>>();
+        let positions = spans.iter().map(|span| span.start).collect::<Vec<_>>();
+        assert_eq!(positions, vec![0, 1, 3, 5, 6]);
+
+        assert_eq!(LegSpan::joint(1), LegSpan { start: 2, end: 4 });
+        assert_eq!(LegSpan::joint(0).gap(&LegSpan::joint(1)), 0);
+        assert_eq!(LegSpan::joint(0).gap(&LegSpan::joint(2)), 2);
+        assert_eq!(LegSpan::joint(2).gap(&spans[0]), 4);
+    }
+
+    #[test]
+    fn deeper_protections() {
+        fn sphere(index: u32, symmetry_index: u32, protection_depth: u32) -> CollisionSphere {
+            CollisionSphere {
+                center: Point3::new(0.0, 0.0, 0.0),
+                radius: 1.0,
+                point_count: 9,
+                symmetry_count: 3,
+                skip: 2,
+                protection_depth,
+                index,
+                symmetry_index,
+            }
+        }
+
+        fn collide(depth: u32, (i1, s1): (u32, u32), (i2, s2): (u32, u32)) -> bool {
+            sphere(i1, s1, depth)
+                .check_collision(&sphere(i2, s2, depth))
+                .is_some()
+        }
+
+        // Same-branch points six legs apart
+        assert!(collide(5, (4, 0), (1, 0)));
+        assert!(!collide(6, (4, 0), (1, 0)));
+
+        // Same-horseshoe symmetrized points five legs apart through the start of the chain
+        assert!(collide(4, (3, 1), (0, 0)));
+        assert!(!collide(5, (3, 1), (0, 0)));
+
+        // The halves of a horseshoe do not meet at the end of the chain
+        assert!(collide(5, (8, 1), (8, 0)));
+
+        // Opposing-horseshoe symmetrized points five legs apart through the end of the chain
+        assert!(collide(4, (5, 5), (8, 0)));
+        assert!(!collide(5, (5, 5), (8, 0)));
+    }
+
+    #[test]
+    fn segment_distances() {
+        let origin = Point3::origin();
+        let x = Point3::new(1.0, 0.0, 0.0);
+
+        // Crossing segments
+        assert_relative_eq!(
+            segment_distance_squ(
+                &Point3::new(-1.0, 0.0, 0.0),
+                &x,
+                &Point3::new(0.0, -1.0, 2.0),
+                &Point3::new(0.0, 1.0, 2.0)
+            ),
+            4.0
+        );
+
+        // Closest points at endpoints
+        assert_relative_eq!(
+            segment_distance_squ(
+                &origin,
+                &x,
+                &Point3::new(2.0, 1.0, 0.0),
+                &Point3::new(3.0, 1.0, 0.0)
+            ),
+            2.0
+        );
+
+        // Parallel, overlapping segments
+        assert_relative_eq!(
+            segment_distance_squ(
+                &origin,
+                &Point3::new(2.0, 0.0, 0.0),
+                &Point3::new(1.0, 3.0, 0.0),
+                &Point3::new(4.0, 3.0, 0.0)
+            ),
+            9.0
+        );
+
+        // Degenerate segment
+        assert_relative_eq!(
+            segment_distance_squ(
+                &origin,
+                &x,
+                &Point3::new(0.5, 0.0, 1.0),
+                &Point3::new(0.5, 0.0, 1.0)
+            ),
+            1.0
+        );
+    }
+
+    #[test]
+    fn single_joint_legs() {
+        let spec = JointSpec::new(1.0, 1.0, PI / 4.0, 0.5);
+        let joints = [spec.in_to_origin()];
+        let legs_vec: Vec<_> = legs(spec, joints.iter().cloned()).collect();
+        let points_vec: Vec<_> = points(spec, joints.iter().cloned()).collect();
+        assert_eq!(legs_vec.len(), 2);
+        assert_relative_eq!(legs_vec[0].0, points_vec[0]);
+        assert_relative_eq!(legs_vec[0].1, points_vec[1]);
+        assert_relative_eq!(legs_vec[1].0, points_vec[1]);
+        assert_relative_eq!(legs_vec[1].1, points_vec[2]);
+    }
+
+    #[test]
+    fn capsules_catch_collisions_between_points() {
+        // Two legs crossing at right angles, offset slightly in z.  Their endpoints are far apart,
+        // but the legs themselves nearly intersect.
+        fn capsule(start: Point3<f64>, end: Point3<f64>, symmetry_index: u32) -> CollisionCapsule {
+            CollisionCapsule {
+                start,
+                end,
+                radius: 0.25,
+                joint_index: 5,
+                joint_count: 12,
+                symmetry_index,
+                symmetry_count: 3,
+                skip: 2,
+                protection_depth: 2,
+            }
+        }
+
+        let capsule1 = capsule(Point3::new(-2.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0), 0);
+        let capsule2 = capsule(Point3::new(0.0, -2.0, 0.1), Point3::new(0.0, 2.0, 0.1), 2);
+
+        assert_relative_eq!(capsule1.check_collision(&capsule2).unwrap().1, -0.4);
+        assert_relative_eq!(capsule2.check_collision(&capsule1).unwrap().1, -0.4);
+
+        let sphere1 = CollisionSphere {
+            center: capsule1.start,
+            radius: 0.25,
+            index: 5,
+            point_count: 12,
+            symmetry_index: 0,
+            symmetry_count: 3,
+            skip: 2,
+            protection_depth: 2,
+        };
+        let sphere2 = CollisionSphere {
+            center: capsule2.start,
+            symmetry_index: 2,
+            ..sphere1
+        };
+        assert!(sphere1.check_collision(&sphere2).is_none());
+    }
+
+    #[test]
+    fn all_collision_pairs() {
+        // The point with index 3 lies close to the axis of rotational symmetry, so each of its "up"
+        // images collides with the other two, as does each of its "down" images.  All other points
+        // are well-separated.
+        let centers = (0..7).map(|i| {
+            if i == 3 {
+                Point3::new(0.1, 0.0, 5.0)
+            } else {
+                Point3::new(10.0 + 2.0 * (i as f64), 1.0, 1.0)
+            }
+        }).collect::<Vec<_>>();
+        let radius = 0.5;
+
+        assert_eq!(
+            collisions_with_symmetry(
+                3,
+                2,
+                2,
+                centers.iter().cloned(),
+                radius,
+                COLLISION_STRATEGY
+            ),
+            CollisionOutcome::Collision
+        );
+
+        let pairs = collision_pairs_with_symmetry(
+            3,
+            2,
+            2,
+            centers.iter().cloned(),
+            radius,
+            COLLISION_STRATEGY.backend,
+        );
+        assert_eq!(pairs.len(), 6);
+        for pair in &pairs {
+            assert_eq!(pair.first.index, 2);
+            assert_eq!(pair.second.index, 2);
+            assert_eq!(pair.first.point, Some(3));
+            assert_eq!(pair.second.point, Some(3));
+            assert_eq!(pair.first.symmetry_index % 2, pair.second.symmetry_index % 2);
+            assert!(pair.first.symmetry_index != pair.second.symmetry_index);
+            assert_relative_eq!(pair.penetration, 1.0 - 0.1 * (3.0f64).sqrt(), epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn collision_strategies_agree() {
+        let spec = JointSpec::new(1.0, 1.0, PI / 6.0, 1.25);
+        let depth = protection_depth(&spec).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut collision_count = 0;
+        let mut trial_count = 0;
+        for &(symmetry_count, skip) in &[(1, 0), (2, 1), (3, 1), (3, 2), (5, 2)] {
+            for _ in 0..100 {
+                let placement = rand_placement(&mut rng, 6.0);
+                let chain = rand_chain(&mut rng, spec, 16, 6);
+                let centers = || points(spec, chain.iter().cloned()).map(|point| placement * point);
+                let joints = || chain.iter().map(|&joint| placement * joint);
+                let outcomes = CollisionStrategy::all()
+                    .into_iter()
+                    .map(|strategy| {
+                        collisions_with_symmetry(
+                            symmetry_count,
+                            skip,
+                            depth,
+                            centers(),
+                            spec.radius(),
+                            strategy,
+                        )
+                    }).collect::<Vec<_>>();
+                for outcome in &outcomes {
+                    assert_eq!(*outcome, outcomes[0]);
+                }
+
+                let sphere_pairs = |backend| {
+                    collision_pairs_with_symmetry(
+                        symmetry_count,
+                        skip,
+                        depth,
+                        centers(),
+                        spec.radius(),
+                        backend,
+                    )
+                };
+                let pairs = sphere_pairs(CollisionBackend::Exhaustive);
+                assert_eq!(pairs, sphere_pairs(CollisionBackend::Grid));
+                assert_eq!(pairs.is_empty(), outcomes[0] == CollisionOutcome::NoCollisions);
+
+                let capsule_pairs = |backend| {
+                    capsule_collision_pairs_with_symmetry(
+                        symmetry_count,
+                        skip,
+                        depth,
+                        spec,
+                        joints(),
+                        backend,
+                    )
+                };
+                assert_eq!(
+                    capsule_pairs(CollisionBackend::Exhaustive),
+                    capsule_pairs(CollisionBackend::Grid)
+                );
+
+                if outcomes[0] == CollisionOutcome::Collision {
+                    collision_count += 1;
+                }
+                trial_count += 1;
+            }
+        }
+
+        // Make sure both outcomes were actually exercised
+        assert!(collision_count > 0 && collision_count < trial_count);
+    }
 }
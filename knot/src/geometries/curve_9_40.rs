@@ -1,8 +1,12 @@
+//! Despite its name, the curve of this module traces the (3, 4) torus knot 8_19, not 9_40.
+
 use std::f64::consts::PI;
 
 use alga::general::SubsetOf;
 use nalgebra::{UnitQuaternion, Vector3};
 
+#[cfg(test)]
+use optimize_tools::RepulsionChain;
 use optimize_tools::{Chain, Leg, PhantomJoint};
 use cost::CostParams;
 use defaults;
@@ -14,6 +18,8 @@ use geometries::spherical::spherical;
 
 const TAU: f64 = 2.0 * PI;
 
+/// A chain following the first of three horseshoes of a curve which winds four times around the
+/// axis of symmetry.
 pub fn chain(
     chain_size: usize,
     scale: f64,
@@ -61,3 +67,28 @@ pub fn chain(
         ).collect(),
     )
 }
+
+/// The chain at the scale of the 8_19 preset, repelled by its copies under the symmetries
+/// of the curve, for tests which need a realistic chain to optimize.
+#[cfg(test)]
+pub fn repulsion_chain(cost_params: CostParams) -> RepulsionChain {
+    use defaults::continuous_optimization::{
+        CURVE_9_40_CHAIN_SIZE, MAX_REPULSION_STRENGTH, RATE, REPULSION_EXPONENT,
+        REPULSION_STRENGTH, RETURN_TO_INITIAL_WEIGHT,
+    };
+    use symmetry::symmetries;
+
+    RepulsionChain::new(
+        chain(
+            CURVE_9_40_CHAIN_SIZE,
+            0.7,
+            cost_params,
+            RETURN_TO_INITIAL_WEIGHT,
+            RATE,
+        ),
+        symmetries(3).map(|quat| quat.to_superset()).collect(),
+        REPULSION_EXPONENT,
+        REPULSION_STRENGTH,
+        MAX_REPULSION_STRENGTH,
+    )
+}
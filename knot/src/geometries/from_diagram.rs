@@ -0,0 +1,611 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use alga::general::SubsetOf;
+use nalgebra::{DMatrix, Point2, Point3, UnitQuaternion, Vector3};
+
+use cost::CostParams;
+use defaults;
+use isometry_adjust as iso_adj;
+use optimize_tools::{Chain, Leg, PhantomJoint};
+use symmetry::{adjacent_symmetry, symmetries_with_skip};
+
+use geometries::from_curve::from_curve_natural_parameterize;
+
+/// How far apart two points of a layout may be while still counting as the same point.
+const TOLERANCE: f64 = 1e-6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiagramError {
+    /// The code could not be parsed, or does not describe a knot diagram.
+    InvalidCode(String),
+
+    /// No diagram in the plane meets its crossings in the order given.
+    NonPlanar,
+
+    /// No layout of the diagram which was tried has dihedral symmetry of the given order.
+    NoSymmetricLayout(u32),
+}
+
+impl fmt::Display for DiagramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &DiagramError::InvalidCode(ref reason) => write!(f, "Invalid code: {}", reason),
+            &DiagramError::NonPlanar => write!(f, "The code does not describe a planar diagram"),
+            &DiagramError::NoSymmetricLayout(symmetry) => write!(
+                f,
+                "Could not lay out the diagram with dihedral-{} symmetry",
+                symmetry
+            ),
+        }
+    }
+}
+
+impl Error for DiagramError {}
+
+/// Parse a list of integers separated by spaces or commas, optionally in brackets, as knot codes
+/// are usually written.
+pub fn parse_code(s: &str) -> Result<Vec<i32>, DiagramError> {
+    s.split(|c: char| c.is_whitespace() || c == ',' || c == '[' || c == ']')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.parse::<i32>()
+                .map_err(|_| DiagramError::InvalidCode(format!("{} is not an integer", word)))
+        }).collect()
+}
+
+/// A knot diagram as the sequence of crossings met along the knot, numbered from 1.  Every crossing
+/// is met twice, once passing over it, written as a positive number, and once passing under it,
+/// written as a negative number.  Like a Dowker-Thistlethwaite code, this only describes the knot
+/// up to mirror image.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GaussCode(Vec<i32>);
+
+impl GaussCode {
+    pub fn new(sequence: Vec<i32>) -> Result<GaussCode, DiagramError> {
+        let count = sequence.len() / 2;
+        if sequence.is_empty() || sequence.len() % 2 != 0 {
+            return Err(DiagramError::InvalidCode(
+                "a Gauss code must have an even, nonzero number of entries".to_string(),
+            ));
+        }
+        let mut over = vec![false; count];
+        let mut under = vec![false; count];
+        for &entry in &sequence {
+            let index = entry.abs() as usize;
+            if index == 0 || index > count {
+                return Err(DiagramError::InvalidCode(format!(
+                    "crossing {} is out of range for {} crossings",
+                    entry, count
+                )));
+            }
+            let seen = if entry > 0 { &mut over } else { &mut under };
+            if seen[index - 1] {
+                return Err(DiagramError::InvalidCode(format!(
+                    "crossing {} is passed {} more than once",
+                    index,
+                    if entry > 0 { "over" } else { "under" }
+                )));
+            }
+            seen[index - 1] = true;
+        }
+        Ok(GaussCode(sequence))
+    }
+
+    /// Convert a Dowker-Thistlethwaite code, in which the `i`th entry is the even label paired
+    /// with the odd label `2 * i + 1` as the knot is traversed.  The odd label passes over the
+    /// crossing if the entry is positive, and under it if the entry is negative, so that the code
+    /// of an alternating diagram is all positive.
+    pub fn from_dowker(code: &[i32]) -> Result<GaussCode, DiagramError> {
+        let count = code.len();
+        let mut sequence = vec![0; 2 * count];
+        for (i, &even) in code.iter().enumerate() {
+            let label = even.abs() as usize;
+            if label % 2 != 0 || label == 0 || label > 2 * count || sequence[label - 1] != 0 {
+                return Err(DiagramError::InvalidCode(format!(
+                    "{} is not an unused even label between 2 and {}",
+                    even,
+                    2 * count
+                )));
+            }
+            let crossing = (i + 1) as i32;
+            let odd_over = even > 0;
+            sequence[2 * i] = if odd_over { crossing } else { -crossing };
+            sequence[label - 1] = if odd_over { -crossing } else { crossing };
+        }
+        GaussCode::new(sequence)
+    }
+
+    pub fn sequence(&self) -> &[i32] {
+        &self.0
+    }
+
+    pub fn crossing_count(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    /// The index of the crossing met at each position along the knot, from 0.
+    fn crossing_at(&self, position: usize) -> usize {
+        self.0[position % self.0.len()].abs() as usize - 1
+    }
+
+    /// The two positions along the knot at which each crossing is met.
+    fn visits(&self) -> Vec<(usize, usize)> {
+        let mut visits = vec![(None, None); self.crossing_count()];
+        for position in 0..self.0.len() {
+            let visit = &mut visits[self.crossing_at(position)];
+            if visit.0.is_none() {
+                visit.0 = Some(position);
+            } else {
+                visit.1 = Some(position);
+            }
+        }
+        visits
+            .into_iter()
+            .map(|(first, second)| (first.unwrap(), second.unwrap()))
+            .collect()
+    }
+}
+
+impl FromStr for GaussCode {
+    type Err = DiagramError;
+
+    fn from_str(s: &str) -> Result<GaussCode, DiagramError> {
+        GaussCode::new(parse_code(s)?)
+    }
+}
+
+// The diagram is a graph with a vertex at every crossing, and an edge from every position along the
+// knot to the next.  Every edge has two darts: dart `2 * e` runs along edge `e` in the direction of
+// the knot, and dart `2 * e + 1` runs against it.
+
+fn forward(edge: usize) -> usize {
+    2 * edge
+}
+
+fn backward(edge: usize) -> usize {
+    2 * edge + 1
+}
+
+fn edge(dart: usize) -> usize {
+    dart / 2
+}
+
+/// The position along the knot at which the dart starts.
+fn tail(dart: usize, positions: usize) -> usize {
+    if dart % 2 == 0 {
+        edge(dart)
+    } else {
+        (edge(dart) + 1) % positions
+    }
+}
+
+/// An embedding of a diagram in the plane.
+struct PlanarMap {
+    /// The darts around each face, with the face on the same side of each.
+    faces: Vec<Vec<usize>>,
+}
+
+fn trace_faces(rotations: &[[usize; 4]], positions: usize) -> Vec<Vec<usize>> {
+    let mut slots = vec![(0, 0); 2 * positions];
+    for (crossing, rotation) in rotations.iter().enumerate() {
+        for (slot, &dart) in rotation.iter().enumerate() {
+            slots[dart] = (crossing, slot);
+        }
+    }
+
+    let mut visited = vec![false; 2 * positions];
+    let mut faces = Vec::new();
+    for start in 0..2 * positions {
+        let mut face = Vec::new();
+        let mut dart = start;
+        while !visited[dart] {
+            visited[dart] = true;
+            face.push(dart);
+            // Turn to the next dart counterclockwise from the reverse of this one
+            let (crossing, slot) = slots[dart ^ 1];
+            dart = rotations[crossing][(slot + 1) % 4];
+        }
+        if !face.is_empty() {
+            faces.push(face);
+        }
+    }
+    faces
+}
+
+/// Find an embedding of the diagram in the plane.  Which way the second strand through each
+/// crossing passes the first is searched exhaustively, taking time exponential in the number of
+/// crossings, until the map has as many faces as a planar one must.
+fn planar_map(code: &GaussCode) -> Result<PlanarMap, DiagramError> {
+    let count = code.crossing_count();
+    let positions = 2 * count;
+    let visits = code.visits();
+
+    // Reflecting the whole map gives another embedding, so the first crossing can be left fixed
+    for choice in 0..(1u64 << (count - 1)) {
+        let rotations = visits
+            .iter()
+            .enumerate()
+            .map(|(crossing, &(first, second))| {
+                let in_first = backward((first + positions - 1) % positions);
+                let out_first = forward(first);
+                let in_second = backward((second + positions - 1) % positions);
+                let out_second = forward(second);
+                if crossing > 0 && (choice >> (crossing - 1)) & 1 == 1 {
+                    [in_first, in_second, out_first, out_second]
+                } else {
+                    [in_first, out_second, out_first, in_second]
+                }
+            }).collect::<Vec<_>>();
+
+        // Euler's formula, with `count` vertices and `2 * count` edges
+        let faces = trace_faces(&rotations, positions);
+        if faces.len() == count + 2 {
+            return Ok(PlanarMap { faces });
+        }
+    }
+    Err(DiagramError::NonPlanar)
+}
+
+/// Lay out the map with the crossings and the midpoints of the edges at equilibrium, as in Tutte's
+/// embedding.  Every face other than `outer` gets an extra vertex connected to everything around
+/// it, and the vertices around `outer` are fixed to a regular polygon inscribed in the unit circle.
+/// Returns the positions of the crossings followed by those of the midpoints, or `None` if the
+/// outer face meets a vertex twice.
+fn tutte_layout(code: &GaussCode, map: &PlanarMap, outer: usize) -> Option<Vec<Point2<f64>>> {
+    let count = code.crossing_count();
+    let positions = 2 * count;
+    let midpoint = |edge: usize| count + edge;
+    let center = |face: usize| count + positions + face;
+    let vertex_count = count + positions + map.faces.len();
+
+    let mut neighbors = vec![Vec::new(); vertex_count];
+    {
+        let mut link = |a: usize, b: usize| {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+        };
+        for edge in 0..positions {
+            link(code.crossing_at(edge), midpoint(edge));
+            link(midpoint(edge), code.crossing_at(edge + 1));
+        }
+        for (face, darts) in map.faces.iter().enumerate() {
+            if face == outer {
+                continue;
+            }
+            for &dart in darts {
+                link(center(face), code.crossing_at(tail(dart, positions)));
+                link(center(face), midpoint(edge(dart)));
+            }
+        }
+    }
+
+    let boundary = map.faces[outer]
+        .iter()
+        .flat_map(|&dart| {
+            vec![
+                code.crossing_at(tail(dart, positions)),
+                midpoint(edge(dart)),
+            ]
+        }).collect::<Vec<_>>();
+    let mut fixed = vec![None; vertex_count];
+    for (i, &vertex) in boundary.iter().enumerate() {
+        if fixed[vertex].is_some() {
+            return None;
+        }
+        let angle = 2.0 * PI * (i as f64) / (boundary.len() as f64);
+        fixed[vertex] = Some(Point2::new(angle.cos(), angle.sin()));
+    }
+
+    let free = (0..vertex_count)
+        .filter(|&vertex| fixed[vertex].is_none() && vertex != center(outer))
+        .collect::<Vec<_>>();
+    let mut index = vec![0; vertex_count];
+    for (i, &vertex) in free.iter().enumerate() {
+        index[vertex] = i;
+    }
+
+    // Every free vertex is at the average of its neighbors
+    let mut lhs = DMatrix::zeros(free.len(), free.len());
+    let mut rhs = DMatrix::zeros(free.len(), 2);
+    for (i, &vertex) in free.iter().enumerate() {
+        lhs[(i, i)] = neighbors[vertex].len() as f64;
+        for &neighbor in &neighbors[vertex] {
+            match fixed[neighbor] {
+                Some(point) => {
+                    rhs[(i, 0)] += point.x;
+                    rhs[(i, 1)] += point.y;
+                }
+                None => lhs[(i, index[neighbor])] -= 1.0,
+            }
+        }
+    }
+    let solution = lhs.lu().solve(&rhs)?;
+
+    Some(
+        (0..count + positions)
+            .map(|vertex| {
+                fixed[vertex].unwrap_or_else(|| {
+                    Point2::new(solution[(index[vertex], 0)], solution[(index[vertex], 1)])
+                })
+            }).collect(),
+    )
+}
+
+fn approx_eq(a: &Point3<f64>, b: &Point3<f64>) -> bool {
+    (a - b).norm() <= TOLERANCE
+}
+
+/// A closed curve following a knot diagram, with dihedral symmetry about the z axis.
+#[derive(Clone, Debug)]
+pub struct SymmetricEmbedding {
+    /// The crossings and the midpoints of the edges between them, alternating, in the order met
+    /// along the knot.  Crossings are lifted above or below the plane, depending on whether they
+    /// are passed over or under, and fit within the unit circle.
+    pub points: Vec<Point3<f64>>,
+
+    /// The `n` of the dihedral-n symmetry.
+    pub symmetry: u32,
+
+    /// The skip relating each piece of the curve to the next, as in `symmetry::adjacent_symmetry`.
+    pub skip: u32,
+
+    /// The index into `points` of the start of the first piece, on the positive x axis.
+    start: usize,
+}
+
+impl SymmetricEmbedding {
+    /// Lay out the diagram with dihedral-`symmetry` symmetry, with crossings lifted by `lift`.
+    /// Every face of the diagram is tried as the outside of the layout, so the diagram must have
+    /// the symmetry about the center of one of its faces.
+    pub fn new(code: &GaussCode, symmetry: u32, lift: f64) -> Result<Self, DiagramError> {
+        let map = planar_map(code)?;
+        let positions = 2 * code.crossing_count();
+        for outer in 0..map.faces.len() {
+            if map.faces[outer].len() % (symmetry as usize) != 0 {
+                continue;
+            }
+            if let Some(layout) = tutte_layout(code, &map, outer) {
+                let points = (0..positions)
+                    .flat_map(|position| {
+                        let crossing = layout[code.crossing_at(position)];
+                        let height = lift * (code.sequence()[position].signum() as f64);
+                        let midpoint = layout[code.crossing_count() + position];
+                        vec![
+                            Point3::new(crossing.x, crossing.y, height),
+                            Point3::new(midpoint.x, midpoint.y, 0.0),
+                        ]
+                    }).collect::<Vec<_>>();
+                if let Some(embedding) = SymmetricEmbedding::symmetric(points, symmetry) {
+                    return Ok(embedding);
+                }
+            }
+        }
+        Err(DiagramError::NoSymmetricLayout(symmetry))
+    }
+
+    /// Check that the points have the symmetry, and rotate them so that the curve starts on the
+    /// positive x axis.
+    fn symmetric(points: Vec<Point3<f64>>, symmetry: u32) -> Option<Self> {
+        let len = points.len();
+        // Each piece must end at the midpoint of an edge, like it starts
+        if len % (4 * symmetry as usize) != 0 {
+            return None;
+        }
+        let piece_len = len / (2 * symmetry as usize);
+
+        let rotation =
+            UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 2.0 * PI / symmetry as f64);
+        let rotates = (0..len).any(|shift| {
+            (0..len).all(|i| approx_eq(&(rotation * points[i]), &points[(i + shift) % len]))
+        });
+        if !rotates {
+            return None;
+        }
+
+        // A flip about an axis through the midpoint of an edge, reversing the direction of the knot
+        for start in (1..len).step_by(2) {
+            let angle = points[start].y.atan2(points[start].x);
+            let flip = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angle)
+                * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI)
+                * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -angle);
+            let flips = points[start].z.abs() <= TOLERANCE
+                && (points[start].x.hypot(points[start].y) > TOLERANCE)
+                && (0..len).all(|i| {
+                    approx_eq(&(flip * points[i]), &points[(2 * start + len - i) % len])
+                });
+            if !flips {
+                continue;
+            }
+
+            let align = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -angle);
+            let points = points.iter().map(|&point| align * point).collect::<Vec<_>>();
+            let end = points[(start + piece_len) % len];
+            let skip = (end.y.atan2(end.x) / PI * (symmetry as f64)).round() as i64;
+            let skip = ((skip % symmetry as i64) + symmetry as i64) % symmetry as i64;
+            return Some(SymmetricEmbedding {
+                points,
+                symmetry,
+                skip: skip as u32,
+                start,
+            });
+        }
+        None
+    }
+
+    /// The length of each piece of the curve, counted in points.
+    fn piece_len(&self) -> f64 {
+        (self.points.len() / (2 * self.symmetry as usize)) as f64
+    }
+
+    /// The smooth closed curve through the points, as a uniform Catmull-Rom spline, at a position
+    /// measured in points from the start of the first piece.
+    pub fn at(&self, position: f64) -> Point3<f64> {
+        let len = self.points.len() as i64;
+        let base = position.floor();
+        let t = position - base;
+        let point = |offset: i64| {
+            let i = self.start as i64 + base as i64 + offset;
+            self.points[(((i % len) + len) % len) as usize].coords
+        };
+        let (p0, p1, p2, p3) = (point(-1), point(0), point(1), point(2));
+        Point3::from(
+            p1 + 0.5
+                * ((p2 - p0) * t
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t * t
+                    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t * t * t),
+        )
+    }
+
+    /// The first piece of the curve, for `t` from 0 to 1.  It runs between adjacent axes of
+    /// symmetry, and its symmetric copies make up the whole curve, as for the parametric curves in
+    /// `geometries`.
+    pub fn piece(&self, t: f64) -> Point3<f64> {
+        self.at(t * self.piece_len())
+    }
+
+    /// The whole curve, with `samples` points between each pair of consecutive points.
+    pub fn sample(&self, samples: usize) -> Vec<Point3<f64>> {
+        (0..self.points.len() * samples)
+            .map(|i| self.at((i as f64) / (samples as f64)))
+            .collect()
+    }
+
+    /// The symmetries of the curve, in the order produced by `symmetry::symmetries_with_skip`.
+    pub fn symmetries(&self) -> Vec<UnitQuaternion<f64>> {
+        symmetries_with_skip(self.symmetry, self.skip).collect()
+    }
+}
+
+/// A chain following the first piece of the embedding, scaled up by `scale`.
+pub fn chain(
+    embedding: &SymmetricEmbedding,
+    scale: f64,
+    arc_len_step: f64,
+    cost_params: CostParams,
+    return_to_initial_weight: f64,
+    descent_rate: f64,
+) -> Chain {
+    let joints = from_curve_natural_parameterize(
+        arc_len_step,
+        0.001, // dt
+        0.0,   // start
+        1.0,   // end
+        |t| embedding.piece(t) * scale,
+    ).collect::<Vec<_>>();
+    Chain::new(
+        // spec
+        defaults::joint_spec(),
+        // num angles
+        defaults::NUM_ANGLES,
+        // pre-phantom
+        PhantomJoint {
+            symmetry: UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI).to_superset(),
+            index: 0,
+            leg: Leg::Incoming,
+        },
+        // post-phantom
+        PhantomJoint {
+            symmetry: adjacent_symmetry(embedding.symmetry, embedding.skip).to_superset(),
+            index: joints.len() - 1,
+            leg: Leg::Outgoing,
+        },
+        // cost params
+        cost_params,
+        // 'return to initial' weight
+        return_to_initial_weight,
+        // descent rate
+        descent_rate,
+        // steps
+        iso_adj::Steps::new_uniform(0.000001),
+        // joints
+        joints,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use geometries::from_diagram::*;
+
+    use defaults::continuous_optimization::{COST_PARAMS, RATE, RETURN_TO_INITIAL_WEIGHT};
+    use defaults::diagram::{ARC_LENGTH_STEP, LIFT, SCALE};
+    use report::JointsParity;
+    use topology::{closed_polyline, Invariants, KnotType};
+
+    fn knot_type(code: &GaussCode, symmetry: u32) -> Option<KnotType> {
+        let embedding = SymmetricEmbedding::new(code, symmetry, LIFT).unwrap();
+        Invariants::new(&embedding.sample(8)).knot_type
+    }
+
+    #[test]
+    fn parse_codes() {
+        assert_eq!(parse_code("[4, 6, 2]"), Ok(vec![4, 6, 2]));
+        assert_eq!(parse_code("1 -2 3"), Ok(vec![1, -2, 3]));
+        assert!(parse_code("1 two").is_err());
+
+        let trefoil = GaussCode::from_dowker(&[4, 6, 2]).unwrap();
+        assert_eq!(trefoil.sequence(), &[1, -3, 2, -1, 3, -2]);
+        assert_eq!("1 -3 2 -1 3 -2".parse::<GaussCode>(), Ok(trefoil));
+
+        assert!(GaussCode::from_dowker(&[4, 6, 3]).is_err());
+        assert!(GaussCode::from_dowker(&[4, 4, 2]).is_err());
+        assert!("1 -2 1 -2".parse::<GaussCode>().is_err());
+        assert!("1 2 -1 -3".parse::<GaussCode>().is_err());
+    }
+
+    #[test]
+    fn non_planar() {
+        let code = "1 -2 3 -1 2 -3".parse::<GaussCode>().unwrap();
+        assert!(planar_map(&code).is_ok());
+        // In a planar diagram, an even number of entries separates the two visits to a crossing
+        let code = "1 -2 -1 2".parse::<GaussCode>().unwrap();
+        assert_eq!(
+            SymmetricEmbedding::new(&code, 2, LIFT).err(),
+            Some(DiagramError::NonPlanar)
+        );
+    }
+
+    #[test]
+    fn symmetric_layouts() {
+        let trefoil = GaussCode::from_dowker(&[4, 6, 2]).unwrap();
+        assert_eq!(knot_type(&trefoil, 3), Some(KnotType::Trefoil));
+        assert_eq!(
+            SymmetricEmbedding::new(&trefoil, 2, LIFT).err(),
+            Some(DiagramError::NoSymmetricLayout(2))
+        );
+
+        let cinquefoil = GaussCode::from_dowker(&[6, 8, 10, 2, 4]).unwrap();
+        assert_eq!(knot_type(&cinquefoil, 5), Some(KnotType::Cinquefoil));
+
+        // The closure of the braid (s_1 s_2)^4
+        let torus_3_4 = "1 2 -4 -5 7 8 -2 -3 5 6 -8 -1 3 4 -6 -7".parse::<GaussCode>().unwrap();
+        assert_eq!(knot_type(&torus_3_4, 4), Some(KnotType::Knot8_19));
+    }
+
+    #[test]
+    fn symmetric_chain() {
+        let trefoil = GaussCode::from_dowker(&[4, 6, 2]).unwrap();
+        let embedding = SymmetricEmbedding::new(&trefoil, 3, LIFT).unwrap();
+        let start = embedding.piece(0.0);
+        assert_relative_eq!(start.y, 0.0, epsilon = 1e-9);
+        assert_relative_eq!(start.z, 0.0, epsilon = 1e-9);
+
+        let chain = chain(
+            &embedding,
+            SCALE,
+            ARC_LENGTH_STEP,
+            COST_PARAMS,
+            RETURN_TO_INITIAL_WEIGHT,
+            RATE,
+        );
+        let symmetries = embedding
+            .symmetries()
+            .iter()
+            .map(|quat| quat.to_superset())
+            .collect::<Vec<_>>();
+        let points = closed_polyline(&chain.spec, JointsParity::Even, &chain.joints, &symmetries);
+        assert_eq!(Invariants::new(&points).knot_type, Some(KnotType::Trefoil));
+    }
+}
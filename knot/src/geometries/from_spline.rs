@@ -73,7 +73,8 @@ pub fn generic_chain<F: Fn() -> bspline::BSpline<Point>>(
     descent_rate: f64,
     spec: JointSpec,
     spline_gen: F,
-    knot_sym: u32
+    knot_sym: u32,
+    skip: u32,
 ) -> Chain {
     let arclen = 1.1*(spec.dist_in() + spec.dist_out());
     let spline_iter = from_spline(
@@ -97,7 +98,7 @@ pub fn generic_chain<F: Fn() -> bspline::BSpline<Point>>(
         },
         // post-phantom
         PhantomJoint {
-            symmetry: adjacent_symmetry(3, 4).to_superset(),
+            symmetry: adjacent_symmetry(knot_sym, skip).to_superset(),
             index: chain_size - 1,
             leg: Leg::Outgoing,
         },
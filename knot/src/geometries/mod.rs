@@ -1,6 +1,8 @@
 extern crate kiss3d;
 
 pub mod curve_9_40;
+pub mod from_diagram;
+pub mod presets;
 pub mod input_curve;
 pub mod trefoil_curve;
 pub mod trefoil_spline;
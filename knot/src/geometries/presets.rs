@@ -0,0 +1,261 @@
+//! Named starting curves for symmetric knots, each with the symmetry it is built with.
+//!
+//! A chain needs at least four symmetric copies to close up, so every preset has a dihedral
+//! symmetry of order at least two.  Knots whose minimal diagrams lack such a symmetry, such as the
+//! three-twist knot 5_2, are laid out from a larger diagram which has it.
+
+use alga::general::SubsetOf;
+use nalgebra::UnitQuaternion;
+
+use cost::CostParams;
+use defaults::continuous_optimization::{
+    MAX_REPULSION_STRENGTH, REPULSION_EXPONENT, REPULSION_STRENGTH,
+};
+use defaults::diagram::{ARC_LENGTH_STEP, LIFT};
+use optimize_tools::{Chain, RepulsionChain};
+use report::Transform;
+use symmetry::symmetries_with_skip;
+use topology::KnotType;
+
+use geometries::curve_9_40;
+use geometries::from_diagram::{self, GaussCode, SymmetricEmbedding};
+
+/// Where the starting curve of a preset comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    /// The curve of `curve_9_40`.
+    Curve9_40,
+
+    /// A diagram given by its Dowker-Thistlethwaite code, laid out by `from_diagram`.
+    Dowker(&'static [i32]),
+
+    /// A diagram given by its Gauss code, laid out by `from_diagram`.
+    Gauss(&'static [i32]),
+}
+
+/// A symmetric knot to start optimizing from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Preset {
+    /// The name of the preset, which is the Alexander-Briggs name of its knot.
+    pub name: &'static str,
+
+    /// The knot the chain of the preset closes up to.
+    pub knot_type: KnotType,
+
+    /// The `n` of the dihedral-n symmetry.
+    pub symmetry: u32,
+
+    /// The skip relating each copy of the chain to the next, as in `symmetry::adjacent_symmetry`.
+    pub skip: u32,
+
+    /// The scale the curve is built at, large enough for the chain to keep the knot type.
+    pub scale: f64,
+
+    /// The number of joints in each copy of the chain.
+    pub chain_size: usize,
+
+    pub source: Source,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "3_1",
+        knot_type: KnotType::Trefoil,
+        symmetry: 3,
+        skip: 1,
+        scale: 8.0,
+        chain_size: 6,
+        source: Source::Dowker(&[4, 6, 2]),
+    },
+    Preset {
+        name: "4_1",
+        knot_type: KnotType::FigureEight,
+        symmetry: 2,
+        skip: 1,
+        scale: 12.0,
+        chain_size: 13,
+        source: Source::Dowker(&[4, 6, 8, 2]),
+    },
+    Preset {
+        name: "5_1",
+        knot_type: KnotType::Cinquefoil,
+        symmetry: 5,
+        skip: 3,
+        scale: 12.0,
+        chain_size: 6,
+        source: Source::Dowker(&[6, 8, 10, 2, 4]),
+    },
+    // Five crossings cannot be laid out with a rotation by half a turn, so this is a diagram with a
+    // sixth crossing, which can
+    Preset {
+        name: "5_2",
+        knot_type: KnotType::ThreeTwist,
+        symmetry: 2,
+        skip: 1,
+        scale: 32.0,
+        chain_size: 37,
+        source: Source::Gauss(&[-1, -2, 3, 1, -4, 5, -6, -3, 2, 6, -5, 4]),
+    },
+    // The closure of the braid (σ1 σ2^-1)^4
+    Preset {
+        name: "8_18",
+        knot_type: KnotType::Knot8_18,
+        symmetry: 4,
+        skip: 1,
+        scale: 16.0,
+        chain_size: 12,
+        source: Source::Gauss(&[1, -2, 4, -5, 7, -8, 2, -3, 5, -6, 8, -1, 3, -4, 6, -7]),
+    },
+    Preset {
+        name: "8_19",
+        knot_type: KnotType::Knot8_19,
+        symmetry: 3,
+        skip: 1,
+        scale: 0.7,
+        chain_size: 8,
+        source: Source::Curve9_40,
+    },
+    // The closure of the braid (σ1 σ2^-1 σ3)^3
+    Preset {
+        name: "9_40",
+        knot_type: KnotType::Knot9_40,
+        symmetry: 3,
+        skip: 2,
+        scale: 24.0,
+        chain_size: 24,
+        source: Source::Gauss(&[
+            1, -2, 3, -6, 8, -1, 4, -5, 6, -9, 2, -4, 7, -8, 9, -3, 5, -7,
+        ]),
+    },
+];
+
+/// Find a preset by its name, or by the common name of its knot, such as "trefoil".
+pub fn find(name: &str) -> Result<&'static Preset, String> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name == name)
+        .or_else(|| {
+            name.parse::<KnotType>()
+                .ok()
+                .and_then(|knot_type| PRESETS.iter().find(|preset| preset.knot_type == knot_type))
+        }).ok_or_else(|| {
+            let names = PRESETS
+                .iter()
+                .map(|preset| preset.name)
+                .collect::<Vec<_>>();
+            format!("Unknown preset {} (expected one of {})", name, names.join(", "))
+        })
+}
+
+impl Preset {
+    /// The diagram of the preset, if it is built from one.
+    pub fn code(&self) -> Option<GaussCode> {
+        let code = match self.source {
+            Source::Curve9_40 => return None,
+            Source::Dowker(code) => GaussCode::from_dowker(code),
+            Source::Gauss(code) => GaussCode::new(code.to_vec()),
+        };
+        Some(code.expect("Invalid preset code"))
+    }
+
+    /// The symmetric layout of the diagram of the preset, if it is built from one.
+    pub fn embedding(&self) -> Option<SymmetricEmbedding> {
+        self.code().map(|code| {
+            SymmetricEmbedding::new(&code, self.symmetry, LIFT)
+                .expect("Preset diagram has no symmetric layout")
+        })
+    }
+
+    /// The symmetries of the preset, in the order produced by `symmetry::symmetries_with_skip`.
+    pub fn symmetries(&self) -> impl Iterator<Item = UnitQuaternion<f64>> {
+        symmetries_with_skip(self.symmetry, self.skip)
+    }
+
+    /// The symmetries of the preset as stored in a `KnotGeometry`.
+    pub fn transforms(&self) -> Vec<Transform> {
+        self.symmetries()
+            .map(|quat| Transform::from_isometry(quat.to_superset()))
+            .collect()
+    }
+
+    /// A chain following the first piece of the starting curve, repelled by its copies under the
+    /// symmetries of the preset as in continuous optimization.
+    pub fn repulsion_chain(
+        &self,
+        cost_params: CostParams,
+        return_to_initial_weight: f64,
+        descent_rate: f64,
+    ) -> RepulsionChain {
+        RepulsionChain::new(
+            self.chain(cost_params, return_to_initial_weight, descent_rate),
+            self.symmetries().map(|quat| quat.to_superset()).collect(),
+            REPULSION_EXPONENT,
+            REPULSION_STRENGTH,
+            MAX_REPULSION_STRENGTH,
+        )
+    }
+
+    /// A chain following the first piece of the starting curve.
+    pub fn chain(
+        &self,
+        cost_params: CostParams,
+        return_to_initial_weight: f64,
+        descent_rate: f64,
+    ) -> Chain {
+        match self.embedding() {
+            Some(embedding) => from_diagram::chain(
+                &embedding,
+                self.scale,
+                ARC_LENGTH_STEP,
+                cost_params,
+                return_to_initial_weight,
+                descent_rate,
+            ),
+            None => curve_9_40::chain(
+                self.chain_size,
+                self.scale,
+                cost_params,
+                return_to_initial_weight,
+                descent_rate,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geometries::presets::*;
+
+    use alga::general::SubsetOf;
+
+    use defaults::continuous_optimization::{COST_PARAMS, RATE, RETURN_TO_INITIAL_WEIGHT};
+    use report::JointsParity;
+    use topology::{closed_polyline, Invariants};
+
+    #[test]
+    fn find_presets() {
+        assert_eq!(find("9_40").unwrap().knot_type, KnotType::Knot9_40);
+        assert_eq!(find("trefoil").unwrap().name, "3_1");
+        assert_eq!(find("three-twist").unwrap().name, "5_2");
+        assert!(find("9_41").is_err());
+    }
+
+    #[test]
+    fn presets_close_up() {
+        for preset in PRESETS {
+            if let Some(embedding) = preset.embedding() {
+                assert_eq!(embedding.skip, preset.skip, "{}", preset.name);
+            }
+            let chain = preset.chain(COST_PARAMS, RETURN_TO_INITIAL_WEIGHT, RATE);
+            assert_eq!(chain.joints.len(), preset.chain_size, "{}", preset.name);
+
+            let symmetries = preset
+                .symmetries()
+                .map(|quat| quat.to_superset())
+                .collect::<Vec<_>>();
+            let points =
+                closed_polyline(&chain.spec, JointsParity::Even, &chain.joints, &symmetries);
+            assert!(Invariants::new(&points).matches(preset.knot_type), "{}", preset.name);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::f64::consts::PI;
+
+use alga::general::SubsetOf;
+use nalgebra::Isometry3;
+
+use cost::{ConnectionCost, CostParams, Costs};
+use energy::mobius_energy;
+use joint::{at_angles, discrete_symmetric_angles, JointSpec, RelativeJoint};
+use lbfgs::{minimize, LbfgsParams, Objective, OptimizationReport};
+use report::{JointsParity, KnotGeometry, Transform};
+use symmetry::symmetries_with_skip;
+use symmetry_adjust::{Problem, Vars};
+
+/// The step used to approximate partial derivatives of the cost by central differences, in radians
+/// for angles and in the units of the joint geometry for the radius.
+const DIFFERENCE_STEP: f64 = 1e-6;
+
+/// A chain of joints parameterized by internal coordinates: the continuous twist of each joint
+/// about the axis of the previous one, and the placement of the whole chain relative to the axes of
+/// symmetry.  Consecutive joints are connected exactly by construction, so only the closure of the
+/// chain with its symmetric copies, the locking angles and (optionally) repulsion are optimized.
+///
+/// Joints are laid out as in `report::knot_geometry`: for even parity the first joint is attached
+/// to its own mirror image, and for odd parity the chain begins with a joint straddling the axis of
+/// symmetry, which has no angle of its own.
+#[derive(Clone, Debug)]
+pub struct InternalChain<C = CostParams> {
+    pub spec: JointSpec,
+    pub num_angles: u16,
+    pub parity: JointsParity,
+    pub symmetry_count: u32,
+    pub symmetry_skip: u32,
+    pub cost_params: C,
+
+    /// The rotation of each joint about its "in" axis, in radians, as in `RelativeJoint::angle`.
+    /// For even parity the first angle is half the twist between the first joint and its mirror
+    /// image, as in `discrete_symmetric_angles`.
+    pub angles: Vec<f64>,
+
+    pub symmetry_adjust: Vars,
+
+    /// The weight assigned to the Möbius energy of the symmetrized chain in its total cost.
+    pub energy_weight: f64,
+
+    symmetries: Vec<Isometry3<f64>>,
+}
+
+impl<C: ConnectionCost> InternalChain<C> {
+    pub fn new(
+        spec: JointSpec,
+        num_angles: u16,
+        parity: JointsParity,
+        symmetry_count: u32,
+        symmetry_skip: u32,
+        cost_params: C,
+        angles: Vec<f64>,
+        symmetry_adjust: Vars,
+    ) -> Self {
+        assert!(!angles.is_empty(), "Chain must have at least one angle");
+        InternalChain {
+            spec,
+            num_angles,
+            parity,
+            symmetry_count,
+            symmetry_skip,
+            cost_params,
+            angles,
+            symmetry_adjust,
+            energy_weight: 0.0,
+            symmetries: symmetries_with_skip(symmetry_count, symmetry_skip)
+                .map(|quat| quat.to_superset())
+                .collect(),
+        }
+    }
+
+    /// Construct a chain at the given discrete angles, in the form used by `KnotReport`, placed
+    /// relative to the axes of symmetry as well as possible.
+    pub fn from_discrete(
+        spec: JointSpec,
+        num_angles: u16,
+        parity: JointsParity,
+        symmetry_count: u32,
+        symmetry_skip: u32,
+        cost_params: C,
+        angles: &[i32],
+    ) -> Self
+    where
+        C: Clone,
+    {
+        let angles = discrete_symmetric_angles(spec, num_angles, parity, angles.iter().cloned())
+            .map(|joint| joint.angle)
+            .collect();
+        let mut chain = InternalChain::new(
+            spec,
+            num_angles,
+            parity,
+            symmetry_count,
+            symmetry_skip,
+            cost_params.clone(),
+            angles,
+            Vars {
+                radius: 0.0,
+                radial_angle: 0.0,
+            },
+        );
+
+        let last_joint_out = chain.local_joints().last().unwrap() * spec.origin_to_out();
+        let problem = Problem::new(
+            cost_params,
+            last_joint_out,
+            num_angles,
+            symmetry_count,
+            symmetry_skip,
+        );
+        chain.symmetry_adjust = problem.solve_direct().0;
+        chain
+    }
+
+    /// The joints of the chain, before being placed relative to the axes of symmetry.
+    fn local_joints(&self) -> Vec<Isometry3<f64>> {
+        let spec = self.spec;
+        let relative_joints = self
+            .angles
+            .iter()
+            .map(|&angle| RelativeJoint { spec, angle });
+        match self.parity {
+            JointsParity::Even => at_angles(relative_joints, Isometry3::identity()).collect(),
+            JointsParity::Odd => {
+                let mut joints = vec![spec.origin_to_symmetric()];
+                joints.extend(at_angles(
+                    relative_joints,
+                    spec.origin_to_symmetric() * spec.origin_to_out(),
+                ));
+                joints
+            }
+        }
+    }
+
+    /// The transformations of the joints of the chain.
+    pub fn joints(&self) -> Vec<Isometry3<f64>> {
+        let adjust = self.symmetry_adjust.transform();
+        self.local_joints()
+            .into_iter()
+            .map(|joint| adjust * joint)
+            .collect()
+    }
+
+    /// The costs of the connection between the last joint and its copy in the adjacent horseshoe.
+    pub fn closure_costs(&self) -> Costs {
+        let last_out = self.joints().last().unwrap() * self.spec.origin_to_out();
+        let last_copy_out = self.symmetries[3] * last_out;
+        self.cost_params
+            .costs_opposing(self.num_angles, &last_out, &last_copy_out)
+    }
+
+    /// The total cost of the chain: the cost of every connection, including those with its
+    /// symmetric copies, plus its weighted Möbius energy.  The connections within the chain only
+    /// contribute locking costs, because they are always exact.
+    pub fn total_cost(&self) -> f64 {
+        let joints = self.joints();
+        let spec = &self.spec;
+        let mut result = 0.0;
+
+        if let JointsParity::Even = self.parity {
+            let first_in = joints[0] * spec.origin_to_in();
+            result += self.cost_params.cost_opposing(
+                self.num_angles,
+                &(self.symmetries[1] * first_in),
+                &first_in,
+            );
+        }
+
+        for pair in joints.windows(2) {
+            result += self.cost_params.cost_aligned(
+                self.num_angles,
+                &(pair[0] * spec.origin_to_out()),
+                &(pair[1] * spec.origin_to_in()),
+            );
+        }
+
+        let last_out = joints[joints.len() - 1] * spec.origin_to_out();
+        let last_copy_out = self.symmetries[3] * last_out;
+        result += self
+            .cost_params
+            .cost_opposing(self.num_angles, &last_out, &last_copy_out);
+
+        if self.energy_weight != 0.0 {
+            let last = joints.len() - 1;
+            let bonded = |i: usize, sym_i: usize, j: usize| {
+                let neighbors_in_same_branch = sym_i == 0 && (i as isize - j as isize).abs() <= 1;
+                let neighbors_at_start = sym_i == 1 && i == 0 && j == 0;
+                let neighbors_at_end = sym_i == 3 && i == last && j == last;
+                neighbors_in_same_branch || neighbors_at_start || neighbors_at_end
+            };
+            result += self.energy_weight * mobius_energy(spec, &joints, &self.symmetries, bonded);
+        }
+
+        result
+    }
+
+    /// The nearest discrete angles to the chain's continuous angles, in the form used by
+    /// `KnotReport`.
+    pub fn nearest_discrete_angles(&self) -> Vec<i32> {
+        let step = 2.0 * PI / (self.num_angles as f64);
+        let num_angles = self.num_angles as i32;
+        self.angles
+            .iter()
+            .enumerate()
+            .map(|(i, &angle)| {
+                let steps = match (i, self.parity) {
+                    (0, JointsParity::Even) => 2.0 * angle / step,
+                    _ => angle / step,
+                };
+                (((steps.round() as i32) % num_angles) + num_angles) % num_angles
+            }).collect()
+    }
+
+    /// Minimize `total_cost` with L-BFGS over the angles and placement of the chain.
+    pub fn lbfgs_optimize(&mut self, params: &LbfgsParams) -> OptimizationReport
+    where
+        C: Clone,
+    {
+        minimize(self, params)
+    }
+
+    fn set_param(&mut self, index: usize, value: f64) {
+        let angle_count = self.angles.len();
+        if index < angle_count {
+            self.angles[index] = value;
+        } else if index == angle_count {
+            self.symmetry_adjust.radius = value;
+        } else {
+            self.symmetry_adjust.radial_angle = value;
+        }
+    }
+
+    fn param(&self, index: usize) -> f64 {
+        let angle_count = self.angles.len();
+        if index < angle_count {
+            self.angles[index]
+        } else if index == angle_count {
+            self.symmetry_adjust.radius
+        } else {
+            self.symmetry_adjust.radial_angle
+        }
+    }
+}
+
+impl InternalChain<CostParams> {
+    /// A geometry describing the chain, suitable for export.
+    pub fn geometry(&self) -> KnotGeometry {
+        KnotGeometry {
+            joint_spec: self.spec,
+            num_angles: self.num_angles,
+            cost_params: self.cost_params,
+            parity: self.parity,
+            symmetries: self
+                .symmetries
+                .iter()
+                .map(|&sym| Transform::from_isometry(sym))
+                .collect(),
+            transforms: self
+                .joints()
+                .into_iter()
+                .map(Transform::from_isometry)
+                .collect(),
+            constraints: Vec::new(),
+        }
+    }
+}
+
+/// The parameters are the angles of the chain followed by its radius and radial angle.
+impl<C: ConnectionCost + Clone> Objective for InternalChain<C> {
+    type Point = (Vec<f64>, Vars);
+
+    fn point(&self) -> (Vec<f64>, Vars) {
+        (self.angles.clone(), self.symmetry_adjust)
+    }
+
+    fn set_point(&mut self, point: (Vec<f64>, Vars)) {
+        self.angles = point.0;
+        self.symmetry_adjust = point.1;
+    }
+
+    fn cost_and_gradient(&self) -> (f64, Vec<f64>) {
+        let cost = self.total_cost();
+
+        // Every parameter affects the closure, so there is little to gain from anything cleverer
+        // than central differences.
+        let mut probe = self.clone();
+        let gradient = (0..self.angles.len() + 2)
+            .map(|i| {
+                let value = self.param(i);
+                probe.set_param(i, value + DIFFERENCE_STEP);
+                let cost_plus = probe.total_cost();
+                probe.set_param(i, value - DIFFERENCE_STEP);
+                let cost_minus = probe.total_cost();
+                probe.set_param(i, value);
+                (cost_plus - cost_minus) / (2.0 * DIFFERENCE_STEP)
+            }).collect();
+
+        (cost, gradient)
+    }
+
+    fn retract(&mut self, step: &[f64]) {
+        for (i, delta) in step.iter().enumerate() {
+            let value = self.param(i);
+            self.set_param(i, value + delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use internal_chain::*;
+
+    use defaults;
+    use defaults::continuous_optimization::LBFGS_PARAMS;
+    use defaults::{COST_PARAMS, NUM_ANGLES};
+    use validate::junction_residuals;
+
+    const ANGLES: [i32; 5] = [3, 0, 5, 1, 14];
+
+    fn chain(parity: JointsParity) -> InternalChain {
+        InternalChain::from_discrete(
+            defaults::joint_spec(),
+            NUM_ANGLES,
+            parity,
+            3,
+            2,
+            COST_PARAMS,
+            &ANGLES,
+        )
+    }
+
+    #[test]
+    fn connected_by_construction() {
+        let mut chain = chain(JointsParity::Even);
+        for (i, angle) in chain.angles.iter_mut().enumerate() {
+            *angle += 0.1 * (i as f64 + 1.0);
+        }
+
+        let junctions = junction_residuals(&chain.geometry()).unwrap();
+        assert_eq!(junctions.len(), ANGLES.len() + 1);
+        for junction in &junctions[..ANGLES.len()] {
+            assert_relative_eq!(junction.costs.dist, 0.0, epsilon = 1e-12);
+            assert_relative_eq!(junction.costs.axis, 0.0, epsilon = 1e-12);
+        }
+        assert_relative_eq!(
+            junctions[ANGLES.len()].costs.dist,
+            chain.closure_costs().dist,
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn discrete_round_trip() {
+        for &parity in &[JointsParity::Even, JointsParity::Odd] {
+            let chain = chain(parity);
+            assert_eq!(chain.nearest_discrete_angles(), ANGLES.to_vec());
+
+            // Only the closure contributes any cost at exactly discrete angles
+            let closure = chain.closure_costs();
+            assert_relative_eq!(chain.total_cost(), COST_PARAMS.weigh(&closure), epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn optimization_decreases_cost() {
+        let mut chain = chain(JointsParity::Even);
+        for (i, angle) in chain.angles.iter_mut().enumerate() {
+            *angle += 0.02 * (i as f64 - 2.0);
+        }
+        let initial_cost = chain.total_cost();
+        let report = chain.lbfgs_optimize(&LbfgsParams {
+            max_iterations: 100,
+            ..LBFGS_PARAMS
+        });
+        assert!(report.final_cost < initial_cost);
+        assert_relative_eq!(report.final_cost, chain.total_cost(), epsilon = 1e-12);
+    }
+}
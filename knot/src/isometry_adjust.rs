@@ -1,3 +1,5 @@
+use std::ops::Add;
+
 use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
 
 #[derive(Clone, Copy, Debug)]
@@ -86,6 +88,48 @@ pub fn differentiate<F: Fn(&Isometry3<f64>) -> f64>(
 }
 
 impl IsometryDifferential {
+    pub fn zero() -> Self {
+        IsometryDifferential {
+            d_x: 0.0,
+            d_y: 0.0,
+            d_z: 0.0,
+
+            d_i: 0.0,
+            d_j: 0.0,
+            d_k: 0.0,
+        }
+    }
+
+    /// The differential of a function with respect to moving a coordinate system, given the rates
+    /// of change of the function as a point rigidly attached to the coordinate system is translated
+    /// and as the coordinate system is rotated about that point.  Rotations are measured as
+    /// rotation vectors in world coordinates, and `offset` is the displacement of the point from
+    /// the origin of the coordinate system.
+    pub fn from_rates_at(
+        offset: &Vector3<f64>,
+        d_translation: &Vector3<f64>,
+        d_rotation: &Vector3<f64>,
+    ) -> Self {
+        // Rotating about the origin also sweeps the point around it
+        let d_rotation = d_rotation + offset.cross(d_translation);
+        IsometryDifferential {
+            d_x: d_translation.x,
+            d_y: d_translation.y,
+            d_z: d_translation.z,
+
+            d_i: d_rotation.x,
+            d_j: d_rotation.y,
+            d_k: d_rotation.z,
+        }
+    }
+
+    /// Express the differential in coordinates rotated by `rotation`.
+    pub fn rotate(&self, rotation: &UnitQuaternion<f64>) -> Self {
+        let d_translation = rotation * Vector3::new(self.d_x, self.d_y, self.d_z);
+        let d_rotation = rotation * Vector3::new(self.d_i, self.d_j, self.d_k);
+        IsometryDifferential::from_rates_at(&Vector3::zeros(), &d_translation, &d_rotation)
+    }
+
     pub fn scale(&self, factor: f64) -> Self {
         IsometryDifferential {
             d_x: self.d_x * factor,
@@ -106,6 +150,22 @@ impl IsometryDifferential {
     }
 }
 
+impl Add for IsometryDifferential {
+    type Output = IsometryDifferential;
+
+    fn add(self, other: IsometryDifferential) -> IsometryDifferential {
+        IsometryDifferential {
+            d_x: self.d_x + other.d_x,
+            d_y: self.d_y + other.d_y,
+            d_z: self.d_z + other.d_z,
+
+            d_i: self.d_i + other.d_i,
+            d_j: self.d_j + other.d_j,
+            d_k: self.d_k + other.d_k,
+        }
+    }
+}
+
 pub fn apply_step(radius: f64, trans: &mut Isometry3<f64>, diff: &IsometryDifferential) {
     let delta_translation = Translation3::new(diff.d_x, diff.d_y, diff.d_z);
     let delta_rotation =
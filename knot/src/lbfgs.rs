@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+
+/// A function to be minimized over a space which is locally, but not necessarily globally,
+/// euclidean.  Steps are expressed as tangent vectors at the current point, in the same coordinates
+/// as the gradient.
+pub trait Objective {
+    type Point: Clone;
+
+    fn point(&self) -> Self::Point;
+
+    fn set_point(&mut self, point: Self::Point);
+
+    /// Compute the value of the function and its gradient at the current point.
+    fn cost_and_gradient(&self) -> (f64, Vec<f64>);
+
+    /// Move the current point along the given tangent vector.
+    fn retract(&mut self, step: &[f64]);
+}
+
+/// Configurable parameters for the L-BFGS optimizer.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LbfgsParams {
+    /// The number of previous steps used to approximate the inverse Hessian.
+    pub memory: usize,
+
+    pub max_iterations: u32,
+
+    /// Stop once the norm of the gradient falls below this value.
+    pub gradient_tolerance: f64,
+
+    /// Stop once an iteration decreases the cost by less than this fraction of its magnitude.
+    pub cost_tolerance: f64,
+
+    /// The length of the first step, before there is any curvature information to scale it.
+    pub initial_step: f64,
+
+    /// The fraction of the decrease predicted by the gradient which a step must achieve to be
+    /// accepted by the line search.
+    pub sufficient_decrease: f64,
+
+    /// The factor by which the line search shrinks a step which is not accepted.
+    pub backtrack_ratio: f64,
+
+    /// The number of times the line search may shrink a step before giving up.
+    pub max_backtracks: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Termination {
+    /// The gradient norm fell below `gradient_tolerance`.
+    GradientConverged,
+
+    /// The cost decreased by less than `cost_tolerance` in one iteration.
+    CostConverged,
+
+    /// No step along the search direction decreased the cost sufficiently.
+    LineSearchFailed,
+
+    MaxIterations,
+}
+
+/// A record of a run of the optimizer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OptimizationReport {
+    pub iterations: u32,
+    pub final_cost: f64,
+    pub final_gradient_norm: f64,
+
+    /// The cost before the first iteration and after every iteration.
+    pub cost_history: Vec<f64>,
+
+    pub termination: Termination,
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+fn norm(a: &[f64]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+/// A step `s` and the corresponding change in gradient `y`.
+struct Correction {
+    s: Vec<f64>,
+    y: Vec<f64>,
+    rho: f64,
+}
+
+/// Compute the search direction `-H g` by the L-BFGS two-loop recursion, where `H` approximates the
+/// inverse Hessian from the stored corrections (oldest first).
+fn search_direction(gradient: &[f64], corrections: &VecDeque<Correction>) -> Vec<f64> {
+    let mut q = gradient.to_vec();
+    let mut alphas = Vec::with_capacity(corrections.len());
+    for correction in corrections.iter().rev() {
+        let alpha = correction.rho * dot(&correction.s, &q);
+        for (q_i, y_i) in q.iter_mut().zip(correction.y.iter()) {
+            *q_i -= alpha * y_i;
+        }
+        alphas.push(alpha);
+    }
+
+    if let Some(latest) = corrections.back() {
+        let gamma = dot(&latest.s, &latest.y) / dot(&latest.y, &latest.y);
+        for q_i in q.iter_mut() {
+            *q_i *= gamma;
+        }
+    }
+
+    for (correction, alpha) in corrections.iter().zip(alphas.iter().rev()) {
+        let beta = correction.rho * dot(&correction.y, &q);
+        for (q_i, s_i) in q.iter_mut().zip(correction.s.iter()) {
+            *q_i += (alpha - beta) * s_i;
+        }
+    }
+
+    for q_i in q.iter_mut() {
+        *q_i = -*q_i;
+    }
+    q
+}
+
+/// Minimize an objective with the limited-memory BFGS method and a backtracking line search.  The
+/// objective is left at the best point found.
+pub fn minimize<O: Objective>(objective: &mut O, params: &LbfgsParams) -> OptimizationReport {
+    let (mut cost, mut gradient) = objective.cost_and_gradient();
+    let mut cost_history = vec![cost];
+    let mut corrections: VecDeque<Correction> = VecDeque::with_capacity(params.memory);
+
+    let mut iterations = 0;
+    let termination = loop {
+        if norm(&gradient) <= params.gradient_tolerance {
+            break Termination::GradientConverged;
+        }
+        if iterations >= params.max_iterations {
+            break Termination::MaxIterations;
+        }
+
+        let mut direction = search_direction(&gradient, &corrections);
+        let mut slope = dot(&gradient, &direction);
+        if corrections.is_empty() || slope >= 0.0 {
+            // Either no curvature information yet, or the approximation has broken down
+            corrections.clear();
+            let scale = params.initial_step / norm(&gradient);
+            direction = gradient.iter().map(|g| -g * scale).collect();
+            slope = dot(&gradient, &direction);
+        }
+
+        let start = objective.point();
+        let mut step_size = 1.0;
+        let mut accepted = None;
+        for _ in 0..=params.max_backtracks {
+            let step = direction.iter().map(|d| d * step_size).collect::<Vec<_>>();
+            objective.retract(&step);
+            let (new_cost, new_gradient) = objective.cost_and_gradient();
+            if new_cost <= cost + params.sufficient_decrease * step_size * slope {
+                accepted = Some((step, new_cost, new_gradient));
+                break;
+            }
+            objective.set_point(start.clone());
+            step_size *= params.backtrack_ratio;
+        }
+
+        let (step, new_cost, new_gradient) = match accepted {
+            Some(accepted) => accepted,
+            None => break Termination::LineSearchFailed,
+        };
+        iterations += 1;
+
+        let y = new_gradient
+            .iter()
+            .zip(gradient.iter())
+            .map(|(new, old)| new - old)
+            .collect::<Vec<_>>();
+        let curvature = dot(&step, &y);
+        // Skip updates which would make the approximate Hessian indefinite
+        if curvature > 1e-12 * norm(&step) * norm(&y) {
+            if corrections.len() == params.memory {
+                corrections.pop_front();
+            }
+            corrections.push_back(Correction {
+                s: step,
+                y,
+                rho: curvature.recip(),
+            });
+        }
+
+        let decrease = cost - new_cost;
+        cost = new_cost;
+        gradient = new_gradient;
+        cost_history.push(cost);
+
+        if decrease <= params.cost_tolerance * cost.abs().max(1.0) {
+            break Termination::CostConverged;
+        }
+    };
+
+    OptimizationReport {
+        iterations,
+        final_cost: cost,
+        final_gradient_norm: norm(&gradient),
+        cost_history,
+        termination,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lbfgs::*;
+
+    /// The Rosenbrock function, whose minimum at `(1, 1)` lies at the bottom of a narrow curved
+    /// valley which defeats plain gradient descent.
+    struct Rosenbrock {
+        x: f64,
+        y: f64,
+    }
+
+    impl Objective for Rosenbrock {
+        type Point = (f64, f64);
+
+        fn point(&self) -> (f64, f64) {
+            (self.x, self.y)
+        }
+
+        fn set_point(&mut self, point: (f64, f64)) {
+            self.x = point.0;
+            self.y = point.1;
+        }
+
+        fn cost_and_gradient(&self) -> (f64, Vec<f64>) {
+            let (x, y) = (self.x, self.y);
+            let cost = (1.0 - x).powi(2) + 100.0 * (y - x * x).powi(2);
+            let gradient = vec![
+                -2.0 * (1.0 - x) - 400.0 * x * (y - x * x),
+                200.0 * (y - x * x),
+            ];
+            (cost, gradient)
+        }
+
+        fn retract(&mut self, step: &[f64]) {
+            self.x += step[0];
+            self.y += step[1];
+        }
+    }
+
+    #[test]
+    fn rosenbrock() {
+        let params = LbfgsParams {
+            memory: 5,
+            max_iterations: 1000,
+            gradient_tolerance: 1e-8,
+            cost_tolerance: 0.0,
+            initial_step: 0.1,
+            sufficient_decrease: 1e-4,
+            backtrack_ratio: 0.5,
+            max_backtracks: 50,
+        };
+        let mut objective = Rosenbrock { x: -1.2, y: 1.0 };
+        let report = minimize(&mut objective, &params);
+
+        assert_eq!(report.termination, Termination::GradientConverged);
+        assert!(report.iterations < 200);
+        assert_eq!(report.cost_history.len(), report.iterations as usize + 1);
+        assert_relative_eq!(objective.x, 1.0, epsilon = 1e-6);
+        assert_relative_eq!(objective.y, 1.0, epsilon = 1e-6);
+    }
+}
@@ -13,17 +13,28 @@ extern crate serde_json;
 extern crate approx;
 
 pub mod approx_locking_angle;
+pub mod assembly;
+pub mod chirality;
 pub mod collision_grid;
 pub mod collision_grid_trivial;
+pub mod continuation;
+pub mod convergence;
 pub mod optimize_tools;
 pub mod cost;
 pub mod defaults;
+pub mod descriptors;
+pub mod energy;
 pub mod filter;
 pub mod geometries;
+pub mod internal_chain;
 pub mod isometry_adjust;
 pub mod joint;
+pub mod lbfgs;
 pub mod rand_problem;
 pub mod report;
+pub mod svg;
 pub mod symmetry;
 pub mod symmetry_adjust;
+pub mod topology;
+pub mod validate;
 pub mod visualize;
@@ -1,10 +1,24 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::f64::INFINITY;
+use std::fmt;
 use std::ops::{Deref, DerefMut};
 
 use nalgebra::{Isometry3, Point3, Translation3, Vector3};
 
-use cost::{cost_aligned, cost_opposing, CostParams};
+use approx_locking_angle::{twist_gradient_aligned, twist_gradient_opposing};
+use collision_grid::{
+    BoundedCollider, BoundingBox, Bounds, CheckCollision, CollisionGrid, CollisionIndex,
+};
+use cost::{ConnectionCost, CostParams, Costs};
+use defaults::continuous_optimization::{
+    MOBIUS_ENERGY_WEIGHT, REPULSION_CUTOFF_FRACTION, REPULSION_FAR_FIELD,
+};
+use energy::{mobius_energy, mobius_gradient};
 use isometry_adjust as iso_adj;
 use joint::JointSpec;
+use lbfgs::{minimize, LbfgsParams, Objective, OptimizationReport};
+use report::{recover_angles, JointsParity, RecoveredAngles};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Leg {
@@ -19,20 +33,199 @@ pub struct PhantomJoint {
     pub leg: Leg,
 }
 
+/// A restriction on how the optimizer may move a joint, e.g. to hold part of a design in place
+/// while the rest of the chain relaxes around it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JointConstraint {
+    Free,
+
+    /// The joint neither moves nor rotates.
+    FixedPose,
+
+    /// The joint may move and rotate, but only so as to preserve the twists at its junctions with
+    /// its neighbors, to first order.  This also restricts how those neighbors may rotate.
+    FixedTwist,
+}
+
+impl Default for JointConstraint {
+    fn default() -> Self {
+        JointConstraint::Free
+    }
+}
+
+impl JointConstraint {
+    /// Remove the components of a differential of the joint which the constraint forbids on its
+    /// own.  The twists preserved by `FixedTwist` depend on the neighbors of the joint as well, so
+    /// are left to `Chain`.
+    pub fn restrict(&self, diff: &iso_adj::IsometryDifferential) -> iso_adj::IsometryDifferential {
+        match self {
+            &JointConstraint::Free | &JointConstraint::FixedTwist => *diff,
+            &JointConstraint::FixedPose => iso_adj::IsometryDifferential::zero(),
+        }
+    }
+
+    /// Whether the constraint allows the joint to be translated.
+    pub fn allows_translation(&self) -> bool {
+        *self != JointConstraint::FixedPose
+    }
+
+    /// The next constraint in a fixed cycle through all constraints, for toggling interactively.
+    pub fn next(&self) -> Self {
+        match self {
+            &JointConstraint::Free => JointConstraint::FixedTwist,
+            &JointConstraint::FixedTwist => JointConstraint::FixedPose,
+            &JointConstraint::FixedPose => JointConstraint::Free,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
-pub struct Chain {
+pub struct Chain<C = CostParams> {
     pub spec: JointSpec,
     pub num_angles: u16,
     pub pre_phantom: PhantomJoint,
     pub post_phantom: PhantomJoint,
-    pub cost_params: CostParams,
+    pub cost_params: C,
     pub return_to_initial_weight: f64,
     pub descent_rate: f64,
     pub steps: iso_adj::Steps,
     pub joints: Vec<Isometry3<f64>>,
+
+    /// The constraint on each joint.  Joints beyond the end of this list are free.
+    pub constraints: Vec<JointConstraint>,
+
     initial_points: Vec<Point3<f64>>,
 }
 
+/// The quantity found to be infinite or NaN after a step of an optimizer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NonFinite {
+    Cost,
+    Differential { joint: usize },
+    Joint { joint: usize },
+    Force { joint: usize },
+}
+
+/// How hard to try to recover from a step of an optimizer which produced non-finite values.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DivergenceGuard {
+    /// The number of times to roll back and retry a step before giving up.
+    pub max_retries: u32,
+
+    /// The factor by which the descent rate is multiplied before each retry.
+    pub rate_shrink: f64,
+}
+
+/// A step of an optimizer kept producing non-finite values, even with a reduced descent rate, or the
+/// chain was not finite to begin with.  The chain is left as it was before the step, with the last
+/// descent rate tried.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DivergenceError {
+    /// The problem found by the last attempt.
+    pub cause: NonFinite,
+
+    /// The number of steps attempted, which is zero if the chain was not finite before the first.
+    pub attempts: u32,
+    pub descent_rate: f64,
+}
+
+impl fmt::Display for DivergenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = match self.cause {
+            NonFinite::Cost => "the cost".to_string(),
+            NonFinite::Differential { joint } => format!("the differential of joint {}", joint),
+            NonFinite::Joint { joint } => format!("the transform of joint {}", joint),
+            NonFinite::Force { joint } => format!("the repulsive force on joint {}", joint),
+        };
+        if self.attempts == 0 {
+            return write!(f, "{} was not finite before the first step", cause);
+        }
+        write!(
+            f,
+            "{} was not finite after {} attempts (descent rate {})",
+            cause, self.attempts, self.descent_rate
+        )
+    }
+}
+
+impl Error for DivergenceError {}
+
+fn check_cost(cost: f64) -> Result<(), NonFinite> {
+    if cost.is_finite() {
+        Ok(())
+    } else {
+        Err(NonFinite::Cost)
+    }
+}
+
+fn check_differentials(diffs: &[iso_adj::IsometryDifferential]) -> Result<(), NonFinite> {
+    for (joint, diff) in diffs.iter().enumerate() {
+        let finite = [diff.d_x, diff.d_y, diff.d_z, diff.d_i, diff.d_j, diff.d_k]
+            .iter()
+            .all(|x| x.is_finite());
+        if !finite {
+            return Err(NonFinite::Differential { joint });
+        }
+    }
+    Ok(())
+}
+
+fn check_joints(joints: &[Isometry3<f64>]) -> Result<(), NonFinite> {
+    for (joint, iso) in joints.iter().enumerate() {
+        let finite = iso.translation.vector.iter().all(|x| x.is_finite())
+            && iso.rotation.coords.iter().all(|x| x.is_finite());
+        if !finite {
+            return Err(NonFinite::Joint { joint });
+        }
+    }
+    Ok(())
+}
+
+/// The state of a chain which `guarded` saves, restores and adjusts.
+trait GuardedChain {
+    fn joints_mut(&mut self) -> &mut Vec<Isometry3<f64>>;
+    fn descent_rate_mut(&mut self) -> &mut f64;
+}
+
+/// Take a step, rolling the joints back and retrying with a smaller descent rate for as long as it
+/// produces non-finite values.  No step is taken from joints which are already not finite, since
+/// every retry would fail the same way.
+fn guarded<T, F>(
+    target: &mut T,
+    guard: &DivergenceGuard,
+    mut step: F,
+) -> Result<f64, DivergenceError>
+where
+    T: GuardedChain,
+    F: FnMut(&mut T) -> Result<f64, NonFinite>,
+{
+    let last_good = target.joints_mut().clone();
+    if let Err(cause) = check_joints(&last_good) {
+        return Err(DivergenceError {
+            cause,
+            attempts: 0,
+            descent_rate: *target.descent_rate_mut(),
+        });
+    }
+    let mut attempts = 0;
+    loop {
+        let cause = match step(target) {
+            Ok(cost) => return Ok(cost),
+            Err(cause) => cause,
+        };
+        attempts += 1;
+        target.joints_mut().clone_from_slice(&last_good);
+        if attempts > guard.max_retries {
+            return Err(DivergenceError {
+                cause,
+                attempts,
+                descent_rate: *target.descent_rate_mut(),
+            });
+        }
+        *target.descent_rate_mut() *= guard.rate_shrink;
+    }
+}
+
 fn get_leg(spec: &JointSpec, joint: &Isometry3<f64>, leg: &Leg) -> Isometry3<f64> {
     match leg {
         &Leg::Incoming => joint * spec.origin_to_in(),
@@ -40,13 +233,13 @@ fn get_leg(spec: &JointSpec, joint: &Isometry3<f64>, leg: &Leg) -> Isometry3<f64
     }
 }
 
-impl Chain {
+impl<C: ConnectionCost> Chain<C> {
     pub fn new(
         spec: JointSpec,
         num_angles: u16,
         pre_phantom: PhantomJoint,
         post_phantom: PhantomJoint,
-        cost_params: CostParams,
+        cost_params: C,
         return_to_initial_weight: f64,
         descent_rate: f64,
         steps: iso_adj::Steps,
@@ -68,10 +261,106 @@ impl Chain {
             descent_rate,
             steps,
             joints,
+            constraints: Vec::new(),
             initial_points,
         }
     }
 
+    pub fn constraint(&self, index: usize) -> JointConstraint {
+        self.constraints.get(index).cloned().unwrap_or_default()
+    }
+
+    /// Restrict a differential of the joint at the given index according to its constraint, and to
+    /// the constraints of its neighbors.
+    fn restrict(
+        &self,
+        index: usize,
+        diff: &iso_adj::IsometryDifferential,
+    ) -> iso_adj::IsometryDifferential {
+        let diff = self.constraint(index).restrict(diff);
+
+        // Remove the rotation along every preserved twist's gradient, after making them orthonormal
+        let mut rotation = Vector3::new(diff.d_i, diff.d_j, diff.d_k);
+        let mut basis: Vec<Vector3<f64>> = Vec::new();
+        for gradient in self.fixed_twist_gradients(index) {
+            let direction = basis
+                .iter()
+                .fold(gradient, |direction, axis| direction - axis * axis.dot(&direction));
+            let norm = direction.norm();
+            if norm > 1e-9 * gradient.norm() {
+                let axis = direction / norm;
+                rotation -= axis * axis.dot(&rotation);
+                basis.push(axis);
+            }
+        }
+        iso_adj::IsometryDifferential {
+            d_i: rotation.x,
+            d_j: rotation.y,
+            d_k: rotation.z,
+            ..diff
+        }
+    }
+
+    /// The gradients, with respect to rotating the joint at the given index, of the twists which
+    /// `FixedTwist` constraints preserve at its junctions, as rotation vectors in world coordinates.
+    /// The twist at a junction is preserved if either joint meeting there is `FixedTwist`, including
+    /// a joint meeting a copy of itself at either end of the chain.
+    fn fixed_twist_gradients(&self, index: usize) -> Vec<Vector3<f64>> {
+        let fixed = |i: usize| self.constraint(i) == JointConstraint::FixedTwist;
+        if !(0..self.joints.len()).any(fixed) {
+            return Vec::new();
+        }
+
+        // Each side of a junction is the index of a joint, the symmetry mapping it to the copy
+        // meeting there if it is a phantom, and the leg meeting there
+        let last = self.joints.len() - 1;
+        let pre = &self.pre_phantom;
+        let post = &self.post_phantom;
+        let mut junctions = vec![(
+            (pre.index, Some(pre.symmetry), pre.leg),
+            (0, None, Leg::Incoming),
+        )];
+        for i in 0..last {
+            junctions.push(((i, None, Leg::Outgoing), (i + 1, None, Leg::Incoming)));
+        }
+        junctions.push((
+            (last, None, Leg::Outgoing),
+            (post.index, Some(post.symmetry), post.leg),
+        ));
+
+        let leg_frame = |&(i, symmetry, leg): &(usize, Option<Isometry3<f64>>, Leg)| {
+            let joint = symmetry.map_or(self.joints[i], |symmetry| symmetry * self.joints[i]);
+            get_leg(&self.spec, &joint, &leg)
+        };
+
+        let mut gradients = Vec::new();
+        for (side_0, side_1) in junctions {
+            if (side_0.0 != index && side_1.0 != index) || (!fixed(side_0.0) && !fixed(side_1.0)) {
+                continue;
+            }
+            let trans_0 = leg_frame(&side_0);
+            let trans_1 = leg_frame(&side_1);
+            let twist_gradient = if side_0.2 == side_1.2 {
+                twist_gradient_opposing(&trans_0, &trans_1)
+            } else {
+                twist_gradient_aligned(&trans_0, &trans_1)
+            };
+
+            // Rotating the first side has the opposite effect, and rotating a joint rotates its
+            // phantom by the symmetry too
+            let mut gradient = Vector3::zeros();
+            for &(side, sign) in &[(&side_0, -1.0), (&side_1, 1.0)] {
+                if side.0 == index {
+                    gradient += sign * side.1.map_or(twist_gradient, |symmetry| {
+                        symmetry.rotation.inverse() * twist_gradient
+                    });
+                }
+            }
+            gradients.push(gradient);
+        }
+        gradients
+    }
+
     fn get_phantom(&self, phantom: &PhantomJoint) -> Isometry3<f64> {
         phantom.symmetry * self.joints[phantom.index]
     }
@@ -88,23 +377,37 @@ impl Chain {
         // (Incoming, Outgoing): [out in]<-- <--[out in]
         // (Outgoing, Incoming): [in out]--> -->[in out]
         // (Outgoing, Outgoing): [in out]--> <--[out in]
-        let cost_fn = if leg_0 == leg_1 {
-            cost_opposing
+        let trans_0 = get_leg(&self.spec, &joint_0, &leg_0);
+        let trans_1 = get_leg(&self.spec, &joint_1, &leg_1);
+        if leg_0 == leg_1 {
+            self.cost_params.cost_opposing(self.num_angles, &trans_0, &trans_1)
         } else {
-            cost_aligned
-        };
+            self.cost_params.cost_aligned(self.num_angles, &trans_0, &trans_1)
+        }
+    }
 
-        cost_fn(
-            &self.cost_params,
-            self.num_angles,
-            &get_leg(&self.spec, &joint_0, &leg_0),
-            &get_leg(&self.spec, &joint_1, &leg_1),
-        )
+    /// Like `cost_between`, but with the individual measures of the fit rather than their weighted
+    /// sum.
+    fn costs_between(
+        &self,
+        joint_0: &Isometry3<f64>,
+        leg_0: &Leg,
+        joint_1: &Isometry3<f64>,
+        leg_1: &Leg,
+    ) -> Costs {
+        let trans_0 = get_leg(&self.spec, &joint_0, &leg_0);
+        let trans_1 = get_leg(&self.spec, &joint_1, &leg_1);
+        if leg_0 == leg_1 {
+            self.cost_params.costs_opposing(self.num_angles, &trans_0, &trans_1)
+        } else {
+            self.cost_params.costs_aligned(self.num_angles, &trans_0, &trans_1)
+        }
     }
 
     pub fn apply_diffs(&mut self, ratio: f64, diffs: &[iso_adj::IsometryDifferential]) {
         let joint_radius = (self.spec.dist_in() + self.spec.dist_out()) * 0.5;
-        for (i, &diff) in diffs.iter().enumerate() {
+        for (i, diff) in diffs.iter().enumerate() {
+            let diff = self.restrict(i, diff);
             iso_adj::apply_step(
                 joint_radius,
                 &mut self.joints[i],
@@ -135,12 +438,94 @@ impl Chain {
         result
     }
 
-    pub fn adaptive_optimize(&mut self, ratios: &[f64], tolerance: f64) -> f64 {
-        let joint_radius = (self.spec.dist_in() + self.spec.dist_out()) * 0.5;
+    /// The unweighted measures of fit summed over the same connections as `total_cost`.
+    pub fn cost_components(&self) -> Costs {
+        let mut result = Costs {
+            dist: 0.0,
+            axis: 0.0,
+            locking: 0.0,
+        };
+        let mut pre_joint = self.get_phantom(&self.pre_phantom);
+        let mut pre_leg = self.pre_phantom.leg;
+        for i in 0..self.joints.len() {
+            let joint = self.joints[i];
+            let (post_joint, post_leg) = if i + 1 < self.joints.len() {
+                (self.joints[i + 1], Leg::Incoming)
+            } else {
+                (self.get_phantom(&self.post_phantom), self.post_phantom.leg)
+            };
 
+            for costs in &[
+                self.costs_between(&pre_joint, &pre_leg, &joint, &Leg::Incoming),
+                self.costs_between(&joint, &Leg::Outgoing, &post_joint, &post_leg),
+            ] {
+                result.dist += costs.dist;
+                result.axis += costs.axis;
+                result.locking += costs.locking;
+            }
+            pre_joint = joint;
+            pre_leg = Leg::Outgoing;
+        }
+        result
+    }
+
+    /// Recover the discrete angles of the chain.  The phantoms must connect the incoming leg of the
+    /// first joint and the outgoing leg of the last joint to their symmetric copies, as they do for
+    /// every chain built from a `KnotGeometry`.
+    pub fn discrete_angles(&self, parity: JointsParity) -> RecoveredAngles {
+        assert!(self.pre_phantom.index == 0 && self.pre_phantom.leg == Leg::Incoming);
+        assert!(
+            self.post_phantom.index + 1 == self.joints.len()
+                && self.post_phantom.leg == Leg::Outgoing
+        );
+        recover_angles(
+            &self.spec,
+            self.num_angles,
+            &self.cost_params,
+            parity,
+            &self.joints,
+            &self.pre_phantom.symmetry,
+            &self.post_phantom.symmetry,
+        )
+    }
+
+    /// Compute the cost of the connection between two legs, along with its differentials with
+    /// respect to moving each of the two joints.
+    fn differentials_between(
+        &self,
+        joint_0: &Isometry3<f64>,
+        leg_0: &Leg,
+        joint_1: &Isometry3<f64>,
+        leg_1: &Leg,
+    ) -> (f64, iso_adj::IsometryDifferential, iso_adj::IsometryDifferential) {
+        // See `cost_between`
+        let trans_0 = get_leg(&self.spec, &joint_0, &leg_0);
+        let trans_1 = get_leg(&self.spec, &joint_1, &leg_1);
+        let (cost, gradient) = if leg_0 == leg_1 {
+            self.cost_params.gradient_opposing(self.num_angles, &trans_0, &trans_1)
+        } else {
+            self.cost_params.gradient_aligned(self.num_angles, &trans_0, &trans_1)
+        };
+
+        let diff_0 = iso_adj::IsometryDifferential::from_rates_at(
+            &(trans_0.translation.vector - joint_0.translation.vector),
+            &-gradient.translation,
+            &-gradient.rotation,
+        );
+        let diff_1 = iso_adj::IsometryDifferential::from_rates_at(
+            &(trans_1.translation.vector - joint_1.translation.vector),
+            &gradient.translation,
+            &gradient.rotation,
+        );
+        (cost, diff_0, diff_1)
+    }
+
+    /// Compute the total cost of the chain, along with the differential of the cost with respect to
+    /// each joint.  As with `optimize`, the symmetric copies of the chain at either end are held
+    /// fixed.
+    fn differentials(&self) -> (f64, Vec<iso_adj::IsometryDifferential>) {
         let mut curr_total_cost = 0.0;
 
-        let mut diff_mag_squ = 0.0;
         let diffs = {
             let mut pre_joint = self.get_phantom(&self.pre_phantom);
             let mut pre_leg = self.pre_phantom.leg;
@@ -153,33 +538,123 @@ impl Chain {
                         (self.get_phantom(&self.post_phantom), self.post_phantom.leg)
                     };
 
-                    let (curr_joint_cost, diff) =
-                        iso_adj::differentiate(&self.steps, joint, |new_joint| {
-                            let pre_cost =
-                                self.cost_between(&pre_joint, &pre_leg, &new_joint, &Leg::Incoming);
-                            let post_cost = self.cost_between(
-                                &new_joint,
-                                &Leg::Outgoing,
-                                &post_joint,
-                                &post_leg,
-                            );
-                            pre_cost + post_cost
-                        });
-                    curr_total_cost += curr_joint_cost;
-                    diff_mag_squ += diff.magnitude_squ(joint_radius);
+                    let (pre_cost, _, pre_diff) =
+                        self.differentials_between(&pre_joint, &pre_leg, &joint, &Leg::Incoming);
+                    let (post_cost, post_diff, _) =
+                        self.differentials_between(&joint, &Leg::Outgoing, &post_joint, &post_leg);
+                    curr_total_cost += pre_cost + post_cost;
                     pre_joint = joint;
                     pre_leg = Leg::Outgoing;
-                    diff
+                    pre_diff + post_diff
                 }).collect::<Vec<_>>()
         };
 
+        (curr_total_cost, diffs)
+    }
+
+    /// Compute `total_cost` along with its exact gradient with respect to each joint.  Unlike
+    /// `differentials`, this accounts for the phantoms moving along with the joints they are copies
+    /// of, and for every connection between two joints of the chain being counted twice.
+    pub fn gradient(&self) -> (f64, Vec<iso_adj::IsometryDifferential>) {
+        let mut total_cost = 0.0;
+        let mut diffs = vec![iso_adj::IsometryDifferential::zero(); self.joints.len()];
+        let last = self.joints.len() - 1;
+
+        let pre = &self.pre_phantom;
+        let (cost, phantom_diff, diff) = self.differentials_between(
+            &self.get_phantom(pre),
+            &pre.leg,
+            &self.joints[0],
+            &Leg::Incoming,
+        );
+        total_cost += cost;
+        diffs[0] = diffs[0] + diff;
+        diffs[pre.index] = diffs[pre.index] + phantom_diff.rotate(&pre.symmetry.rotation.inverse());
+
+        for i in 0..last {
+            let (cost, diff_0, diff_1) = self.differentials_between(
+                &self.joints[i],
+                &Leg::Outgoing,
+                &self.joints[i + 1],
+                &Leg::Incoming,
+            );
+            total_cost += 2.0 * cost;
+            diffs[i] = diffs[i] + diff_0.scale(2.0);
+            diffs[i + 1] = diffs[i + 1] + diff_1.scale(2.0);
+        }
+
+        let post = &self.post_phantom;
+        let (cost, diff, phantom_diff) = self.differentials_between(
+            &self.joints[last],
+            &Leg::Outgoing,
+            &self.get_phantom(post),
+            &post.leg,
+        );
+        total_cost += cost;
+        diffs[last] = diffs[last] + diff;
+        diffs[post.index] =
+            diffs[post.index] + phantom_diff.rotate(&post.symmetry.rotation.inverse());
+
+        (total_cost, diffs)
+    }
+
+    /// Minimize `total_cost` with L-BFGS over the positions and orientations of all the joints at
+    /// once.  Rotations are scaled by the radius of a joint, as in `apply_diffs`.
+    pub fn lbfgs_optimize(&mut self, params: &LbfgsParams) -> OptimizationReport {
+        minimize(self, params)
+    }
+
+    /// Stack the restricted differentials of every joint into the gradient seen by L-BFGS, with
+    /// rotations scaled by the radius of a joint.
+    fn stack_gradient(&self, diffs: &[iso_adj::IsometryDifferential]) -> Vec<f64> {
+        let joint_radius = (self.spec.dist_in() + self.spec.dist_out()) * 0.5;
+        let mut gradient = Vec::with_capacity(diffs.len() * 6);
+        for (i, diff) in diffs.iter().enumerate() {
+            let diff = self.restrict(i, diff);
+            gradient.extend_from_slice(&[
+                diff.d_x,
+                diff.d_y,
+                diff.d_z,
+                diff.d_i / joint_radius,
+                diff.d_j / joint_radius,
+                diff.d_k / joint_radius,
+            ]);
+        }
+        gradient
+    }
+
+    /// The change in cost which `line_search` expects from stepping down the given differentials at
+    /// the given ratio of the descent rate, as by `apply_diffs`.  The differentials computed by
+    /// `differentials` are half the gradient of `total_cost`, so the change in cost is twice this to
+    /// first order.  Only the restricted differentials are stepped down, so only they count.
+    fn expected_delta_cost(&self, ratio: f64, diffs: &[iso_adj::IsometryDifferential]) -> f64 {
+        let joint_radius = (self.spec.dist_in() + self.spec.dist_out()) * 0.5;
+        let diff_mag_squ = diffs
+            .iter()
+            .enumerate()
+            .map(|(i, diff)| self.restrict(i, diff).magnitude_squ(joint_radius))
+            .sum::<f64>();
+        -diff_mag_squ * self.descent_rate * ratio
+    }
+
+    /// Step down the given differentials, trying each of the given ratios of the descent rate in
+    /// turn until the decrease in `total_cost` is at least `tolerance` times the decrease predicted
+    /// by the differentials.
+    fn line_search<F: Fn(&Self) -> f64>(
+        &mut self,
+        ratios: &[f64],
+        tolerance: f64,
+        curr_total_cost: f64,
+        diffs: &[iso_adj::IsometryDifferential],
+        total_cost: F,
+    ) -> f64 {
         // Ratios should be in DESCENDING order!
         let old_joints = self.joints.clone();
         for &ratio in ratios {
             self.joints.clone_from_slice(&old_joints);
-            self.apply_diffs(ratio, &diffs);
-            let new_cost = self.total_cost();
-            let expected_delta_cost = -diff_mag_squ * self.descent_rate * ratio;
+            let expected_delta_cost = self.expected_delta_cost(ratio, diffs);
+            self.apply_diffs(ratio, diffs);
+            let new_cost = total_cost(self);
             let actual_delta_cost = new_cost - curr_total_cost;
             if actual_delta_cost / expected_delta_cost >= tolerance {
                 self.descent_rate *= ratio;
@@ -192,55 +667,114 @@ impl Chain {
         curr_total_cost
     }
 
-    pub fn optimize(&mut self) -> f64 {
-        let joint_radius = (self.spec.dist_in() + self.spec.dist_out()) * 0.5;
-
-        let mut pre_joint = self.get_phantom(&self.pre_phantom);
-        let mut pre_leg = self.pre_phantom.leg;
-
-        let mut curr_total_cost = 0.0;
+    pub fn adaptive_optimize(&mut self, ratios: &[f64], tolerance: f64) -> f64 {
+        let (curr_total_cost, diffs) = self.differentials();
+        self.line_search(ratios, tolerance, curr_total_cost, &diffs, Self::total_cost)
+    }
 
-        for i in 0..self.joints.len() {
-            let joint = self.joints[i];
-            let (post_joint, post_leg) = if i + 1 < self.joints.len() {
-                (self.joints[i + 1], Leg::Incoming)
-            } else {
-                (self.get_phantom(&self.post_phantom), self.post_phantom.leg)
-            };
+    fn adaptive_optimize_checked(
+        &mut self,
+        ratios: &[f64],
+        tolerance: f64,
+    ) -> Result<f64, NonFinite> {
+        let (curr_total_cost, diffs) = self.differentials();
+        check_cost(curr_total_cost)?;
+        check_differentials(&diffs)?;
+        self.line_search(ratios, tolerance, curr_total_cost, &diffs, Self::total_cost);
+        check_joints(&self.joints)?;
+        Ok(curr_total_cost)
+    }
 
-            let (curr_joint_cost, diff) = iso_adj::differentiate(&self.steps, joint, |new_joint| {
-                let pre_cost = self.cost_between(&pre_joint, &pre_leg, &new_joint, &Leg::Incoming);
-                let post_cost =
-                    self.cost_between(&new_joint, &Leg::Outgoing, &post_joint, &post_leg);
-                pre_cost + post_cost
-            });
-            iso_adj::apply_step(
-                joint_radius,
-                &mut self.joints[i],
-                &diff.scale(-self.descent_rate),
-            );
+    /// Like `adaptive_optimize`, but recovering from non-finite values as described by `guard`.
+    pub fn try_adaptive_optimize(
+        &mut self,
+        ratios: &[f64],
+        tolerance: f64,
+        guard: &DivergenceGuard,
+    ) -> Result<f64, DivergenceError> {
+        guarded(self, guard, |chain| chain.adaptive_optimize_checked(ratios, tolerance))
+    }
 
-            curr_total_cost += curr_joint_cost;
+    fn optimize_checked(&mut self) -> Result<f64, NonFinite> {
+        let cost = self.optimize();
+        check_cost(cost)?;
+        check_joints(&self.joints)?;
+        Ok(cost)
+    }
 
-            pre_joint = joint;
-            pre_leg = Leg::Outgoing;
-        }
+    /// Like `optimize`, but recovering from non-finite values as described by `guard`.  A
+    /// non-finite differential of any joint shows up as a non-finite transform.
+    pub fn try_optimize(&mut self, guard: &DivergenceGuard) -> Result<f64, DivergenceError> {
+        guarded(self, guard, Self::optimize_checked)
+    }
 
+    /// Take one step of gradient descent, moving every joint down the analytic differential of its
+    /// connections with its neighbors.  Returns the total cost before the step.
+    pub fn optimize(&mut self) -> f64 {
+        let (curr_total_cost, diffs) = self.differentials();
+        self.apply_diffs(1.0, &diffs);
         curr_total_cost
     }
 
     pub fn return_to_initial(&mut self) {
-        for (joint, initial) in self.joints.iter_mut().zip(self.initial_points.iter()) {
-            let diff = initial - Point3 {
+        for i in 0..self.joints.len() {
+            if !self.constraint(i).allows_translation() {
+                continue;
+            }
+            let joint = self.joints[i];
+            let diff = self.initial_points[i] - Point3 {
                 coords: joint.translation.vector,
             };
-            *joint =
+            self.joints[i] =
                 Translation3::from(diff * self.return_to_initial_weight * self.descent_rate)
-                    * *joint;
+                    * joint;
         }
     }
 }
 
+impl<C> GuardedChain for Chain<C> {
+    fn joints_mut(&mut self) -> &mut Vec<Isometry3<f64>> {
+        &mut self.joints
+    }
+
+    fn descent_rate_mut(&mut self) -> &mut f64 {
+        &mut self.descent_rate
+    }
+}
+
+impl<C: ConnectionCost> Objective for Chain<C> {
+    type Point = Vec<Isometry3<f64>>;
+
+    fn point(&self) -> Vec<Isometry3<f64>> {
+        self.joints.clone()
+    }
+
+    fn set_point(&mut self, joints: Vec<Isometry3<f64>>) {
+        self.joints = joints;
+    }
+
+    fn cost_and_gradient(&self) -> (f64, Vec<f64>) {
+        let (cost, diffs) = self.gradient();
+        (cost, self.stack_gradient(&diffs))
+    }
+
+    fn retract(&mut self, step: &[f64]) {
+        let joint_radius = (self.spec.dist_in() + self.spec.dist_out()) * 0.5;
+        for (i, step) in step.chunks(6).enumerate() {
+            let diff = iso_adj::IsometryDifferential {
+                d_x: step[0],
+                d_y: step[1],
+                d_z: step[2],
+
+                d_i: step[3] * joint_radius,
+                d_j: step[4] * joint_radius,
+                d_k: step[5] * joint_radius,
+            };
+            let diff = self.restrict(i, &diff);
+            iso_adj::apply_step(joint_radius, &mut self.joints[i], &diff);
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct RepulsionChain {
@@ -249,14 +783,27 @@ pub struct RepulsionChain {
     pub repulsion_strength: f64,
     pub max_repulsion_strength: f64,
 
+    /// The fraction of `max_repulsion_strength` below which a repulsive force is considered
+    /// negligible.  Together with `repulsion_strength` and `repulsion_exp`, this determines the
+    /// cutoff distance beyond which pairs of joints are not considered individually.
+    pub cutoff_fraction: f64,
+
+    /// Whether to approximate the forces from joints beyond the cutoff distance by aggregating them
+    /// into one force per grid cell, rather than ignoring them entirely.
+    pub far_field: bool,
+
+    /// The weight assigned to the Möbius energy of the symmetrized chain in its total cost.  Unlike
+    /// the forces applied by `repulse`, this term is part of the objective minimized by `optimize`
+    /// and `adaptive_optimize`.  When it is nonzero, the energy takes the place of those forces:
+    /// `repulse` does nothing, and `lbfgs_optimize` leaves out their potential.
+    pub energy_weight: f64,
+
     // Necessary because the chain only contains boundary condition information, not global
     // symmetries.
     pub symmetries: Vec<Isometry3<f64>>,
 
     // cached workspace to avoid reallocation
     forces: Vec<Vector3<f64>>,
-    // TODO: Spatial partition structure to avoid qudadratic-time force calculation (can probably
-    // reuse or extend CollisionGrid)
 }
 
 impl Deref for RepulsionChain {
@@ -273,20 +820,212 @@ impl DerefMut for RepulsionChain {
     }
 }
 
+impl GuardedChain for RepulsionChain {
+    fn joints_mut(&mut self) -> &mut Vec<Isometry3<f64>> {
+        &mut self.chain.joints
+    }
+
+    fn descent_rate_mut(&mut self) -> &mut f64 {
+        &mut self.chain.descent_rate
+    }
+}
+
 fn within(a: usize, b: usize, range: usize) -> bool {
     ((a as isize) - (b as isize)).abs() <= (range as isize)
 }
 
 fn clamped_inverse_power(x: f64, n: i32, scale: f64, clamp: f64) -> f64 {
-    let result = if x <= 0.0 {
+    if x <= 0.0 {
         clamp
     } else {
         (scale * x.powi(-n)).min(clamp)
-    };
-    if result.is_nan() {
-        eprintln!("Clamped inverse power returned NaN!");
     }
-    result
+}
+
+/// Parameters of the repulsive force law, bundled so that they can be carried by the colliders
+/// used for spatial partitioning.
+#[derive(Clone, Copy, Debug)]
+struct ForceLaw {
+    exp: i32,
+    strength: f64,
+    max_strength: f64,
+    joint_radius: f64,
+}
+
+impl ForceLaw {
+    /// The force exerted on a joint centered at `center` by a joint centered at `other`.
+    fn force(&self, center: Point3<f64>, other: Point3<f64>) -> Vector3<f64> {
+        let diff = center - other;
+        // surface distance
+        let surf_dist = diff.norm() - self.joint_radius * 2.0;
+        diff / diff.norm() * clamped_inverse_power(
+            surf_dist,
+            self.exp,
+            self.strength,
+            self.max_strength,
+        )
+    }
+
+    /// The potential energy of a joint centered at `center` due to a joint centered at `other`,
+    /// whose negative gradient with respect to `center` is `force`.  Assumes a positive exponent.
+    fn potential(&self, center: Point3<f64>, other: Point3<f64>) -> f64 {
+        if self.strength == 0.0 {
+            return 0.0;
+        }
+        let surf_dist = (center - other).norm() - self.joint_radius * 2.0;
+        // Closer than this, the force is clamped to `max_strength`
+        let clamp_dist = (self.strength / self.max_strength).powf(1.0 / (self.exp as f64));
+        if surf_dist < clamp_dist {
+            self.unclamped_potential(clamp_dist) + self.max_strength * (clamp_dist - surf_dist)
+        } else {
+            self.unclamped_potential(surf_dist)
+        }
+    }
+
+    fn unclamped_potential(&self, surf_dist: f64) -> f64 {
+        if self.exp == 1 {
+            -self.strength * surf_dist.ln()
+        } else {
+            self.strength * surf_dist.powi(1 - self.exp) / ((self.exp - 1) as f64)
+        }
+    }
+}
+
+/// A symmetrized copy of a joint, stored in a `CollisionGrid` as a single point so that each source
+/// is found at most once by any query.
+#[derive(Clone, Copy, Debug)]
+struct RepulsionSource {
+    center: Point3<f64>,
+    symmetry_index: usize,
+    index: usize,
+}
+
+impl BoundedCollider for RepulsionSource {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            x: Bounds {
+                min: self.center.x,
+                max: self.center.x,
+            },
+            y: Bounds {
+                min: self.center.y,
+                max: self.center.y,
+            },
+            z: Bounds {
+                min: self.center.z,
+                max: self.center.z,
+            },
+        }
+    }
+}
+
+/// A joint of the chain whose neighborhood, out to the cutoff distance, is searched for sources of
+/// repulsion.  The "collision data" of a probe with a source is the force the source exerts on it.
+#[derive(Clone, Copy, Debug)]
+struct RepulsionProbe<'a> {
+    center: Point3<f64>,
+    index: usize,
+    bonds: &'a Bonds,
+    cutoff: f64,
+    law: ForceLaw,
+}
+
+impl<'a> BoundedCollider for RepulsionProbe<'a> {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            x: Bounds {
+                min: self.center.x - self.cutoff,
+                max: self.center.x + self.cutoff,
+            },
+            y: Bounds {
+                min: self.center.y - self.cutoff,
+                max: self.center.y + self.cutoff,
+            },
+            z: Bounds {
+                min: self.center.z - self.cutoff,
+                max: self.center.z + self.cutoff,
+            },
+        }
+    }
+}
+
+impl<'a> CheckCollision<RepulsionSource> for RepulsionProbe<'a> {
+    type CollisionData = Vector3<f64>;
+
+    fn check_collision(&self, other: &RepulsionSource) -> Option<Vector3<f64>> {
+        let in_range = (other.center - self.center).norm_squared() < self.cutoff * self.cutoff;
+        if in_range && !self.bonds.contains(self.index, other.symmetry_index, other.index) {
+            Some(self.law.force(self.center, other.center))
+        } else {
+            None
+        }
+    }
+}
+
+/// How close two isometries must be to count as the same symmetry.
+const SYMMETRY_TOLERANCE: f64 = 1e-6;
+
+fn symmetry_index(symmetries: &[Isometry3<f64>], symmetry: &Isometry3<f64>) -> Option<usize> {
+    symmetries.iter().position(|sym| {
+        let diff = sym.inverse() * symmetry;
+        diff.translation.vector.norm() < SYMMETRY_TOLERANCE
+            && diff.rotation.angle() < SYMMETRY_TOLERANCE
+    })
+}
+
+/// The pairs of joints which are meant to be connected, and so neither repel each other nor
+/// contribute to the Möbius energy: neighbors within the chain, and the joints at either end of the
+/// chain together with the copies the phantoms connect them to.  A pair is given as in
+/// `energy::mobius_energy`, by the index of a joint, the index of a symmetry and the index of a
+/// joint in that copy of the chain.  Every connection with a copy is listed both under its own
+/// symmetry and under the inverse, so the pairs are the same seen from either joint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bonds {
+    identity: Option<usize>,
+    ends: Vec<(usize, usize, usize)>,
+}
+
+impl Bonds {
+    /// The bonds of a chain with `joint_count` joints, whose first joint connects to the
+    /// pre-phantom and whose last joint connects to the post-phantom.  Phantoms whose symmetry is
+    /// not among `symmetries` have no copy to bond with.
+    pub fn new(
+        symmetries: &[Isometry3<f64>],
+        joint_count: usize,
+        pre_phantom: &PhantomJoint,
+        post_phantom: &PhantomJoint,
+    ) -> Bonds {
+        let mut ends = Vec::new();
+        for &(joint, phantom) in &[(0, pre_phantom), (joint_count - 1, post_phantom)] {
+            if let Some(sym_i) = symmetry_index(symmetries, &phantom.symmetry) {
+                ends.push((joint, sym_i, phantom.index));
+            }
+            if let Some(sym_i) = symmetry_index(symmetries, &phantom.symmetry.inverse()) {
+                ends.push((phantom.index, sym_i, joint));
+            }
+        }
+        Bonds {
+            identity: symmetry_index(symmetries, &Isometry3::identity()),
+            ends,
+        }
+    }
+
+    /// The bonds of a chain and the copies of it produced by `symmetries`.
+    pub fn of_chain<C>(chain: &Chain<C>, symmetries: &[Isometry3<f64>]) -> Bonds {
+        Bonds::new(
+            symmetries,
+            chain.joints.len(),
+            &chain.pre_phantom,
+            &chain.post_phantom,
+        )
+    }
+
+    /// Whether joint `i` of the chain is bonded to joint `j` of its copy under the symmetry with
+    /// index `sym_i`.
+    pub fn contains(&self, i: usize, sym_i: usize, j: usize) -> bool {
+        let neighbors_in_same_branch = Some(sym_i) == self.identity && within(i, j, 1);
+        neighbors_in_same_branch || self.ends.contains(&(i, sym_i, j))
+    }
 }
 
 impl RepulsionChain {
@@ -302,46 +1041,859 @@ impl RepulsionChain {
             repulsion_exp,
             repulsion_strength,
             max_repulsion_strength,
+            cutoff_fraction: REPULSION_CUTOFF_FRACTION,
+            far_field: REPULSION_FAR_FIELD,
+            energy_weight: MOBIUS_ENERGY_WEIGHT,
             symmetries,
             forces: Vec::new(),
         }
     }
 
-    pub fn repulse(&mut self) {
-        assert_eq!(self.forces.len(), 0);
-        self.forces
-            .resize(self.chain.joints.len(), Vector3::new(0.0, 0.0, 0.0));
+    /// The pairs of joints which do not repel each other.  See `Bonds`.
+    pub fn bonds(&self) -> Bonds {
+        Bonds::of_chain(&self.chain, &self.symmetries)
+    }
+
+    fn force_law(&self) -> ForceLaw {
+        ForceLaw {
+            exp: self.repulsion_exp,
+            strength: self.repulsion_strength,
+            max_strength: self.max_repulsion_strength,
+            joint_radius: self.chain.spec.radius(),
+        }
+    }
+
+    /// The center-to-center distance beyond which the repulsion between two joints falls below
+    /// `cutoff_fraction` of `max_repulsion_strength`, which is infinite if `cutoff_fraction` is zero.
+    /// Never less than the distance between the centers of two connected joints, so that bonded
+    /// neighbors are always handled exactly.
+    pub fn cutoff(&self) -> f64 {
+        let surf_cutoff = if self.repulsion_exp > 0 {
+            (self.repulsion_strength / (self.cutoff_fraction * self.max_repulsion_strength))
+                .powf(1.0 / (self.repulsion_exp as f64))
+        } else {
+            INFINITY
+        };
+        let spec = &self.chain.spec;
+        (surf_cutoff.max(0.0) + spec.radius() * 2.0).max(spec.dist_in() + spec.dist_out())
+    }
+
+    /// Accumulate the repulsive force on every joint into `self.forces` by considering every pair of
+    /// joints.  Quadratic in the number of joints.
+    fn accumulate_forces_exhaustive(&mut self) {
+        let law = self.force_law();
+        let bonds = self.bonds();
         for i in 0..self.chain.joints.len() {
+            let center = Point3::from(self.chain.joints[i].translation.vector);
             for (sym_i, sym) in self.symmetries.iter().enumerate() {
                 for j in 0..self.chain.joints.len() {
-                    let neighbors_in_same_branch = sym_i == 0 && within(i, j, 1);
-                    let neighbors_at_start = sym_i == 1 && i == 0 && j == 0; // DEBUG
-                    let neighbors_at_end = sym_i == 2
-                        && i == self.chain.joints.len() - 1
-                        && j == self.chain.joints.len() - 1;
-
-                    let neighbors =
-                        neighbors_in_same_branch || neighbors_at_start || neighbors_at_end;
-
-                    if !neighbors {
-                        let diff = self.chain.joints[i].translation.vector
-                            - (sym * self.chain.joints[j]).translation.vector;
-                        // surface distance
-                        let surf_dist = diff.norm() - self.chain.spec.radius() * 2.0;
-                        self.forces[i] += diff / diff.norm() * clamped_inverse_power(
-                            surf_dist,
-                            self.repulsion_exp,
-                            self.repulsion_strength,
-                            self.max_repulsion_strength,
-                        );
+                    if !bonds.contains(i, sym_i, j) {
+                        let other = Point3::from((sym * self.chain.joints[j]).translation.vector);
+                        self.forces[i] += law.force(center, other);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Accumulate the repulsive force on every joint into `self.forces`, using a spatial hash grid
+    /// to only consider pairs of joints closer than `cutoff`.
+    fn accumulate_forces_partitioned(&mut self, cutoff: f64) {
+        let law = self.force_law();
+        let bonds = self.bonds();
+
+        let mut grid = CollisionGrid::new(cutoff);
+        // The sources in each grid cell, for the far-field approximation
+        let mut cells: HashMap<Point3<i32>, Vec<RepulsionSource>> = HashMap::new();
+        for (sym_i, sym) in self.symmetries.iter().enumerate() {
+            for (j, joint) in self.chain.joints.iter().enumerate() {
+                let source = RepulsionSource {
+                    center: Point3::from((sym * joint).translation.vector),
+                    symmetry_index: sym_i,
+                    index: j,
+                };
+                grid.add(&source);
+                if self.far_field {
+                    cells
+                        .entry(cell_index(cutoff, &source.center))
+                        .or_insert_with(Vec::new)
+                        .push(source);
+                }
+            }
+        }
+
+        for i in 0..self.chain.joints.len() {
+            let probe = RepulsionProbe {
+                center: Point3::from(self.chain.joints[i].translation.vector),
+                index: i,
+                bonds: &bonds,
+                cutoff,
+                law,
+            };
+            for force in grid.collisions(&probe) {
+                self.forces[i] += force;
+            }
+
+            if self.far_field {
+                // Cells adjacent to the probe's cell are too close for their sources to be
+                // approximated by their centroid, so those of their sources which lie beyond the
+                // cutoff, and were not found in the grid, are added exactly.
+                let probe_cell = cell_index(cutoff, &probe.center);
+                for (cell, sources) in &cells {
+                    let near = (cell.x - probe_cell.x).abs() <= 1
+                        && (cell.y - probe_cell.y).abs() <= 1
+                        && (cell.z - probe_cell.z).abs() <= 1;
+                    if near {
+                        for source in sources {
+                            let beyond = (source.center - probe.center).norm_squared()
+                                >= cutoff * cutoff;
+                            if beyond && !bonds.contains(i, source.symmetry_index, source.index) {
+                                self.forces[i] += law.force(probe.center, source.center);
+                            }
+                        }
+                    } else {
+                        let count = sources.len() as f64;
+                        let sum = sources
+                            .iter()
+                            .fold(Vector3::zeros(), |sum, source| sum + source.center.coords);
+                        let centroid = Point3::from(sum / count);
+                        self.forces[i] += law.force(probe.center, centroid) * count;
                     }
                 }
             }
         }
+    }
+
+    /// The Möbius energy of the chain and all its symmetric copies.  See `energy::mobius_energy`.
+    pub fn energy(&self) -> f64 {
+        chain_energy(&self.chain, &self.symmetries)
+    }
 
-        for (force, joint) in self.forces.iter().zip(self.chain.joints.iter_mut()) {
-            joint.translation.vector += force * self.chain.descent_rate;
+    /// The connection cost of the chain plus its weighted Möbius energy.
+    pub fn total_cost(&self) -> f64 {
+        let mut result = self.chain.total_cost();
+        if self.energy_weight != 0.0 {
+            result += self.energy_weight * self.energy();
+        }
+        result
+    }
+
+    /// The potential energy of the repulsion between every pair of joints of the symmetrized chain
+    /// which are not bonded, along with the force it exerts on every joint.  Unlike `repulse`, this
+    /// considers every pair, however far apart, so that the forces are exactly the negative
+    /// gradient of the energy.
+    fn repulsion_energy_and_forces(&self) -> (f64, Vec<Vector3<f64>>) {
+        let law = self.force_law();
+        let bonds = self.bonds();
+        let mut energy = 0.0;
+        let mut forces = vec![Vector3::zeros(); self.chain.joints.len()];
+        for i in 0..self.chain.joints.len() {
+            let center = Point3::from(self.chain.joints[i].translation.vector);
+            for (sym_i, sym) in self.symmetries.iter().enumerate() {
+                for j in 0..self.chain.joints.len() {
+                    if !bonds.contains(i, sym_i, j) {
+                        let other = Point3::from((sym * self.chain.joints[j]).translation.vector);
+                        // Every pair is seen once from either joint
+                        energy += 0.5 * law.potential(center, other);
+                        forces[i] += law.force(center, other);
+                    }
+                }
+            }
+        }
+        (energy, forces)
+    }
+
+    /// The potential energy of the forces applied by `repulse`, neglecting the cutoff.
+    pub fn repulsion_energy(&self) -> f64 {
+        self.repulsion_energy_and_forces().0
+    }
+
+    /// Minimize the full cost of the chain with L-BFGS: the connection cost, and either the weighted
+    /// Möbius energy or, if it has no weight, the potential energy of the repulsion.  See
+    /// `Chain::lbfgs_optimize`.
+    pub fn lbfgs_optimize(&mut self, params: &LbfgsParams) -> OptimizationReport {
+        minimize(self, params)
+    }
+
+    /// Compute `total_cost` along with its differential with respect to each joint, to be stepped
+    /// down by `optimize` and `adaptive_optimize`.  Like those of `Chain::differentials`, these are
+    /// half the gradient of the cost.
+    fn cost_and_differentials(&self) -> (f64, Vec<iso_adj::IsometryDifferential>) {
+        let (connection_cost, mut diffs) = self.chain.differentials();
+        let energy = self.add_energy_differentials(0.5, &mut diffs);
+        (connection_cost + energy, diffs)
+    }
+
+    /// Add the weighted gradient of the Möbius energy, times `scale`, to the given differentials,
+    /// and return the weighted energy.
+    fn add_energy_differentials(
+        &self,
+        scale: f64,
+        diffs: &mut [iso_adj::IsometryDifferential],
+    ) -> f64 {
+        if self.energy_weight == 0.0 {
+            return 0.0;
+        }
+
+        let bonds = self.bonds();
+        let gradients = mobius_gradient(
+            &self.chain.spec,
+            &self.chain.joints,
+            &self.symmetries,
+            |i, sym_i, j| bonds.contains(i, sym_i, j),
+        );
+        let weight = scale * self.energy_weight;
+        for (diff, gradient) in diffs.iter_mut().zip(gradients.iter()) {
+            diff.d_x += weight * gradient.x;
+            diff.d_y += weight * gradient.y;
+            diff.d_z += weight * gradient.z;
+        }
+        self.energy_weight * self.energy()
+    }
+
+    /// Like `Chain::optimize`, but also descends the gradient of the weighted Möbius energy.
+    /// Returns the total cost before the step, including the energy.
+    pub fn optimize(&mut self) -> f64 {
+        let (cost, diffs) = self.cost_and_differentials();
+        self.chain.apply_diffs(1.0, &diffs);
+        cost
+    }
+
+    /// Like `Chain::adaptive_optimize`, but with the weighted Möbius energy included in the
+    /// objective.
+    pub fn adaptive_optimize(&mut self, ratios: &[f64], tolerance: f64) -> f64 {
+        let (cost, diffs) = self.cost_and_differentials();
+        self.line_search(ratios, tolerance, cost, &diffs)
+    }
+
+    fn line_search(
+        &mut self,
+        ratios: &[f64],
+        tolerance: f64,
+        curr_total_cost: f64,
+        diffs: &[iso_adj::IsometryDifferential],
+    ) -> f64 {
+        let energy_weight = self.energy_weight;
+        let symmetries = &self.symmetries;
+        self.chain.line_search(
+            ratios,
+            tolerance,
+            curr_total_cost,
+            diffs,
+            |chain| {
+                let mut result = chain.total_cost();
+                if energy_weight != 0.0 {
+                    result += energy_weight * chain_energy(chain, symmetries);
+                }
+                result
+            },
+        )
+    }
+
+    fn accumulate_forces(&mut self) {
+        assert_eq!(self.forces.len(), 0);
+        self.forces
+            .resize(self.chain.joints.len(), Vector3::new(0.0, 0.0, 0.0));
+
+        let cutoff = self.cutoff();
+        if cutoff.is_finite() {
+            self.accumulate_forces_partitioned(cutoff);
+        } else {
+            self.accumulate_forces_exhaustive();
+        }
+    }
+
+    /// The total magnitude of the forces `repulse` would apply to the joints which are free to
+    /// move, without applying them.  When the Möbius energy takes the place of those forces, this is
+    /// instead the total magnitude of the weighted gradient of the energy at those joints.
+    pub fn repulsion_magnitude(&mut self) -> f64 {
+        if self.energy_weight != 0.0 {
+            let mut diffs = vec![iso_adj::IsometryDifferential::zero(); self.chain.joints.len()];
+            self.add_energy_differentials(1.0, &mut diffs);
+            return diffs
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| self.chain.constraint(i).allows_translation())
+                .map(|(_, diff)| Vector3::new(diff.d_x, diff.d_y, diff.d_z).norm())
+                .sum();
+        }
+        self.accumulate_forces();
+        let mut result = 0.0;
+        for (i, force) in self.forces.iter().enumerate() {
+            if self.chain.constraint(i).allows_translation() {
+                result += force.norm();
+            }
         }
         self.forces.clear();
+        result
+    }
+
+    fn optimize_checked(&mut self) -> Result<f64, NonFinite> {
+        let cost = self.optimize();
+        check_cost(cost)?;
+        check_joints(&self.chain.joints)?;
+        Ok(cost)
+    }
+
+    /// Like `optimize`, but recovering from non-finite values as described by `guard`.
+    pub fn try_optimize(&mut self, guard: &DivergenceGuard) -> Result<f64, DivergenceError> {
+        guarded(self, guard, Self::optimize_checked)
+    }
+
+    fn adaptive_optimize_checked(
+        &mut self,
+        ratios: &[f64],
+        tolerance: f64,
+    ) -> Result<f64, NonFinite> {
+        let (cost, diffs) = self.cost_and_differentials();
+        check_cost(cost)?;
+        check_differentials(&diffs)?;
+        let cost = self.line_search(ratios, tolerance, cost, &diffs);
+        check_joints(&self.chain.joints)?;
+        Ok(cost)
+    }
+
+    /// Like `adaptive_optimize`, but recovering from non-finite values as described by `guard`.
+    pub fn try_adaptive_optimize(
+        &mut self,
+        ratios: &[f64],
+        tolerance: f64,
+        guard: &DivergenceGuard,
+    ) -> Result<f64, DivergenceError> {
+        guarded(self, guard, |chain| chain.adaptive_optimize_checked(ratios, tolerance))
+    }
+
+    /// Like `repulse`, but leaving the chain untouched if any force is not finite.
+    fn repulse_checked(&mut self) -> Result<f64, NonFinite> {
+        if self.energy_weight != 0.0 {
+            return Ok(0.0);
+        }
+        self.accumulate_forces();
+        for (joint, force) in self.forces.iter().enumerate() {
+            if !force.iter().all(|x| x.is_finite()) {
+                self.forces.clear();
+                return Err(NonFinite::Force { joint });
+            }
+        }
+        self.apply_forces();
+        Ok(0.0)
+    }
+
+    /// Like `repulse`, but recovering from non-finite forces as described by `guard`.
+    pub fn try_repulse(&mut self, guard: &DivergenceGuard) -> Result<(), DivergenceError> {
+        guarded(self, guard, Self::repulse_checked).map(|_| ())
+    }
+
+    /// Push every joint which is free to move away from the joints it is not bonded to, unless the
+    /// Möbius energy takes the place of the repulsion.
+    pub fn repulse(&mut self) {
+        if self.energy_weight != 0.0 {
+            return;
+        }
+        self.accumulate_forces();
+        self.apply_forces();
+    }
+
+    fn apply_forces(&mut self) {
+        for (i, force) in self.forces.iter().enumerate() {
+            if !self.chain.constraint(i).allows_translation() {
+                continue;
+            }
+            self.chain.joints[i].translation.vector += force * self.chain.descent_rate;
+        }
+        self.forces.clear();
+    }
+}
+
+impl Objective for RepulsionChain {
+    type Point = Vec<Isometry3<f64>>;
+
+    fn point(&self) -> Vec<Isometry3<f64>> {
+        self.chain.point()
+    }
+
+    fn set_point(&mut self, joints: Vec<Isometry3<f64>>) {
+        self.chain.set_point(joints);
+    }
+
+    fn cost_and_gradient(&self) -> (f64, Vec<f64>) {
+        let (connection_cost, mut diffs) = self.chain.gradient();
+        let mut cost = connection_cost + self.add_energy_differentials(1.0, &mut diffs);
+        if self.energy_weight == 0.0 {
+            let (repulsion_energy, forces) = self.repulsion_energy_and_forces();
+            for (diff, force) in diffs.iter_mut().zip(forces.iter()) {
+                diff.d_x -= force.x;
+                diff.d_y -= force.y;
+                diff.d_z -= force.z;
+            }
+            cost += repulsion_energy;
+        }
+        (cost, self.chain.stack_gradient(&diffs))
+    }
+
+    fn retract(&mut self, step: &[f64]) {
+        self.chain.retract(step);
+    }
+}
+
+fn chain_energy(chain: &Chain, symmetries: &[Isometry3<f64>]) -> f64 {
+    let bonds = Bonds::of_chain(chain, symmetries);
+    mobius_energy(&chain.spec, &chain.joints, symmetries, |i, sym_i, j| {
+        bonds.contains(i, sym_i, j)
+    })
+}
+
+fn cell_index(cell_size: f64, point: &Point3<f64>) -> Point3<i32> {
+    Point3::new(
+        (point.x / cell_size).floor() as i32,
+        (point.y / cell_size).floor() as i32,
+        (point.z / cell_size).floor() as i32,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use optimize_tools::*;
+
+    use std::f64::{INFINITY, NAN};
+
+    use approx_locking_angle::locking_angle_aligned;
+    use cost::{CostModel, DistancePenalty, Gating, LockingPenalty, Thresholds};
+    use defaults::continuous_optimization::{
+        COST_PARAMS, DIVERGENCE_GUARD, LBFGS_PARAMS, MAX_REPULSION_STRENGTH, RATE,
+    };
+    use lbfgs::Termination;
+    use geometries::curve_9_40;
+
+    fn forces(chain: &mut RepulsionChain, partitioned: bool) -> Vec<Vector3<f64>> {
+        chain.forces.clear();
+        chain
+            .forces
+            .resize(chain.chain.joints.len(), Vector3::new(0.0, 0.0, 0.0));
+        if partitioned {
+            let cutoff = chain.cutoff();
+            chain.accumulate_forces_partitioned(cutoff);
+        } else {
+            chain.accumulate_forces_exhaustive();
+        }
+        chain.forces.drain(..).collect()
+    }
+
+    #[test]
+    fn partitioned_matches_exhaustive() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        // Cutoff large enough to include every pair of joints
+        chain.cutoff_fraction = 1e-9;
+
+        let exhaustive = forces(&mut chain, false);
+        let partitioned = forces(&mut chain, true);
+
+        for (a, b) in exhaustive.iter().zip(partitioned.iter()) {
+            assert_relative_eq!(a, b, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn far_field_approximates_exhaustive() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.cutoff_fraction = 0.001;
+        assert!(chain.cutoff().is_finite());
+        let exhaustive = forces(&mut chain, false);
+        let truncated = forces(&mut chain, true);
+
+        chain.far_field = true;
+        let approximated = forces(&mut chain, true);
+
+        let error = |forces: &[Vector3<f64>]| {
+            exhaustive
+                .iter()
+                .zip(forces)
+                .map(|(a, b)| (a - b).norm())
+                .fold(0.0, f64::max)
+        };
+        assert!(error(&approximated) <= 0.01 * MAX_REPULSION_STRENGTH);
+        assert!(error(&approximated) < error(&truncated));
+    }
+
+    #[test]
+    fn exhaustive_by_default() {
+        let chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        assert_eq!(chain.cutoff(), INFINITY);
+        assert!(!chain.far_field);
+    }
+
+    /// The differentials of each joint's cost, approximated with finite differences.
+    fn finite_differentials<C: ConnectionCost>(
+        chain: &Chain<C>,
+    ) -> Vec<iso_adj::IsometryDifferential> {
+        let mut pre_joint = chain.get_phantom(&chain.pre_phantom);
+        let mut pre_leg = chain.pre_phantom.leg;
+        (0..chain.joints.len())
+            .map(|i| {
+                let joint = chain.joints[i];
+                let (post_joint, post_leg) = if i + 1 < chain.joints.len() {
+                    (chain.joints[i + 1], Leg::Incoming)
+                } else {
+                    (chain.get_phantom(&chain.post_phantom), chain.post_phantom.leg)
+                };
+                let (_, diff) = iso_adj::differentiate(&chain.steps, joint, |new_joint| {
+                    chain.cost_between(&pre_joint, &pre_leg, &new_joint, &Leg::Incoming)
+                        + chain.cost_between(&new_joint, &Leg::Outgoing, &post_joint, &post_leg)
+                });
+                pre_joint = joint;
+                pre_leg = Leg::Outgoing;
+                diff
+            }).collect()
+    }
+
+    fn assert_differentials_match<C: ConnectionCost>(chain: &Chain<C>) {
+        let (total_cost, analytic) = chain.differentials();
+        assert_relative_eq!(total_cost, chain.total_cost(), epsilon = 1e-9);
+        for (a, b) in analytic.iter().zip(finite_differentials(chain).iter()) {
+            let tolerance = 1e-4 * (1.0 + b.magnitude_squ(1.0).sqrt());
+            assert_relative_eq!(a.d_x, b.d_x, epsilon = tolerance);
+            assert_relative_eq!(a.d_y, b.d_y, epsilon = tolerance);
+            assert_relative_eq!(a.d_z, b.d_z, epsilon = tolerance);
+            assert_relative_eq!(a.d_i, b.d_i, epsilon = tolerance);
+            assert_relative_eq!(a.d_j, b.d_j, epsilon = tolerance);
+            assert_relative_eq!(a.d_k, b.d_k, epsilon = tolerance);
+        }
+    }
+
+    #[test]
+    fn analytic_matches_finite_differences() {
+        let chain = curve_9_40::repulsion_chain(COST_PARAMS).chain;
+        assert_differentials_match(&chain);
+
+        let model = CostModel {
+            dist_weight: 4.5,
+            axis_weight: 3.0,
+            locking_weight: 0.5,
+            distance: DistancePenalty::Huber { delta: 0.1 },
+            locking: LockingPenalty::Cosine,
+            gating: Gating::Sigmoid {
+                thresholds: Thresholds {
+                    dist_for_axis: 0.5,
+                    axis_for_locking: 0.1,
+                },
+                dist_width: 0.2,
+                axis_width: 0.05,
+            },
+        };
+        let smooth_chain = Chain::new(
+            chain.spec,
+            chain.num_angles,
+            chain.pre_phantom,
+            chain.post_phantom,
+            model,
+            chain.return_to_initial_weight,
+            chain.descent_rate,
+            chain.steps,
+            chain.joints.clone(),
+        );
+        assert_differentials_match(&smooth_chain);
+    }
+
+    #[test]
+    fn exact_gradient() {
+        let chain = curve_9_40::repulsion_chain(COST_PARAMS).chain;
+        let (total_cost, analytic) = chain.gradient();
+        assert_relative_eq!(total_cost, chain.total_cost(), epsilon = 1e-9);
+
+        // Perturbing the first and last joints also moves the phantoms
+        for (i, a) in analytic.iter().enumerate() {
+            let (_, b) = iso_adj::differentiate(&chain.steps, chain.joints[i], |new_joint| {
+                let mut perturbed = chain.clone();
+                perturbed.joints[i] = *new_joint;
+                perturbed.total_cost()
+            });
+            let tolerance = 1e-4 * (1.0 + b.magnitude_squ(1.0).sqrt());
+            assert_relative_eq!(a.d_x, b.d_x, epsilon = tolerance);
+            assert_relative_eq!(a.d_y, b.d_y, epsilon = tolerance);
+            assert_relative_eq!(a.d_z, b.d_z, epsilon = tolerance);
+            assert_relative_eq!(a.d_i, b.d_i, epsilon = tolerance);
+            assert_relative_eq!(a.d_j, b.d_j, epsilon = tolerance);
+            assert_relative_eq!(a.d_k, b.d_k, epsilon = tolerance);
+        }
+    }
+
+    #[test]
+    fn lbfgs_decreases_cost() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS).chain;
+        let initial_cost = chain.total_cost();
+        let report = chain.lbfgs_optimize(&LbfgsParams {
+            max_iterations: 200,
+            ..LBFGS_PARAMS
+        });
+
+        assert_eq!(report.cost_history.len(), report.iterations as usize + 1);
+        assert_relative_eq!(report.cost_history[0], initial_cost, epsilon = 1e-9);
+        assert_relative_eq!(report.final_cost, chain.total_cost(), epsilon = 1e-9);
+        for pair in report.cost_history.windows(2) {
+            assert!(pair[1] <= pair[0]);
+        }
+        assert!(report.final_cost < 0.5 * initial_cost);
+        assert!(report.termination != Termination::LineSearchFailed);
+    }
+
+    /// The cost minimized by `RepulsionChain::lbfgs_optimize`.
+    fn full_cost(chain: &RepulsionChain) -> f64 {
+        if chain.energy_weight == 0.0 {
+            chain.total_cost() + chain.repulsion_energy()
+        } else {
+            chain.total_cost()
+        }
+    }
+
+    #[test]
+    fn full_cost_gradient() {
+        for &energy_weight in &[0.0, 0.01] {
+            let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+            chain.energy_weight = energy_weight;
+            let (cost, gradient) = chain.cost_and_gradient();
+            assert_relative_eq!(cost, full_cost(&chain), epsilon = 1e-9);
+            assert!(chain.repulsion_energy() > 0.0);
+
+            // The rotations do not affect the repulsion or the energy, so only check translations
+            let (_, connection_gradient) = chain.chain.cost_and_gradient();
+            let step = 1e-6;
+            for i in 0..chain.joints.len() {
+                for axis in 0..3 {
+                    let mut stepped = chain.clone();
+                    stepped.chain.joints[i].translation.vector[axis] += step;
+                    let cost_plus = full_cost(&stepped);
+                    stepped.chain.joints[i].translation.vector[axis] -= 2.0 * step;
+                    let cost_minus = full_cost(&stepped);
+                    let expected = (cost_plus - cost_minus) / (2.0 * step);
+                    assert_relative_eq!(
+                        gradient[i * 6 + axis],
+                        expected,
+                        epsilon = 1e-4 * (1.0 + expected.abs())
+                    );
+                }
+                for axis in 3..6 {
+                    assert_relative_eq!(
+                        gradient[i * 6 + axis],
+                        connection_gradient[i * 6 + axis],
+                        epsilon = 1e-12
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lbfgs_decreases_full_cost() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.energy_weight = 0.01;
+        let initial_cost = full_cost(&chain);
+        let report = chain.lbfgs_optimize(&LbfgsParams {
+            max_iterations: 100,
+            ..LBFGS_PARAMS
+        });
+        assert_relative_eq!(report.cost_history[0], initial_cost, epsilon = 1e-9);
+        assert_relative_eq!(report.final_cost, full_cost(&chain), epsilon = 1e-9);
+        assert!(report.final_cost < initial_cost);
+    }
+
+    #[test]
+    fn energy_replaces_repulsion() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.energy_weight = 0.0;
+        assert!(chain.repulsion_magnitude() > 0.0);
+
+        chain.energy_weight = 0.01;
+        let joints = chain.joints.clone();
+        chain.repulse();
+        chain.try_repulse(&DIVERGENCE_GUARD).unwrap();
+        assert_eq!(chain.joints, joints);
+        // The magnitude reported is that of the energy's gradient instead
+        assert!(chain.repulsion_magnitude() > 0.0);
+    }
+
+    #[test]
+    fn energy_gradient_matches_finite_differences() {
+        let chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        let last = chain.joints.len() - 1;
+
+        // The ends connect to their copies under the flip and `adjacent_symmetry(3, 1)`, which are
+        // their own inverses
+        let bonds = chain.bonds();
+        assert!(bonds.contains(0, 1, 0));
+        assert!(bonds.contains(last, 3, last));
+        assert!(!bonds.contains(last, 2, last));
+        assert!(!bonds.contains(last, 4, last));
+
+        let gradient = mobius_gradient(
+            &chain.spec,
+            &chain.joints,
+            &chain.symmetries,
+            |i, sym_i, j| bonds.contains(i, sym_i, j),
+        );
+        let step = 1e-6;
+        for i in 0..chain.joints.len() {
+            for axis in 0..3 {
+                let mut stepped = chain.clone();
+                stepped.chain.joints[i].translation.vector[axis] += step;
+                let energy_plus = stepped.energy();
+                stepped.chain.joints[i].translation.vector[axis] -= 2.0 * step;
+                let energy_minus = stepped.energy();
+                assert_relative_eq!(
+                    (energy_plus - energy_minus) / (2.0 * step),
+                    gradient[i][axis],
+                    epsilon = 1e-5 * (1.0 + gradient[i][axis].abs())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn energy_descent() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.energy_weight = 0.01;
+        let initial_cost = chain.total_cost();
+        // The returned cost is that of the chain before the step, energy included
+        assert_relative_eq!(chain.optimize(), initial_cost, epsilon = 1e-9);
+        assert!(chain.total_cost() < initial_cost);
+    }
+
+    #[test]
+    fn energy_step_consistent() {
+        // The energy is weighted in the step as it is in the cost, so the actual change in cost is
+        // twice the expected one with or without it, as for the connection cost alone
+        for &energy_weight in &[0.0, 0.1] {
+            let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+            chain.energy_weight = energy_weight;
+            chain.descent_rate = 1e-7;
+            let (cost, diffs) = chain.cost_and_differentials();
+            assert_relative_eq!(cost, chain.total_cost(), epsilon = 1e-9);
+            let expected = chain.chain.expected_delta_cost(1.0, &diffs);
+            chain.chain.apply_diffs(1.0, &diffs);
+            assert_relative_eq!(chain.total_cost() - cost, 2.0 * expected, max_relative = 1e-4);
+        }
+    }
+
+    #[test]
+    fn constrained_joints() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.constraints = vec![JointConstraint::FixedPose, JointConstraint::FixedTwist];
+        let fixed = chain.joints[0];
+        let twisted = chain.joints[1];
+
+        // The twists at both junctions of the twist-fixed joint, in clicks
+        let twists = |chain: &RepulsionChain| {
+            let spec = chain.spec;
+            [0, 1]
+                .iter()
+                .map(|&i| {
+                    locking_angle_aligned(
+                        chain.num_angles,
+                        &(chain.joints[i] * spec.origin_to_out()),
+                        &(chain.joints[i + 1] * spec.origin_to_in()),
+                    )
+                }).collect::<Vec<_>>()
+        };
+        let initial_twists = twists(&chain);
+
+        // Neither joint meeting the twist-fixed one may rotate so as to change the twist between
+        // them, while translations are unrestricted
+        let (_, diffs) = chain.differentials();
+        for i in 1..3 {
+            let restricted = chain.restrict(i, &diffs[i]);
+            let rotation = Vector3::new(restricted.d_i, restricted.d_j, restricted.d_k);
+            let gradients = chain.fixed_twist_gradients(i);
+            assert!(!gradients.is_empty());
+            for gradient in gradients {
+                assert_relative_eq!(gradient.dot(&rotation), 0.0, epsilon = 1e-12);
+            }
+            assert_eq!(restricted.d_x, diffs[i].d_x);
+        }
+        assert!(chain.fixed_twist_gradients(3).is_empty());
+
+        for _ in 0..1000 {
+            chain.adaptive_optimize(&[2.0, 1.0, 0.5], 0.5);
+            chain.repulse();
+            chain.return_to_initial();
+        }
+        chain.lbfgs_optimize(&LbfgsParams {
+            max_iterations: 20,
+            ..LBFGS_PARAMS
+        });
+
+        assert_eq!(chain.joints[0], fixed);
+        assert!(chain.joints[1] != twisted);
+        assert!(chain.joints[2] != fixed);
+        for (twist, initial) in twists(&chain).iter().zip(&initial_twists) {
+            assert_relative_eq!(twist, initial, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn divergence_rolls_back() {
+        let guard = DivergenceGuard {
+            max_retries: 3,
+            rate_shrink: 0.5,
+        };
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS).chain;
+        let original = chain.joints.clone();
+
+        let mut calls = 0;
+        let cost = guarded(&mut chain, &guard, |chain| {
+            calls += 1;
+            if calls <= 2 {
+                chain.joints[3].translation.vector.x = NAN;
+                check_joints(&chain.joints)?;
+            }
+            Ok(1.0)
+        });
+        assert_eq!(cost, Ok(1.0));
+        assert_eq!(chain.joints, original);
+        assert_relative_eq!(chain.descent_rate, RATE * 0.25);
+
+        let error = guarded(&mut chain, &guard, |chain| {
+            chain.joints[3].translation.vector.x = NAN;
+            check_joints(&chain.joints)?;
+            Ok(1.0)
+        }).unwrap_err();
+        assert_eq!(error.cause, NonFinite::Joint { joint: 3 });
+        assert_eq!(error.attempts, guard.max_retries + 1);
+        assert_relative_eq!(error.descent_rate, RATE * 0.25 * 0.5f64.powi(3));
+        assert_eq!(chain.joints, original);
+    }
+
+    #[test]
+    fn divergence_detected() {
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.joints[2].translation.vector.y = NAN;
+
+        let error = chain
+            .try_adaptive_optimize(&[2.0, 1.0, 0.5], 0.5, &DIVERGENCE_GUARD)
+            .unwrap_err();
+        assert_eq!(error.cause, NonFinite::Joint { joint: 2 });
+        assert_eq!(error.attempts, 0);
+        assert_relative_eq!(error.descent_rate, RATE);
+        assert_eq!(
+            error.to_string(),
+            "the transform of joint 2 was not finite before the first step"
+        );
+        assert!(chain.try_optimize(&DIVERGENCE_GUARD).is_err());
+
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        chain.repulsion_strength = INFINITY;
+        chain.max_repulsion_strength = INFINITY;
+        let joints = chain.joints.clone();
+        let error = chain.try_repulse(&DIVERGENCE_GUARD).unwrap_err();
+        match error.cause {
+            NonFinite::Force { .. } => {}
+            cause => panic!("Expected a non-finite force, found {:?}", cause),
+        }
+        assert_eq!(error.attempts, DIVERGENCE_GUARD.max_retries + 1);
+        assert_eq!(chain.joints, joints);
+
+        let mut chain = curve_9_40::repulsion_chain(COST_PARAMS);
+        let joints = chain.joints.clone();
+        chain.try_adaptive_optimize(&[2.0, 1.0, 0.5], 0.5, &DIVERGENCE_GUARD).unwrap();
+        chain.try_repulse(&DIVERGENCE_GUARD).unwrap();
+        assert!(chain.joints != joints);
     }
 }
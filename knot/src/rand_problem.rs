@@ -1,7 +1,10 @@
+use std::f64::consts::PI;
+
 use rand::distributions::{Distribution, Normal};
 use rand::Rng;
 
 use cost::CostParams;
+use joint::{at_angles, discrete_angles, JointSpec};
 use symmetry_adjust::Problem;
 
 use nalgebra::{Isometry3, Quaternion, Translation3, Unit, UnitQuaternion, Vector3};
@@ -53,3 +56,36 @@ pub fn rand_problem<R: Rng>(
         symmetry_count - 1,
     )
 }
+
+/// Generate a chain of `joint_count` joints at random discrete angles, starting at the origin like
+/// the chains passed to `filter::points`.
+pub fn rand_chain<R: Rng>(
+    rng: &mut R,
+    spec: JointSpec,
+    num_angles: u16,
+    joint_count: usize,
+) -> Vec<Isometry3<f64>> {
+    let angles = (0..joint_count)
+        .map(|_| rng.gen_range(0, num_angles as i32))
+        .collect::<Vec<_>>();
+    at_angles(
+        discrete_angles(spec, num_angles, angles.into_iter()),
+        Isometry3::identity(),
+    ).collect()
+}
+
+/// Generate a random placement for a chain, in a random orientation at a distance of up to
+/// `max_offset` from the z axis.  Symmetric copies of a randomly placed chain collide with each
+/// other only some of the time, which makes them useful for exercising collision tests.
+pub fn rand_placement<R: Rng>(rng: &mut R, max_offset: f64) -> Isometry3<f64> {
+    let offset_angle = rng.gen_range(0.0, 2.0 * PI);
+    let offset_radius = rng.gen_range(0.0, max_offset);
+    Isometry3::from_parts(
+        Translation3::new(
+            offset_radius * offset_angle.cos(),
+            offset_radius * offset_angle.sin(),
+            rng.gen_range(-max_offset, max_offset),
+        ),
+        rand_quaternion(rng),
+    )
+}
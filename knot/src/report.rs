@@ -1,12 +1,16 @@
+use std::f64::consts::PI;
+
 use alga::general::SubsetOf;
 use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
 
-use approx_locking_angle::locking_angle_opposing;
-use cost::{CostParams, Costs};
+use approx_locking_angle::{locking_angle_aligned, locking_angle_opposing};
+use cost::{ConnectionCost, CostParams, Costs};
 use defaults;
 use joint::{at_angles, discrete_symmetric_angles, JointSpec};
-use symmetry::adjacent_symmetry;
+use optimize_tools::JointConstraint;
+use symmetry::{adjacent_symmetry, symmetries_with_skip};
 use symmetry_adjust::{self, Problem};
+use topology::Invariants;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct KnotReport {
@@ -16,6 +20,15 @@ pub struct KnotReport {
     pub symmetry_adjust: Option<symmetry_adjust::Vars>,
     pub costs: Option<Costs>,
     pub total_cost: f64,
+
+    /// The invariants of the knot formed by the chain, if computed.
+    #[serde(default)]
+    pub invariants: Option<Invariants>,
+
+    /// How deeply the deepest pair of colliding joints interpenetrate, or zero if the chain is free
+    /// of collisions.
+    #[serde(default)]
+    pub penetration: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -127,6 +140,144 @@ pub fn complete_report(reports: &CompleteKnotReports, index: usize) -> CompleteK
     }
 }
 
+/// Lay out a completed report as a concrete geometry, with every joint of the chain in its final
+/// position.
+pub fn knot_geometry(reports: &CompleteKnotReports, knot: &CompleteKnotReport) -> KnotGeometry {
+    let mut isometries = Vec::new();
+
+    match reports.parity {
+        JointsParity::Even => {}
+        JointsParity::Odd => isometries.push(reports.joint_spec.origin_to_symmetric()),
+    }
+
+    isometries.extend(at_angles(
+        discrete_symmetric_angles(
+            reports.joint_spec,
+            reports.num_angles,
+            reports.parity,
+            knot.angles.iter().cloned().map(|angle| angle as i32),
+        ),
+        match reports.parity {
+            JointsParity::Even => Isometry3::identity(),
+            JointsParity::Odd => {
+                reports.joint_spec.origin_to_symmetric() * reports.joint_spec.origin_to_out()
+            }
+        },
+    ));
+
+    let adjust_trans = knot.symmetry_adjust.transform();
+
+    let transforms = isometries
+        .iter()
+        .cloned()
+        .map(|iso| Transform::from_isometry(adjust_trans * iso))
+        .collect::<Vec<_>>();
+
+    let symms = symmetries_with_skip(reports.symmetry_count, reports.symmetry_skip)
+        .map(|quat| Transform::from_isometry(quat.to_superset()))
+        .collect::<Vec<_>>();
+
+    KnotGeometry {
+        joint_spec: reports.joint_spec,
+        num_angles: reports.num_angles,
+        cost_params: reports.cost_params,
+        parity: reports.parity,
+        symmetries: symms,
+        transforms,
+        constraints: Vec::new(),
+    }
+}
+
+/// The discrete angles best describing a chain whose joints may not be at exactly valid angles.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecoveredAngles {
+    /// The angles of the joints, in the form used by `KnotReport`.
+    pub angles: Vec<i32>,
+
+    /// The difference between each continuous angle and the integer in `angles` it was rounded to,
+    /// in units of the step between valid angles.
+    pub rounding_errors: Vec<f64>,
+
+    /// The locking angle between the last joint and its copy in the adjacent horseshoe.
+    pub final_angle: f64,
+
+    pub parity: JointsParity,
+
+    /// The cost of the connection between the last joint and its copy in the adjacent horseshoe.
+    pub final_cost: f64,
+}
+
+impl RecoveredAngles {
+    pub fn report(&self, num_angles: u16) -> KnotReport {
+        KnotReport {
+            angles: self.angles.clone(),
+            final_angle: Some(self.final_angle),
+            angle_parity: (self.angles.iter().sum::<i32>() + (self.final_angle.round() as i32))
+                % (num_angles as i32),
+            symmetry_adjust: None,
+            costs: None,
+            total_cost: self.final_cost,
+            invariants: None,
+            penetration: 0.0,
+        }
+    }
+}
+
+/// Recover the discrete angles of a chain of joints, inverting `at_angles` and
+/// `discrete_symmetric_angles`.  The chain may be in any position, and its joints may deviate from
+/// valid angles, as for the output of continuous optimization.
+///
+/// * `mirror`: The symmetry mapping the first joint to the copy its incoming leg meets.
+/// * `adjacent`: The symmetry mapping the last joint to the copy its outgoing leg meets.
+///
+/// For odd parity, the first joint is assumed to be the one straddling the chain's axis of
+/// symmetry, as placed by `knot_geometry`, and is not given an angle.
+pub fn recover_angles<C: ConnectionCost>(
+    spec: &JointSpec,
+    num_angles: u16,
+    cost_params: &C,
+    parity: JointsParity,
+    joints: &[Isometry3<f64>],
+    mirror: &Isometry3<f64>,
+    adjacent: &Isometry3<f64>,
+) -> RecoveredAngles {
+    let steps = num_angles as f64;
+    let normalize = |angle: f64| ((angle % steps) + steps) % steps;
+
+    let mut continuous = Vec::with_capacity(joints.len());
+    if let JointsParity::Even = parity {
+        // The first joint is attached to its own mirror image, with half its angle applied to each,
+        // so that the two meet at half a revolution minus the angle.
+        let first_in = joints[0] * spec.origin_to_in();
+        let twist = locking_angle_opposing(num_angles, &(mirror * first_in), &first_in);
+        continuous.push(normalize(steps * 0.5 - twist));
+    }
+    for pair in joints.windows(2) {
+        continuous.push(locking_angle_aligned(
+            num_angles,
+            &(pair[0] * spec.origin_to_out()),
+            &(pair[1] * spec.origin_to_in()),
+        ));
+    }
+
+    let last_out = joints[joints.len() - 1] * spec.origin_to_out();
+    let final_out = adjacent * last_out;
+
+    RecoveredAngles {
+        angles: continuous
+            .iter()
+            .map(|&angle| (angle.round() as i32) % (num_angles as i32))
+            .collect(),
+        rounding_errors: continuous
+            .iter()
+            .map(|&angle| angle - angle.round())
+            .collect(),
+        final_angle: locking_angle_opposing(num_angles, &last_out, &final_out),
+        parity,
+        final_cost: cost_params.cost_opposing(num_angles, &last_out, &final_out),
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RotationMatrix {
     pub col_x: [f64; 3],
@@ -181,4 +332,138 @@ pub struct KnotGeometry {
     pub parity: JointsParity,
     pub symmetries: Vec<Transform>,
     pub transforms: Vec<Transform>,
+
+    /// The constraint on each joint during optimization.  Joints beyond the end of this list,
+    /// including all joints of geometries saved without constraints, are free.
+    #[serde(default)]
+    pub constraints: Vec<JointConstraint>,
+}
+
+impl KnotGeometry {
+    /// The transformations of the joints of the chain.
+    pub fn joints(&self) -> Vec<Isometry3<f64>> {
+        self.transforms.iter().map(Transform::to_isometry).collect()
+    }
+
+    /// The transformations mapping the chain to each of its symmetric copies, in the order produced
+    /// by `symmetry::symmetries_with_skip`.
+    pub fn symmetry_isometries(&self) -> Vec<Isometry3<f64>> {
+        self.symmetries.iter().map(Transform::to_isometry).collect()
+    }
+
+    /// The `n` of the dihedral-n symmetry of the knot.
+    pub fn symmetry_count(&self) -> u32 {
+        (self.symmetries.len() / 2) as u32
+    }
+
+    /// How many times the knot winds around the z axis, recovered from the rotation between the
+    /// first two "up" symmetries.
+    pub fn symmetry_skip(&self) -> u32 {
+        let count = self.symmetry_count();
+        if count < 2 {
+            return 0;
+        }
+        let step = self.symmetries[2].to_isometry() * Vector3::x_axis().to_superset();
+        let angle = step.y.atan2(step.x);
+        let skip = (angle / (2.0 * PI) * (count as f64)).round() as i64;
+        (((skip % count as i64) + count as i64) % count as i64) as u32
+    }
+
+    /// Recover the discrete angles of the chain, assuming it is connected to its copies as in
+    /// `continuous_optimize`.  See `recover_angles`.
+    pub fn discrete_angles(&self) -> RecoveredAngles {
+        let symms = self.symmetry_isometries();
+        recover_angles(
+            &self.joint_spec,
+            self.num_angles,
+            &self.cost_params,
+            self.parity,
+            &self.joints(),
+            &symms[1],
+            &symms[3],
+        )
+    }
+
+    /// A one-knot set of reports describing the chain at its nearest discrete angles.
+    pub fn discrete_reports(&self) -> KnotReports {
+        KnotReports {
+            joint_spec: Some(self.joint_spec),
+            num_angles: Some(self.num_angles),
+            symmetry_count: self.symmetry_count(),
+            symmetry_skip: self.symmetry_skip(),
+            cost_params: Some(self.cost_params),
+            knots: vec![self.discrete_angles().report(self.num_angles)],
+            parity: self.parity,
+        }
+    }
+}
+
+/// Fixtures shared by the tests of the modules which work with knot geometries.
+#[cfg(test)]
+pub mod fixtures {
+    use report::*;
+
+    /// Reports describing a single knot with the given angles, using the default joint spec,
+    /// number of angles and cost parameters, and the dihedral-3 symmetry of the trefoil.
+    pub fn reports(parity: JointsParity, angles: Vec<i32>) -> KnotReports {
+        KnotReports {
+            joint_spec: None,
+            num_angles: None,
+            symmetry_count: 3,
+            symmetry_skip: 2,
+            cost_params: None,
+            knots: vec![KnotReport {
+                angles,
+                final_angle: None,
+                angle_parity: 0,
+                symmetry_adjust: None,
+                costs: None,
+                total_cost: 0.0,
+                invariants: None,
+                penetration: 0.0,
+            }],
+            parity,
+        }
+    }
+
+    /// The geometry of the first knot of a set of reports.
+    pub fn first_geometry(reports: KnotReports) -> KnotGeometry {
+        let reports = complete_reports(reports);
+        let knot = complete_report(&reports, 0);
+        knot_geometry(&reports, &knot)
+    }
+
+    /// The geometry of a single knot with the given angles, as described by `reports`.
+    pub fn geometry(parity: JointsParity, angles: Vec<i32>) -> KnotGeometry {
+        first_geometry(reports(parity, angles))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use report::*;
+
+    fn round_trip(parity: JointsParity, angles: Vec<i32>) {
+        let reports = complete_reports(fixtures::reports(parity, angles.clone()));
+        let knot = complete_report(&reports, 0);
+        let geometry = knot_geometry(&reports, &knot);
+
+        let recovered = geometry.discrete_angles();
+        assert_eq!(recovered.angles, angles);
+        for &error in &recovered.rounding_errors {
+            assert_relative_eq!(error, 0.0, epsilon = 1e-6);
+        }
+        assert_relative_eq!(recovered.final_angle, knot.final_angle, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn recover_even_angles() {
+        round_trip(JointsParity::Even, vec![5, 0, 14, 15, 2]);
+        round_trip(JointsParity::Even, vec![11, 3, 8, 1]);
+    }
+
+    #[test]
+    fn recover_odd_angles() {
+        round_trip(JointsParity::Odd, vec![4, 12, 7, 9]);
+    }
 }
@@ -0,0 +1,490 @@
+//! Two-dimensional diagrams of knots as SVG, drawn entirely on the CPU.
+//!
+//! Each view is an orthographic projection of every symmetric copy of the chain, with each leg of
+//! each joint drawn as a straight stroke.  Strokes are painted from back to front, each
+//! over a white outline, so a leg passing in front of another breaks it as in a knot diagram.
+//! Joints and junctions may be labelled as in `assembly::Instructions`, so that a diagram can
+//! accompany the instructions for building the knot.
+
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use nalgebra::{Point2, Point3, Vector3};
+
+use assembly::{Instructions, Part};
+use defaults::svg::{FONT_SIZE, HALO, MARGIN, PANEL_SIZE, STROKE_WIDTH, VIEWS};
+use report::{JointsParity, KnotGeometry};
+use validate::junction_residuals;
+
+/// A direction from which to view a knot.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    /// The angle of the viewer around the z axis, counterclockwise from the x axis, in degrees.
+    pub azimuth: f64,
+
+    /// The angle of the viewer above the xy plane, in degrees.
+    pub elevation: f64,
+}
+
+impl View {
+    pub fn new(azimuth: f64, elevation: f64) -> View {
+        View {
+            azimuth,
+            elevation,
+        }
+    }
+
+    /// Unit vectors pointing right, up and towards the viewer, forming a right-handed frame.
+    pub fn basis(&self) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>) {
+        let (sin_az, cos_az) = (self.azimuth * PI / 180.0).sin_cos();
+        let (sin_el, cos_el) = (self.elevation * PI / 180.0).sin_cos();
+        (
+            Vector3::new(-sin_az, cos_az, 0.0),
+            Vector3::new(-sin_el * cos_az, -sin_el * sin_az, cos_el),
+            Vector3::new(cos_el * cos_az, cos_el * sin_az, sin_el),
+        )
+    }
+
+    /// The position of a point across the view, with y pointing up, and its depth towards the
+    /// viewer.
+    pub fn project(&self, point: &Point3<f64>) -> (Point2<f64>, f64) {
+        let (right, up, towards) = self.basis();
+        let coords = point.coords;
+        (
+            Point2::new(coords.dot(&right), coords.dot(&up)),
+            coords.dot(&towards),
+        )
+    }
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    /// Parse `top`, `front` or `side`, or an azimuth and elevation in degrees separated by a
+    /// comma.
+    fn from_str(s: &str) -> Result<View, String> {
+        match s.to_lowercase().as_str() {
+            "top" => return Ok(View::new(-90.0, 90.0)),
+            "front" => return Ok(View::new(-90.0, 0.0)),
+            "side" => return Ok(View::new(0.0, 0.0)),
+            _ => {}
+        }
+        let angles = s
+            .split(',')
+            .map(|angle| angle.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("Could not parse view {}", s))?;
+        if angles.len() != 2 {
+            return Err(format!("Expected an azimuth and an elevation, not {}", s));
+        }
+        if !(angles[1].abs() <= 90.0) {
+            return Err(format!("Elevation must be between -90 and 90, not {}", angles[1]));
+        }
+        Ok(View::new(angles[0], angles[1]))
+    }
+}
+
+/// How to colour the strokes of a diagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Coloring {
+    /// A hue for each horseshoe, lighter for the second of its two symmetric copies.
+    Symmetry,
+
+    /// From green to red with the cost of the junction at the end of each leg, relative to the
+    /// most costly junction.
+    Cost,
+}
+
+impl FromStr for Coloring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Coloring, String> {
+        match s.to_lowercase().as_str() {
+            "symmetry" => Ok(Coloring::Symmetry),
+            "cost" => Ok(Coloring::Cost),
+            _ => Err(format!("Unknown coloring {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgOptions {
+    /// The views to draw, side by side, at the same scale.
+    pub views: Vec<View>,
+
+    pub coloring: Coloring,
+
+    /// Whether to label every joint and the number of clicks at every junction.
+    pub labels: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        SvgOptions {
+            views: VIEWS
+                .iter()
+                .map(|&(azimuth, elevation)| View::new(azimuth, elevation))
+                .collect(),
+            coloring: Coloring::Symmetry,
+            labels: false,
+        }
+    }
+}
+
+/// One leg of a joint.
+#[derive(Clone, Debug, PartialEq)]
+struct Stroke {
+    start: Point3<f64>,
+    end: Point3<f64>,
+    color: String,
+}
+
+/// A label placed at a point of the knot.
+#[derive(Clone, Debug, PartialEq)]
+struct Label {
+    position: Point3<f64>,
+    text: String,
+
+    /// Whether the label names a joint, rather than giving the clicks at a junction.
+    joint: bool,
+}
+
+fn branch_color(symmetry_index: usize, symmetry_len: usize) -> String {
+    let horseshoes = (symmetry_len / 2).max(1);
+    let hue = 360.0 * ((symmetry_index / 2) as f64) / (horseshoes as f64);
+    let lightness = if symmetry_index % 2 == 0 { 35 } else { 55 };
+    format!("hsl({:.0}, 70%, {}%)", hue, lightness)
+}
+
+fn cost_color(fraction: f64) -> String {
+    format!("hsl({:.0}, 75%, 40%)", 120.0 * (1.0 - fraction.max(0.0).min(1.0)))
+}
+
+/// The weighted cost of each junction of the chain, indexed as by `JunctionResidual::index`, or
+/// `None` if the geometry cannot be validated.  Junctions which do not exist cost nothing.
+fn junction_costs(geometry: &KnotGeometry) -> Option<Vec<f64>> {
+    let params = geometry.cost_params;
+    let mut costs = vec![0.0; geometry.transforms.len() + 1];
+    for junction in junction_residuals(geometry).ok()? {
+        costs[junction.index] = junction.costs.dist * params.dist_weight
+            + junction.costs.axis * params.axis_weight
+            + junction.costs.locking * params.locking_weight;
+    }
+    Some(costs)
+}
+
+/// Every leg of every joint of every symmetric copy of the chain.
+fn strokes(geometry: &KnotGeometry, coloring: Coloring) -> Vec<Stroke> {
+    let spec = geometry.joint_spec;
+    let joints = geometry.joints();
+    let symmetries = geometry.symmetry_isometries();
+
+    let costs = match coloring {
+        Coloring::Symmetry => None,
+        // Geometries which cannot be validated are colored by symmetry instead
+        Coloring::Cost => junction_costs(geometry),
+    };
+    let max_cost = costs
+        .as_ref()
+        .map_or(0.0, |costs| costs.iter().cloned().fold(0.0, f64::max));
+
+    let mut strokes = Vec::with_capacity(2 * symmetries.len() * joints.len());
+    for (s, symmetry) in symmetries.iter().enumerate() {
+        for (j, joint) in joints.iter().enumerate() {
+            let placed = symmetry * joint;
+            let center = Point3::from(placed.translation.vector);
+            let in_end = Point3::from((placed * spec.origin_to_in()).translation.vector);
+            let out_end = Point3::from((placed * spec.origin_to_out()).translation.vector);
+            // Junction `j` is at the incoming end of joint `j`, except that the first joint of an
+            // odd chain is its own mirror image, so its incoming leg mirrors its outgoing one.
+            let in_junction = match geometry.parity {
+                JointsParity::Odd if j == 0 => 1,
+                _ => j,
+            };
+            let legs = [(in_end, center, in_junction), (center, out_end, j + 1)];
+            for &(start, end, junction) in &legs {
+                let color = match costs {
+                    Some(ref costs) if max_cost > 0.0 => cost_color(costs[junction] / max_cost),
+                    Some(_) => cost_color(0.0),
+                    None => branch_color(s, symmetries.len()),
+                };
+                strokes.push(Stroke { start, end, color });
+            }
+        }
+    }
+    strokes
+}
+
+/// A label for every joint at its center, and the clicks of every junction where the legs meet.
+fn labels(geometry: &KnotGeometry) -> Vec<Label> {
+    let spec = geometry.joint_spec;
+    let joints = geometry.joints();
+    let symmetries = geometry.symmetry_isometries();
+    let instructions = Instructions::new(geometry);
+    let placed = |part: Part| {
+        let (s, j) = instructions.locate(part);
+        symmetries[s] * joints[j]
+    };
+
+    let mut labels = Vec::with_capacity(2 * instructions.part_count());
+    for horseshoe in 1..(instructions.horseshoes + 1) {
+        for position in 1..(instructions.joints_per_horseshoe + 1) {
+            let part = Part {
+                horseshoe,
+                position,
+            };
+            labels.push(Label {
+                position: Point3::from(placed(part).translation.vector),
+                text: part.to_string(),
+                joint: true,
+            });
+        }
+    }
+    for step in &instructions.steps {
+        // The legs meeting at the junction are the closest pair of ends of the two joints
+        let ends = |part| {
+            let joint = placed(part);
+            vec![
+                Point3::from((joint * spec.origin_to_in()).translation.vector),
+                Point3::from((joint * spec.origin_to_out()).translation.vector),
+            ]
+        };
+        let (from, to) = (ends(step.from), ends(step.to));
+        let (a, b) = from
+            .iter()
+            .flat_map(|a| to.iter().map(move |b| (*a, *b)))
+            .min_by(|&(a0, b0), &(a1, b1)| {
+                (b0 - a0)
+                    .norm()
+                    .partial_cmp(&(b1 - a1).norm())
+                    .unwrap_or(Ordering::Equal)
+            }).unwrap();
+        labels.push(Label {
+            position: Point3::from((a.coords + b.coords) / 2.0),
+            text: step.clicks.to_string(),
+            joint: false,
+        });
+    }
+    labels
+}
+
+/// The order in which to paint strokes in a view, from the back to the front by the depth of their
+/// midpoints.
+fn paint_order(strokes: &[Stroke], view: &View) -> Vec<usize> {
+    let depths = strokes
+        .iter()
+        .map(|stroke| {
+            let midpoint = Point3::from((stroke.start.coords + stroke.end.coords) / 2.0);
+            view.project(&midpoint).1
+        }).collect::<Vec<_>>();
+    let mut order = (0..strokes.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| depths[a].partial_cmp(&depths[b]).unwrap_or(Ordering::Equal));
+    order
+}
+
+/// Draw a geometry as an SVG document, with its views side by side.  The geometry must have at
+/// least dihedral-2 symmetry to be coloured by cost or labelled.
+pub fn render(geometry: &KnotGeometry, options: &SvgOptions) -> String {
+    let strokes = strokes(geometry, options.coloring);
+    let labels = if options.labels {
+        labels(geometry)
+    } else {
+        Vec::new()
+    };
+
+    // Every view has the same scale, fitting the sphere around the knot's center
+    let count = (2 * strokes.len()) as f64;
+    let center = strokes.iter().fold(Vector3::zeros(), |sum, stroke| {
+        sum + stroke.start.coords + stroke.end.coords
+    }) / count.max(1.0);
+    let radius = geometry.joint_spec.radius();
+    let extent = strokes
+        .iter()
+        .flat_map(|stroke| vec![stroke.start, stroke.end])
+        .map(|point| (point.coords - center).norm())
+        .fold(0.0, f64::max)
+        + radius;
+    let scale = (PANEL_SIZE / 2.0 - MARGIN) / extent.max(radius).max(1e-9);
+
+    let width = STROKE_WIDTH * radius * scale;
+    let halo = width * (1.0 + 2.0 * HALO);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\">",
+        PANEL_SIZE * (options.views.len() as f64),
+        PANEL_SIZE,
+    ).unwrap();
+    writeln!(out, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>").unwrap();
+
+    for (i, view) in options.views.iter().enumerate() {
+        let to_svg = |point: &Point3<f64>| {
+            let (point, _) = view.project(&Point3::from(point.coords - center));
+            Point2::new(
+                PANEL_SIZE / 2.0 + scale * point.x,
+                PANEL_SIZE / 2.0 - scale * point.y,
+            )
+        };
+
+        writeln!(out, "<g transform=\"translate({}, 0)\">", PANEL_SIZE * (i as f64)).unwrap();
+        writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\">\
+             azimuth {}&#176;, elevation {}&#176;</text>",
+            MARGIN / 2.0,
+            MARGIN / 2.0 + FONT_SIZE / 2.0,
+            FONT_SIZE,
+            view.azimuth,
+            view.elevation,
+        ).unwrap();
+
+        writeln!(out, "<g stroke-linecap=\"round\" fill=\"none\">").unwrap();
+        for index in paint_order(&strokes, view) {
+            let stroke = &strokes[index];
+            let (start, end) = (to_svg(&stroke.start), to_svg(&stroke.end));
+            // The outline ends flush with the leg, so it doesn't break the legs joined to it
+            writeln!(
+                out,
+                "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"white\" \
+                 stroke-width=\"{:.2}\" stroke-linecap=\"butt\"/>",
+                start.x, start.y, end.x, end.y, halo,
+            ).unwrap();
+            writeln!(
+                out,
+                "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" \
+                 stroke-width=\"{:.2}\"/>",
+                start.x, start.y, end.x, end.y, stroke.color, width,
+            ).unwrap();
+        }
+        writeln!(out, "</g>").unwrap();
+
+        if !labels.is_empty() {
+            writeln!(
+                out,
+                "<g font-family=\"sans-serif\" font-size=\"{}\" text-anchor=\"middle\" \
+                 dominant-baseline=\"central\" stroke=\"white\" stroke-width=\"3\" \
+                 paint-order=\"stroke\">",
+                FONT_SIZE,
+            ).unwrap();
+            for label in &labels {
+                let position = to_svg(&label.position);
+                let style = if label.joint {
+                    "fill=\"black\" font-weight=\"bold\""
+                } else {
+                    "fill=\"#555\" font-style=\"italic\""
+                };
+                writeln!(
+                    out,
+                    "<text x=\"{:.2}\" y=\"{:.2}\" {}>{}</text>",
+                    position.x, position.y, style, label.text,
+                ).unwrap();
+            }
+            writeln!(out, "</g>").unwrap();
+        }
+        writeln!(out, "</g>").unwrap();
+    }
+    writeln!(out, "</svg>").unwrap();
+    out
+}
+
+impl KnotGeometry {
+    /// Draw the knot as an SVG document.  See `svg::render`.
+    pub fn svg(&self, options: &SvgOptions) -> String {
+        render(self, options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use svg::*;
+
+    use report::fixtures::geometry;
+    use report::JointsParity;
+
+    #[test]
+    fn views() {
+        let point = Point3::new(1.0, 2.0, 3.0);
+
+        let (across, depth) = "top".parse::<View>().unwrap().project(&point);
+        assert_relative_eq!(across, Point2::new(1.0, 2.0), epsilon = 1e-12);
+        assert_relative_eq!(depth, 3.0, epsilon = 1e-12);
+
+        let (across, depth) = "front".parse::<View>().unwrap().project(&point);
+        assert_relative_eq!(across, Point2::new(1.0, 3.0), epsilon = 1e-12);
+        assert_relative_eq!(depth, -2.0, epsilon = 1e-12);
+
+        let (right, up, towards) = View::new(37.0, -21.0).basis();
+        assert_relative_eq!(right.cross(&up), towards, epsilon = 1e-12);
+        assert_relative_eq!(right.norm(), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(up.norm(), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(right.dot(&up), 0.0, epsilon = 1e-12);
+
+        assert_eq!("30, -45".parse::<View>(), Ok(View::new(30.0, -45.0)));
+        assert!("30".parse::<View>().is_err());
+        assert!("0,100".parse::<View>().is_err());
+        assert!("overhead".parse::<View>().is_err());
+    }
+
+    #[test]
+    fn over_under() {
+        let stroke = |start, end, color: &str| Stroke {
+            start,
+            end,
+            color: color.to_string(),
+        };
+        let strokes = vec![
+            stroke(Point3::new(-1.0, 0.0, 1.0), Point3::new(1.0, 0.0, 1.0), "high"),
+            stroke(Point3::new(0.0, -1.0, 0.0), Point3::new(0.0, 1.0, 0.0), "low"),
+        ];
+        // The nearer stroke is painted last, over the other
+        assert_eq!(paint_order(&strokes, &"top".parse().unwrap()), vec![1, 0]);
+        assert_eq!(paint_order(&strokes, &View::new(0.0, -90.0)), vec![0, 1]);
+    }
+
+    #[test]
+    fn render_diagram() {
+        let geometry = geometry(JointsParity::Even, vec![5, 0, 14, 15, 2]);
+        let legs = 2 * geometry.symmetries.len() * geometry.transforms.len();
+
+        let svg = geometry.svg(&SvgOptions::default());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<line").count(), 2 * legs * VIEWS.len());
+        assert_eq!(svg.matches("stroke-linecap=\"butt\"").count(), legs * VIEWS.len());
+        assert!(!svg.contains("H1-1"));
+
+        let options = SvgOptions {
+            views: vec!["top".parse().unwrap()],
+            coloring: Coloring::Cost,
+            labels: true,
+        };
+        let svg = geometry.svg(&options);
+        assert!(!svg.contains("NaN"));
+        let instructions = Instructions::new(&geometry);
+        for part in 1..(instructions.joints_per_horseshoe + 1) {
+            assert_eq!(svg.matches(&format!(">H3-{}<", part)).count(), 1);
+        }
+        assert_eq!(
+            svg.matches("font-style=\"italic\"").count(),
+            instructions.steps.len()
+        );
+    }
+
+    #[test]
+    fn odd_junction_costs() {
+        // Only the closure of a chain at discrete angles costs anything, and the first joint of an
+        // odd chain has no junction with its mirror image
+        let geometry = geometry(JointsParity::Odd, vec![4, 12, 7, 9]);
+        let costs = junction_costs(&geometry).unwrap();
+        assert_eq!(costs.len(), geometry.transforms.len() + 1);
+        let (closure, internal) = costs.split_last().unwrap();
+        assert!(*closure > 0.0);
+        for &cost in internal {
+            assert_relative_eq!(cost, 0.0, epsilon = 1e-9);
+        }
+    }
+}
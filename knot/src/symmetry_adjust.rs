@@ -2,7 +2,7 @@ use std::f64::consts::{FRAC_PI_2, PI};
 
 use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector2, Vector3};
 
-use cost::{cost_opposing, costs_opposing, CostParams, Costs};
+use cost::{ConnectionCost, CostParams, Costs};
 use symmetry;
 
 /// Analog parameters for how a chain of joints can be positioned in space with symmetry.
@@ -86,8 +86,8 @@ fn cot(theta: f64) -> f64 {
 
 /// A joint-chain-positioning optimization problem to be solved.
 #[derive(Clone, Copy, Debug)]
-pub struct Problem {
-    cost_params: CostParams,
+pub struct Problem<C = CostParams> {
+    cost_params: C,
     last_joint_out: Isometry3<f64>,
     num_angles: u16,
     adjacent_symmetry: UnitQuaternion<f64>,
@@ -96,7 +96,7 @@ pub struct Problem {
     skip: u32,
 }
 
-impl Problem {
+impl<C: ConnectionCost> Problem<C> {
     /// Construct a new problem.
     ///
     /// * `cost_params`: Parameters configuring how to measure the quality of joint positioning and
@@ -113,12 +113,12 @@ impl Problem {
     /// * `symmetry_count`: The dihedral symmetry number of the knot.  Determines how a given joint
     ///   chain is positioned relative to its neighbor to which it is supposed to connect.
     pub fn new(
-        cost_params: CostParams,
+        cost_params: C,
         last_joint_out: Isometry3<f64>,
         num_angles: u16,
         symmetry_count: u32,
         skip: u32,
-    ) -> Problem {
+    ) -> Self {
         let adjacent_symmetry = symmetry::adjacent_symmetry(symmetry_count, skip);
         let radial_angle_normalizer = Vector2::new(
             last_joint_out.translation.vector.y,
@@ -143,8 +143,7 @@ impl Problem {
         let adjust = vars.transform();
         let adjusted_last_joint_out = adjust * self.last_joint_out;
         let opposing_last_joint_out = self.adjacent_symmetry * adjusted_last_joint_out;
-        costs_opposing(
-            &self.cost_params.thresholds,
+        self.cost_params.costs_opposing(
             self.num_angles,
             &adjusted_last_joint_out,
             &opposing_last_joint_out,
@@ -157,8 +156,7 @@ impl Problem {
         let adjust = vars.transform();
         let adjusted_last_joint_out = adjust * self.last_joint_out;
         let opposing_last_joint_out = self.adjacent_symmetry * adjusted_last_joint_out;
-        cost_opposing(
-            &self.cost_params,
+        self.cost_params.cost_opposing(
             self.num_angles,
             &adjusted_last_joint_out,
             &opposing_last_joint_out,
@@ -0,0 +1,712 @@
+use std::fmt;
+use std::str::FromStr;
+
+use nalgebra::{Isometry3, Point3, Unit, Vector2, Vector3};
+
+use defaults::topology::PROJECTION_DIRECTION;
+use joint::JointSpec;
+use report::{JointsParity, KnotGeometry, Transform};
+
+/// Knots which models are commonly built as, with the invariants used to recognize them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KnotType {
+    Unknot,
+    Trefoil,
+    FigureEight,
+    Cinquefoil,
+    ThreeTwist,
+    Stevedore,
+    Knot8_18,
+
+    /// The (3, 4) torus knot.
+    Knot8_19,
+
+    Knot9_40,
+}
+
+impl KnotType {
+    pub fn all() -> &'static [KnotType] {
+        &[
+            KnotType::Unknot,
+            KnotType::Trefoil,
+            KnotType::FigureEight,
+            KnotType::Cinquefoil,
+            KnotType::ThreeTwist,
+            KnotType::Stevedore,
+            KnotType::Knot8_18,
+            KnotType::Knot8_19,
+            KnotType::Knot9_40,
+        ]
+    }
+
+    /// The name of the knot in Alexander-Briggs notation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            &KnotType::Unknot => "0_1",
+            &KnotType::Trefoil => "3_1",
+            &KnotType::FigureEight => "4_1",
+            &KnotType::Cinquefoil => "5_1",
+            &KnotType::ThreeTwist => "5_2",
+            &KnotType::Stevedore => "6_1",
+            &KnotType::Knot8_18 => "8_18",
+            &KnotType::Knot8_19 => "8_19",
+            &KnotType::Knot9_40 => "9_40",
+        }
+    }
+
+    /// The smallest number of crossings in any diagram of the knot.
+    pub fn crossing_number(&self) -> usize {
+        match self {
+            &KnotType::Unknot => 0,
+            &KnotType::Trefoil => 3,
+            &KnotType::FigureEight => 4,
+            &KnotType::Cinquefoil | &KnotType::ThreeTwist => 5,
+            &KnotType::Stevedore => 6,
+            &KnotType::Knot8_18 | &KnotType::Knot8_19 => 8,
+            &KnotType::Knot9_40 => 9,
+        }
+    }
+
+    /// The Alexander polynomial of the knot, normalized as by `alexander_polynomial`.
+    pub fn alexander(&self) -> Vec<i64> {
+        match self {
+            &KnotType::Unknot => vec![1],
+            &KnotType::Trefoil => vec![1, -1, 1],
+            &KnotType::FigureEight => vec![-1, 3, -1],
+            &KnotType::Cinquefoil => vec![1, -1, 1, -1, 1],
+            &KnotType::ThreeTwist => vec![2, -3, 2],
+            &KnotType::Stevedore => vec![-2, 5, -2],
+            &KnotType::Knot8_18 => vec![-1, 5, -10, 13, -10, 5, -1],
+            &KnotType::Knot8_19 => vec![1, -1, 0, 1, 0, -1, 1],
+            &KnotType::Knot9_40 => vec![1, -7, 18, -23, 18, -7, 1],
+        }
+    }
+
+    /// The first known knot type with the given Alexander polynomial.  Distinct knots may share an
+    /// Alexander polynomial, so this is only a guess, although it is a good one among knots with
+    /// few crossings.
+    pub fn identify(alexander: &[i64]) -> Option<KnotType> {
+        KnotType::all()
+            .iter()
+            .cloned()
+            .find(|knot_type| knot_type.alexander() == alexander)
+    }
+}
+
+impl fmt::Display for KnotType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for KnotType {
+    type Err = String;
+
+    /// Parse either the Alexander-Briggs name of a knot or its common name.
+    fn from_str(s: &str) -> Result<KnotType, String> {
+        let lower = s.to_lowercase();
+        let common_name = match lower.as_str() {
+            "unknot" => Some(KnotType::Unknot),
+            "trefoil" => Some(KnotType::Trefoil),
+            "figure-eight" | "figure_eight" => Some(KnotType::FigureEight),
+            "cinquefoil" => Some(KnotType::Cinquefoil),
+            "three-twist" | "three_twist" => Some(KnotType::ThreeTwist),
+            "stevedore" => Some(KnotType::Stevedore),
+            _ => None,
+        };
+        common_name
+            .or_else(|| {
+                KnotType::all()
+                    .iter()
+                    .cloned()
+                    .find(|knot_type| knot_type.name() == lower)
+            }).ok_or_else(|| format!("Unknown knot type {}", s))
+    }
+}
+
+/// A point at which the projection of a closed polyline passes over itself.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Crossing {
+    /// The position of the upper strand along the polyline: `i + t` lies a fraction `t` of the way
+    /// along the segment from point `i` to point `i + 1`.
+    pub over: f64,
+
+    /// The position of the lower strand along the polyline, as for `over`.
+    pub under: f64,
+
+    /// `1` for a right-handed crossing and `-1` for a left-handed one, given the orientation of the
+    /// polyline.
+    pub sign: i32,
+}
+
+/// The crossings of a closed polyline projected onto a plane.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Diagram {
+    /// The number of segments of the polyline, including the one closing it.
+    pub segments: usize,
+
+    pub crossings: Vec<Crossing>,
+
+    /// Whether the projection was generic: no two segments overlap along a line, and no crossing
+    /// lies at a vertex.  Otherwise some crossings may have been missed or counted twice.
+    pub generic: bool,
+}
+
+fn cross_2d(a: &Vector2<f64>, b: &Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Relative tolerance within which a projection is judged not to be generic.
+const GENERIC_TOLERANCE: f64 = 1e-9;
+
+/// Whether a position along a segment lies on it, allowing for `GENERIC_TOLERANCE`.
+fn within(t: f64) -> bool {
+    t >= -GENERIC_TOLERANCE && t <= 1.0 + GENERIC_TOLERANCE
+}
+
+/// Whether a position along a segment lies at one of its ends, within `GENERIC_TOLERANCE`.
+fn near_end(t: f64) -> bool {
+    t.abs() <= GENERIC_TOLERANCE || (t - 1.0).abs() <= GENERIC_TOLERANCE
+}
+
+impl Diagram {
+    /// Project a closed polyline onto the plane perpendicular to `direction`, as seen from a viewer
+    /// far away along `direction`.  The last point is joined back to the first.  The direction
+    /// should be generic, so that no crossing lies at a vertex of the polyline; if not, the
+    /// diagram is marked as such.
+    pub fn project(points: &[Point3<f64>], direction: &Unit<Vector3<f64>>) -> Diagram {
+        let direction = direction.into_inner();
+        // Any basis such that `basis_0 × basis_1 = direction`
+        let helper = if direction.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let basis_1 = direction.cross(&helper).normalize();
+        let basis_0 = basis_1.cross(&direction);
+
+        let projected = points
+            .iter()
+            .map(|p| Vector2::new(p.coords.dot(&basis_0), p.coords.dot(&basis_1)))
+            .collect::<Vec<_>>();
+        let heights = points
+            .iter()
+            .map(|p| p.coords.dot(&direction))
+            .collect::<Vec<_>>();
+
+        // Points closer than this are the same point
+        let same_point = GENERIC_TOLERANCE
+            * points
+                .iter()
+                .map(|p| p.coords.norm())
+                .fold(1.0, f64::max);
+
+        let segments = points.len();
+        let mut crossings = Vec::new();
+        let mut generic = true;
+        for i in 0..segments {
+            for j in (i + 2)..segments {
+                if i == 0 && j + 1 == segments {
+                    // Adjacent through the closing segment
+                    continue;
+                }
+                let (a, b) = (projected[i], projected[(i + 1) % segments]);
+                let (c, d) = (projected[j], projected[(j + 1) % segments]);
+                let (r, s) = (b - a, d - c);
+                let denom = cross_2d(&r, &s);
+                if denom.abs() <= GENERIC_TOLERANCE * r.norm() * s.norm() {
+                    // Parallel segments only meet if they overlap along the same line
+                    let offset = cross_2d(&(c - a), &r);
+                    if offset.abs() <= GENERIC_TOLERANCE * r.norm_squared() {
+                        let t_c = (c - a).dot(&r) / r.norm_squared();
+                        let t_d = (d - a).dot(&r) / r.norm_squared();
+                        if t_c.max(t_d) >= 0.0 && t_c.min(t_d) <= 1.0 {
+                            generic = false;
+                        }
+                    }
+                    continue;
+                }
+                let t = cross_2d(&(c - a), &s) / denom;
+                let u = cross_2d(&(c - a), &r) / denom;
+                if within(t) && within(u) && (near_end(t) || near_end(u)) {
+                    let vertex_i = points[(i + t.round() as usize) % segments];
+                    let vertex_j = points[(j + u.round() as usize) % segments];
+                    if near_end(t) && near_end(u) && (vertex_i - vertex_j).norm() <= same_point {
+                        // The polyline passes through the same point twice in a row, joined by
+                        // segments of no length
+                        continue;
+                    }
+                    generic = false;
+                }
+                if t < 0.0 || t >= 1.0 || u < 0.0 || u >= 1.0 {
+                    continue;
+                }
+
+                let height_i = heights[i] + t * (heights[(i + 1) % segments] - heights[i]);
+                let height_j = heights[j] + u * (heights[(j + 1) % segments] - heights[j]);
+                let (over, under, over_dir, under_dir) = if height_i > height_j {
+                    (i as f64 + t, j as f64 + u, r, s)
+                } else {
+                    (j as f64 + u, i as f64 + t, s, r)
+                };
+                crossings.push(Crossing {
+                    over,
+                    under,
+                    sign: if cross_2d(&over_dir, &under_dir) > 0.0 {
+                        1
+                    } else {
+                        -1
+                    },
+                });
+            }
+        }
+
+        Diagram {
+            segments,
+            crossings,
+            generic,
+        }
+    }
+}
+
+type Polynomial = Vec<i128>;
+
+fn trim(mut p: Polynomial) -> Polynomial {
+    while p.last() == Some(&0) {
+        p.pop();
+    }
+    p
+}
+
+fn poly_mul(a: &[i128], b: &[i128]) -> Polynomial {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0; a.len() + b.len() - 1];
+    for (i, &x) in a.iter().enumerate() {
+        for (j, &y) in b.iter().enumerate() {
+            result[i + j] += x * y;
+        }
+    }
+    trim(result)
+}
+
+fn poly_sub(a: &[i128], b: &[i128]) -> Polynomial {
+    let mut result = vec![0; a.len().max(b.len())];
+    for (i, &x) in a.iter().enumerate() {
+        result[i] += x;
+    }
+    for (i, &y) in b.iter().enumerate() {
+        result[i] -= y;
+    }
+    trim(result)
+}
+
+/// Divide two polynomials with integer coefficients, where the division is known to be exact.
+fn poly_div_exact(a: &[i128], b: &[i128]) -> Polynomial {
+    let mut remainder = a.to_vec();
+    if remainder.len() < b.len() {
+        return Vec::new();
+    }
+    let lead = *b.last().unwrap();
+    let mut quotient = vec![0; remainder.len() - b.len() + 1];
+    for shift in (0..quotient.len()).rev() {
+        let coefficient = remainder[shift + b.len() - 1] / lead;
+        quotient[shift] = coefficient;
+        for (i, &y) in b.iter().enumerate() {
+            remainder[shift + i] -= coefficient * y;
+        }
+    }
+    debug_assert!(remainder.iter().all(|&x| x == 0));
+    trim(quotient)
+}
+
+/// The determinant of a square matrix of polynomials, by fraction-free Gaussian elimination.
+fn determinant(mut matrix: Vec<Vec<Polynomial>>) -> Polynomial {
+    let n = matrix.len();
+    let mut negate = false;
+    let mut prev_pivot: Polynomial = vec![1];
+    for k in 0..n {
+        if matrix[k][k].is_empty() {
+            match (k + 1..n).find(|&i| !matrix[i][k].is_empty()) {
+                Some(i) => {
+                    matrix.swap(k, i);
+                    negate = !negate;
+                }
+                None => return Vec::new(),
+            }
+        }
+        for i in (k + 1)..n {
+            for j in (k + 1)..n {
+                let numerator = poly_sub(
+                    &poly_mul(&matrix[k][k], &matrix[i][j]),
+                    &poly_mul(&matrix[i][k], &matrix[k][j]),
+                );
+                matrix[i][j] = poly_div_exact(&numerator, &prev_pivot);
+            }
+        }
+        prev_pivot = matrix[k][k].clone();
+    }
+
+    let det = if n == 0 {
+        vec![1]
+    } else {
+        matrix[n - 1][n - 1].clone()
+    };
+    if negate {
+        det.iter().map(|&x| -x).collect()
+    } else {
+        det
+    }
+}
+
+/// The Alexander polynomial of the knot represented by a diagram, as its coefficients in order of
+/// increasing degree.  It is normalized to have a nonzero constant term and to take the value 1 at
+/// `t = 1`.  An empty result means the diagram does not faithfully represent a knot, because the
+/// projection was not generic.
+pub fn alexander_polynomial(diagram: &Diagram) -> Vec<i64> {
+    if !diagram.generic {
+        return Vec::new();
+    }
+    let n = diagram.crossings.len();
+    if n == 0 {
+        return vec![1];
+    }
+
+    // The arcs of the diagram run from one undercrossing to the next.  Arc `m` ends at the `m`th
+    // undercrossing along the polyline, so arc 0 wraps around from the last undercrossing.
+    let mut crossings = diagram.crossings.clone();
+    crossings.sort_by(|a, b| a.under.partial_cmp(&b.under).unwrap());
+    let arc_at = |position: f64| crossings.iter().filter(|c| c.under < position).count() % n;
+
+    // Each crossing contributes the Fox derivatives of its Wirtinger relation, with `t` standing
+    // for every generator.
+    let mut matrix = vec![vec![vec![0i128; 2]; n]; n];
+    for (m, crossing) in crossings.iter().enumerate() {
+        let over = arc_at(crossing.over);
+        let incoming = m;
+        let outgoing = (m + 1) % n;
+        matrix[m][over][0] += 1;
+        matrix[m][over][1] -= 1;
+        if crossing.sign > 0 {
+            matrix[m][incoming][1] += 1;
+            matrix[m][outgoing][0] -= 1;
+        } else {
+            matrix[m][incoming][0] -= 1;
+            matrix[m][outgoing][1] += 1;
+        }
+    }
+
+    // Any minor of size n - 1 gives the polynomial, up to a unit
+    let minor = matrix
+        .into_iter()
+        .take(n - 1)
+        .map(|row| row.into_iter().take(n - 1).map(trim).collect())
+        .collect();
+    let det = determinant(minor);
+
+    let low = match det.iter().position(|&x| x != 0) {
+        Some(low) => low,
+        None => return Vec::new(),
+    };
+    let sign = if det.iter().sum::<i128>() < 0 { -1 } else { 1 };
+    det[low..].iter().map(|&x| (sign * x) as i64).collect()
+}
+
+/// Invariants of a closed polyline, from which its knot type can be recognized.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Invariants {
+    /// The number of crossings in the diagram projected along `PROJECTION_DIRECTION`.  This is at
+    /// least the crossing number of the knot, and usually more.
+    pub crossings: usize,
+
+    /// The Alexander polynomial, as by `alexander_polynomial`.  Empty if the projection was not
+    /// generic.
+    pub alexander: Vec<i64>,
+
+    /// The absolute value of the Alexander polynomial at -1, if it could be computed.
+    pub determinant: Option<i64>,
+
+    /// The difference between the highest and lowest powers in the Alexander polynomial, if it
+    /// could be computed.
+    pub degree: Option<usize>,
+
+    /// The known knot type with the same Alexander polynomial, if any.
+    pub knot_type: Option<KnotType>,
+}
+
+impl Invariants {
+    /// The invariants of a closed polyline, projected along `PROJECTION_DIRECTION`.
+    pub fn new(points: &[Point3<f64>]) -> Invariants {
+        let direction = Unit::new_normalize(Vector3::from(PROJECTION_DIRECTION));
+        Invariants::from_diagram(&Diagram::project(points, &direction))
+    }
+
+    /// The invariants of a closed polyline, from its diagram projected along any direction.
+    pub fn from_diagram(diagram: &Diagram) -> Invariants {
+        let alexander = alexander_polynomial(diagram);
+        let computed = !alexander.is_empty();
+        Invariants {
+            crossings: diagram.crossings.len(),
+            determinant: if computed {
+                Some(
+                    alexander
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &coeff)| if i % 2 == 0 { coeff } else { -coeff })
+                        .sum::<i64>()
+                        .abs(),
+                )
+            } else {
+                None
+            },
+            degree: if computed {
+                Some(alexander.len() - 1)
+            } else {
+                None
+            },
+            knot_type: KnotType::identify(&alexander),
+            alexander,
+        }
+    }
+
+    /// Whether the invariants are consistent with the given knot type.
+    pub fn matches(&self, knot_type: KnotType) -> bool {
+        self.alexander == knot_type.alexander() && self.crossings >= knot_type.crossing_number()
+    }
+}
+
+/// The points of a joint chain's closed centerline: the ends of every joint's legs and every
+/// joint's center, across all symmetric copies of the chain.  The copies are joined end to end in
+/// whichever order brings matching ends together, starting from the first copy traversed from its
+/// first joint to its last.
+///
+/// For odd parity, the first joint is assumed to be shared between each pair of copies under the
+/// symmetries at indices `2k` and `2k + 1`, and is only included in the latter, as in
+/// `KnotGeometry`.
+pub fn closed_polyline(
+    spec: &JointSpec,
+    parity: JointsParity,
+    joints: &[Isometry3<f64>],
+    symmetries: &[Isometry3<f64>],
+) -> Vec<Point3<f64>> {
+    let copies = symmetries
+        .iter()
+        .enumerate()
+        .map(|(i, sym)| {
+            let skip = match parity {
+                JointsParity::Odd if i % 2 == 0 => 1,
+                _ => 0,
+            };
+            let joints = &joints[skip..];
+            let mut points = Vec::with_capacity(joints.len() + 2);
+            points.push(Point3::from(
+                (sym * joints[0] * spec.origin_to_in()).translation.vector,
+            ));
+            points.extend(
+                joints
+                    .iter()
+                    .map(|joint| Point3::from((sym * joint).translation.vector)),
+            );
+            points.push(Point3::from(
+                (sym * joints[joints.len() - 1] * spec.origin_to_out()).translation.vector,
+            ));
+            points
+        }).collect::<Vec<_>>();
+    stitch(&copies)
+}
+
+/// Join open polylines end to end into a closed one, in whichever order and orientation brings
+/// matching ends together, starting from the first polyline as given.
+fn stitch(copies: &[Vec<Point3<f64>>]) -> Vec<Point3<f64>> {
+    let mut used = vec![false; copies.len()];
+    used[0] = true;
+    let mut result = copies[0].clone();
+    for _ in 1..copies.len() {
+        let tail = *result.last().unwrap();
+        let mut best = None;
+        let mut best_dist = ::std::f64::INFINITY;
+        for (i, copy) in copies.iter().enumerate() {
+            if used[i] {
+                continue;
+            }
+            for &(end, reversed) in &[(copy[0], false), (copy[copy.len() - 1], true)] {
+                let dist = (end - tail).norm();
+                if dist < best_dist {
+                    best_dist = dist;
+                    best = Some((i, reversed));
+                }
+            }
+        }
+
+        let (i, reversed) = best.unwrap();
+        used[i] = true;
+        // The matching end coincides with the current tail
+        if reversed {
+            result.extend(copies[i].iter().rev().skip(1));
+        } else {
+            result.extend(copies[i].iter().skip(1));
+        }
+    }
+    // The last point coincides with the first
+    result.pop();
+    result
+}
+
+impl KnotGeometry {
+    /// The closed centerline of the knot.  See `closed_polyline`.
+    pub fn polyline(&self) -> Vec<Point3<f64>> {
+        closed_polyline(
+            &self.joint_spec,
+            self.parity,
+            &self.joints(),
+            &self
+                .symmetries
+                .iter()
+                .map(Transform::to_isometry)
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use topology::*;
+
+    use std::f64::consts::PI;
+
+    use alga::general::SubsetOf;
+
+    use defaults::continuous_optimization::{
+        COST_PARAMS, CURVE_9_40_CHAIN_SIZE, RATE, RETURN_TO_INITIAL_WEIGHT,
+    };
+    use geometries::curve_9_40;
+    use symmetry::symmetries;
+
+    fn sample<F: Fn(f64) -> Point3<f64>>(count: usize, curve: F) -> Vec<Point3<f64>> {
+        (0..count)
+            .map(|i| curve(2.0 * PI * (i as f64) / (count as f64)))
+            .collect()
+    }
+
+    fn trefoil() -> Vec<Point3<f64>> {
+        sample(120, |t| {
+            Point3::new(
+                t.sin() + 2.0 * (2.0 * t).sin(),
+                t.cos() - 2.0 * (2.0 * t).cos(),
+                -(3.0 * t).sin(),
+            )
+        })
+    }
+
+    #[test]
+    fn unknot() {
+        let circle = sample(50, |t| Point3::new(t.cos(), t.sin(), 0.1 * (2.0 * t).sin()));
+        let invariants = Invariants::new(&circle);
+        assert_eq!(invariants.crossings, 0);
+        assert_eq!(invariants.alexander, vec![1]);
+        assert_eq!(invariants.knot_type, Some(KnotType::Unknot));
+    }
+
+    #[test]
+    fn trefoil_invariants() {
+        let invariants = Invariants::new(&trefoil());
+        assert_eq!(invariants.crossings, 3);
+        assert_eq!(invariants.alexander, vec![1, -1, 1]);
+        assert_eq!(invariants.determinant, Some(3));
+        assert_eq!(invariants.degree, Some(2));
+        assert!(invariants.matches(KnotType::Trefoil));
+        assert!(!invariants.matches(KnotType::FigureEight));
+
+        // Reversing the orientation gives the same knot
+        let mut reversed = trefoil();
+        reversed.reverse();
+        assert_eq!(Invariants::new(&reversed).knot_type, Some(KnotType::Trefoil));
+    }
+
+    fn assert_not_computed(invariants: &Invariants) {
+        assert!(invariants.alexander.is_empty());
+        assert_eq!(invariants.determinant, None);
+        assert_eq!(invariants.degree, None);
+        assert_eq!(invariants.knot_type, None);
+        for &knot_type in KnotType::all() {
+            assert!(!invariants.matches(knot_type));
+        }
+    }
+
+    #[test]
+    fn degenerate_projection() {
+        // A planar curve seen edge on projects onto a line, overlapping itself
+        let planar = sample(50, |t| Point3::new(t.cos(), 0.0, t.sin()));
+        let diagram = Diagram::project(&planar, &Vector3::x_axis());
+        assert!(!diagram.generic);
+        assert_not_computed(&Invariants::from_diagram(&diagram));
+
+        // A vertex of the trefoil lies exactly on another segment when seen along z
+        let mut trefoil = trefoil();
+        trefoil[0] = Point3::new(0.0, 0.0, 1.0);
+        trefoil[1] = Point3::new(1.0, 0.0, 1.0);
+        trefoil[60] = Point3::new(0.5, -1.0, 0.0);
+        trefoil[61] = Point3::new(0.5, 0.0, 0.0);
+        trefoil[62] = Point3::new(0.5, 1.0, 0.0);
+        let diagram = Diagram::project(&trefoil, &Vector3::z_axis());
+        assert!(!diagram.generic);
+        assert_not_computed(&Invariants::from_diagram(&diagram));
+    }
+
+    #[test]
+    fn figure_eight_invariants() {
+        let figure_eight = sample(200, |t| {
+            let r = 2.0 + (2.0 * t).cos();
+            Point3::new(r * (3.0 * t).cos(), r * (3.0 * t).sin(), (4.0 * t).sin())
+        });
+        let invariants = Invariants::new(&figure_eight);
+        assert_eq!(invariants.alexander, vec![-1, 3, -1]);
+        assert_eq!(invariants.determinant, Some(5));
+        assert_eq!(invariants.knot_type, Some(KnotType::FigureEight));
+    }
+
+    #[test]
+    fn torus_knot_invariants() {
+        let torus = |p: f64, q: f64| {
+            sample(400, |t| {
+                let r = 2.0 + (q * t).cos();
+                Point3::new(r * (p * t).cos(), r * (p * t).sin(), -(q * t).sin())
+            })
+        };
+        assert_eq!(Invariants::new(&torus(2.0, 5.0)).knot_type, Some(KnotType::Cinquefoil));
+        // Different diagrams of the same knot
+        assert!(Invariants::new(&torus(3.0, 4.0)).matches(KnotType::Knot8_19));
+        assert!(Invariants::new(&torus(4.0, 3.0)).matches(KnotType::Knot8_19));
+    }
+
+    #[test]
+    fn chain_invariants() {
+        let chain = curve_9_40::chain(
+            CURVE_9_40_CHAIN_SIZE,
+            0.7,
+            COST_PARAMS,
+            RETURN_TO_INITIAL_WEIGHT,
+            RATE,
+        );
+        let symmetries = symmetries(3)
+            .map(|quat| quat.to_superset())
+            .collect::<Vec<_>>();
+        let points = closed_polyline(&chain.spec, JointsParity::Even, &chain.joints, &symmetries);
+        assert_eq!(points.len(), symmetries.len() * (chain.joints.len() + 1));
+        // Despite its name, the curve traces the (3, 4) torus knot
+        assert_eq!(Invariants::new(&points).knot_type, Some(KnotType::Knot8_19));
+    }
+
+    #[test]
+    fn parse_knot_types() {
+        for &knot_type in KnotType::all() {
+            assert_eq!(knot_type.name().parse::<KnotType>(), Ok(knot_type));
+            assert_eq!(KnotType::identify(&knot_type.alexander()), Some(knot_type));
+            let alexander = knot_type.alexander();
+            assert_eq!(alexander.iter().sum::<i64>(), 1);
+        }
+        assert_eq!("Trefoil".parse::<KnotType>(), Ok(KnotType::Trefoil));
+        assert!("5_3".parse::<KnotType>().is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+use nalgebra::Isometry3;
+
+use approx_locking_angle::{swing_twist_aligned, swing_twist_opposing};
+use cost::{costs_aligned, costs_opposing, Costs, NO_THRESHOLD};
+use defaults::COLLISION_STRATEGY;
+use filter::{capsule_collision_pairs_with_symmetry, protection_depth, CollisionPair};
+use report::{JointsParity, KnotGeometry};
+
+/// How far a geometry may deviate from a physically buildable knot and still be considered valid.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tolerances {
+    /// The maximum euclidean distance between the ends of two joints which are meant to meet.
+    pub dist: f64,
+
+    /// The maximum axis cost (one minus the dot product of the axes) at any junction.
+    pub axis: f64,
+
+    /// The maximum distance between a locking angle and the nearest valid angle, as a fraction of
+    /// the step between valid angles.
+    pub locking: f64,
+}
+
+/// How well the two joints meeting at a junction fit together.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JunctionResidual {
+    /// Junction `i` joins joint `i - 1` to joint `i`.  Junction `0` joins the first joint to its
+    /// mirror image, and the last junction joins the last joint to its copy in the adjacent
+    /// horseshoe.  Geometries with odd parity have no junction `0`, because their first joint
+    /// straddles the axis of the mirror symmetry and is its own mirror image.
+    pub index: usize,
+
+    /// Every term of the cost function, with no thresholds applied.
+    pub costs: Costs,
+
+    /// The angle between the axes of the two joints, in radians.
+    pub swing: f64,
+
+    /// The angle at which the joints lock together, in units of the step between valid angles.
+    pub locking_angle: f64,
+
+    /// The distance from `locking_angle` to the nearest integer.
+    pub locking_deviation: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub junctions: Vec<JunctionResidual>,
+
+    /// Every pair of colliding joints, across all symmetric copies of the chain.  Symmetry indices
+    /// refer to the order produced by `symmetry::symmetries`.
+    pub collisions: Vec<CollisionPair>,
+
+    /// Whether every junction is within tolerance and there are no collisions.
+    pub valid: bool,
+}
+
+/// Why a geometry could not be validated at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationError {
+    /// The geometry has no joints.
+    NoJoints,
+
+    /// The geometry has fewer than the four symmetries of dihedral-2 symmetry, which are needed to
+    /// describe how the chain meets its copies.
+    TooFewSymmetries(usize),
+
+    /// The joints fold back on themselves, so that neighboring legs always collide.
+    FoldedJoints,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ValidationError::NoJoints => write!(f, "Geometry must have at least one joint"),
+            &ValidationError::TooFewSymmetries(count) => write!(
+                f,
+                "Geometry must have at least dihedral-2 symmetry, but has only {} symmetries",
+                count
+            ),
+            &ValidationError::FoldedJoints => {
+                write!(f, "Joints which fold back on themselves always collide")
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl JunctionResidual {
+    fn new(
+        index: usize,
+        opposing: bool,
+        num_angles: u16,
+        trans_0: &Isometry3<f64>,
+        trans_1: &Isometry3<f64>,
+    ) -> Self {
+        let (costs, swing_twist) = if opposing {
+            (
+                costs_opposing(&NO_THRESHOLD, num_angles, trans_0, trans_1),
+                swing_twist_opposing(trans_0, trans_1),
+            )
+        } else {
+            (
+                costs_aligned(&NO_THRESHOLD, num_angles, trans_0, trans_1),
+                swing_twist_aligned(trans_0, trans_1),
+            )
+        };
+        let locking_angle = swing_twist.twist / (2.0 * PI) * (num_angles as f64);
+
+        JunctionResidual {
+            index,
+            costs,
+            swing: swing_twist.swing,
+            locking_angle,
+            locking_deviation: (locking_angle - locking_angle.round()).abs(),
+        }
+    }
+
+    pub fn within(&self, tolerances: &Tolerances) -> bool {
+        self.costs.dist.sqrt() <= tolerances.dist
+            && self.costs.axis <= tolerances.axis
+            && self.locking_deviation <= tolerances.locking
+    }
+}
+
+/// Compute the residual at every junction of a geometry, including the junctions with its
+/// symmetric copies.  The chain is assumed to be connected to its copies the same way as in
+/// `continuous_optimize`: the first joint's incoming leg meets the incoming leg of its copy under
+/// the second symmetry, unless the parity is odd and the first joint is its own mirror image, and
+/// the last joint's outgoing leg meets the outgoing leg of its copy under the fourth.
+pub fn junction_residuals(
+    geometry: &KnotGeometry,
+) -> Result<Vec<JunctionResidual>, ValidationError> {
+    if geometry.transforms.is_empty() {
+        return Err(ValidationError::NoJoints);
+    }
+    if geometry.symmetries.len() < 4 {
+        return Err(ValidationError::TooFewSymmetries(geometry.symmetries.len()));
+    }
+
+    let spec = geometry.joint_spec;
+    let num_angles = geometry.num_angles;
+    let joints = geometry.joints();
+    let symms = geometry.symmetry_isometries();
+
+    let first = joints[0];
+    let last = joints[joints.len() - 1];
+
+    let mut residuals = Vec::with_capacity(joints.len() + 1);
+    if let JointsParity::Even = geometry.parity {
+        residuals.push(JunctionResidual::new(
+            0,
+            true,
+            num_angles,
+            &(symms[1] * first * spec.origin_to_in()),
+            &(first * spec.origin_to_in()),
+        ));
+    }
+    for (i, pair) in joints.windows(2).enumerate() {
+        residuals.push(JunctionResidual::new(
+            i + 1,
+            false,
+            num_angles,
+            &(pair[0] * spec.origin_to_out()),
+            &(pair[1] * spec.origin_to_in()),
+        ));
+    }
+    residuals.push(JunctionResidual::new(
+        joints.len(),
+        true,
+        num_angles,
+        &(last * spec.origin_to_out()),
+        &(symms[3] * last * spec.origin_to_out()),
+    ));
+    Ok(residuals)
+}
+
+/// Check whether a geometry describes a physically buildable knot: every pair of joints meeting at
+/// a junction must fit together flush at a valid locking angle, and no two legs of any symmetric
+/// copies of the chain may intersect.
+pub fn validate(
+    geometry: &KnotGeometry,
+    tolerances: &Tolerances,
+) -> Result<ValidationReport, ValidationError> {
+    let junctions = junction_residuals(geometry)?;
+
+    let spec = geometry.joint_spec;
+    let depth = protection_depth(&spec).ok_or(ValidationError::FoldedJoints)?;
+    let collisions = capsule_collision_pairs_with_symmetry(
+        geometry.symmetry_count(),
+        geometry.symmetry_skip(),
+        depth,
+        spec,
+        geometry.joints().into_iter(),
+        COLLISION_STRATEGY.backend,
+    );
+
+    let valid =
+        junctions.iter().all(|junction| junction.within(tolerances)) && collisions.is_empty();
+
+    Ok(ValidationReport {
+        junctions,
+        collisions,
+        valid,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use validate::*;
+
+    use alga::general::SubsetOf;
+    use nalgebra::Translation3;
+    use std::f64::INFINITY;
+
+    use defaults;
+    use defaults::{COST_PARAMS, NUM_ANGLES};
+    use joint::{at_angles, discrete_angles};
+    use report::{fixtures, Transform};
+    use symmetry::symmetries_with_skip;
+
+    fn geometry(start: Isometry3<f64>) -> KnotGeometry {
+        let spec = defaults::joint_spec();
+        KnotGeometry {
+            joint_spec: spec,
+            num_angles: NUM_ANGLES,
+            cost_params: COST_PARAMS,
+            parity: JointsParity::Even,
+            symmetries: symmetries_with_skip(3, 2)
+                .map(|quat| Transform::from_isometry(quat.to_superset()))
+                .collect(),
+            transforms: at_angles(
+                discrete_angles(spec, NUM_ANGLES, vec![3, 0, 5, 1].into_iter()),
+                start,
+            ).map(Transform::from_isometry)
+            .collect(),
+            constraints: Vec::new(),
+        }
+    }
+
+    const LOOSE: Tolerances = Tolerances {
+        dist: INFINITY,
+        axis: INFINITY,
+        locking: INFINITY,
+    };
+
+    #[test]
+    fn symmetry_skip() {
+        assert_eq!(geometry(Isometry3::identity()).symmetry_count(), 3);
+        assert_eq!(geometry(Isometry3::identity()).symmetry_skip(), 2);
+    }
+
+    #[test]
+    fn internal_junctions_are_exact() {
+        let geometry = geometry(Translation3::new(20.0, 10.0, 10.0).to_superset());
+        let junctions = junction_residuals(&geometry).unwrap();
+        assert_eq!(junctions.len(), 5);
+        for junction in &junctions[1..4] {
+            assert_relative_eq!(junction.costs.dist, 0.0, epsilon = 1e-12);
+            assert_relative_eq!(junction.costs.axis, 0.0, epsilon = 1e-12);
+            assert_relative_eq!(junction.locking_deviation, 0.0, epsilon = 1e-6);
+            assert!(junction.within(&Tolerances {
+                dist: 1e-6,
+                axis: 1e-6,
+                locking: 1e-6,
+            }));
+        }
+
+        // The chain is nowhere near its symmetric copies
+        assert!(junctions[0].costs.dist.sqrt() > 1.0);
+        assert!(junctions[4].costs.dist.sqrt() > 1.0);
+    }
+
+    #[test]
+    fn verdict() {
+        // Far from the axis of symmetry, so that no copies collide
+        let separated = geometry(Translation3::new(20.0, 10.0, 10.0).to_superset());
+        let report = validate(&separated, &LOOSE).unwrap();
+        assert!(report.collisions.is_empty());
+        assert!(report.valid);
+
+        // The ends of the chain are too far from its copies
+        assert!(!validate(&separated, &defaults::validation::TOLERANCES).unwrap().valid);
+
+        // Every "up" copy starts at the same point
+        let overlapping = geometry(Isometry3::identity());
+        let report = validate(&overlapping, &LOOSE).unwrap();
+        assert!(!report.collisions.is_empty());
+        assert!(!report.valid);
+    }
+
+    #[test]
+    fn odd_parity() {
+        // The first joint straddles the mirror axis, so it has no junction with its mirror image
+        let geometry = fixtures::geometry(JointsParity::Odd, vec![4, 12, 7, 9]);
+        let junctions = junction_residuals(&geometry).unwrap();
+        assert_eq!(junctions.len(), geometry.transforms.len());
+        assert_eq!(junctions[0].index, 1);
+
+        // Only the closure is left to optimization
+        let (closure, internal) = junctions.split_last().unwrap();
+        assert_eq!(closure.index, geometry.transforms.len());
+        for junction in internal {
+            assert!(junction.within(&defaults::validation::TOLERANCES));
+        }
+    }
+
+    #[test]
+    fn invalid_geometries() {
+        let mut geometry = geometry(Isometry3::identity());
+        geometry.symmetries.truncate(2);
+        assert_eq!(
+            junction_residuals(&geometry).unwrap_err(),
+            ValidationError::TooFewSymmetries(2)
+        );
+
+        geometry.transforms.clear();
+        assert_eq!(
+            validate(&geometry, &LOOSE).unwrap_err(),
+            ValidationError::NoJoints
+        );
+    }
+}